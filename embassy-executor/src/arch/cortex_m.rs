@@ -1,8 +1,11 @@
 use core::arch::asm;
+use core::cell::UnsafeCell;
+use core::future::Future;
 use core::marker::PhantomData;
 use core::ptr;
 
-use super::{raw, Spawner};
+use super::idle::idle;
+use super::{raw, DefaultIdle, IdleHook, Spawner};
 
 /// Thread mode executor, using WFE/SEV.
 ///
@@ -14,16 +17,30 @@ use super::{raw, Spawner};
 /// This executor allows for ultra low power consumption for chips where `WFE`
 /// triggers low-power sleep without extra steps. If your chip requires extra steps,
 /// you may use [`raw::Executor`] directly to program custom behavior.
-pub struct Executor {
+///
+/// When there's no work to do, the executor waits with an [`IdleHook`], [`DefaultIdle`] unless
+/// created with [`with_idle_hook()`](Executor::with_idle_hook).
+pub struct Executor<H: IdleHook = DefaultIdle> {
     inner: raw::Executor,
+    run_until: raw::RunUntil,
+    idle_hook: UnsafeCell<H>,
     not_send: PhantomData<*mut ()>,
 }
 
 impl Executor {
     /// Create a new Executor.
     pub fn new() -> Self {
+        Self::with_idle_hook(DefaultIdle)
+    }
+}
+
+impl<H: IdleHook> Executor<H> {
+    /// Create a new Executor, waiting for work with `idle_hook`.
+    pub fn with_idle_hook(idle_hook: H) -> Self {
         Self {
             inner: raw::Executor::new(|_| unsafe { asm!("sev") }, ptr::null_mut()),
+            run_until: raw::RunUntil::new(),
+            idle_hook: UnsafeCell::new(idle_hook),
             not_send: PhantomData,
         }
     }
@@ -52,8 +69,36 @@ impl Executor {
         loop {
             unsafe {
                 self.inner.poll();
-                asm!("wfe");
+                idle(&self.inner, self.idle_hook.get_mut(), wait);
             };
         }
     }
+
+    /// Run the executor until `fut` completes, and return its output.
+    ///
+    /// `fut` is polled from the executor like a task, while the executor also runs the other
+    /// tasks. It may spawn tasks with [`Spawner::for_current_executor()`]. Once `fut` completes,
+    /// this returns, leaving the other tasks suspended: they run again the next time the executor
+    /// is run.
+    ///
+    /// This allows running the executor for a bounded phase, and continuing with synchronous
+    /// code afterwards. Since this takes `&'static self`, it may be called several times: store
+    /// the executor in a `&'static Executor` to do so. This can't be combined with
+    /// [`run()`](Executor::run), which needs exclusive access to the executor.
+    ///
+    /// # Panics
+    ///
+    /// Panics if called from a task running in this executor.
+    pub fn run_until<F: Future>(&'static self, fut: F) -> F::Output {
+        // Safety: `run_until` can't be called reentrantly, and `run` needs `&mut self`,
+        // so nothing else accesses the hook.
+        self.run_until.run(&self.inner, fut, || unsafe {
+            idle(&self.inner, &mut *self.idle_hook.get(), wait)
+        })
+    }
+}
+
+/// Sleep until the executor has work to do.
+fn wait() {
+    unsafe { asm!("wfe") }
 }
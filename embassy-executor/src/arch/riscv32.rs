@@ -1,22 +1,37 @@
+use core::cell::UnsafeCell;
+use core::future::Future;
 use core::marker::PhantomData;
 use core::ptr;
 use core::sync::atomic::{AtomicBool, Ordering};
 
-use super::{raw, Spawner};
+use super::idle::idle;
+use super::{raw, DefaultIdle, IdleHook, Spawner};
 
 /// global atomic used to keep track of whether there is work to do since sev() is not available on RISCV
 ///
 static SIGNAL_WORK_THREAD_MODE: AtomicBool = AtomicBool::new(false);
 
 /// RISCV32 Executor
-pub struct Executor {
+///
+/// When there's no work to do, the executor waits with an [`IdleHook`], [`DefaultIdle`] unless
+/// created with [`with_idle_hook()`](Executor::with_idle_hook).
+pub struct Executor<H: IdleHook = DefaultIdle> {
     inner: raw::Executor,
+    run_until: raw::RunUntil,
+    idle_hook: UnsafeCell<H>,
     not_send: PhantomData<*mut ()>,
 }
 
 impl Executor {
     /// Create a new Executor.
     pub fn new() -> Self {
+        Self::with_idle_hook(DefaultIdle)
+    }
+}
+
+impl<H: IdleHook> Executor<H> {
+    /// Create a new Executor, waiting for work with `idle_hook`.
+    pub fn with_idle_hook(idle_hook: H) -> Self {
         Self {
             // use Signal_Work_Thread_Mode as substitute for local interrupt register
             inner: raw::Executor::new(
@@ -25,6 +40,8 @@ impl Executor {
                 },
                 ptr::null_mut(),
             ),
+            run_until: raw::RunUntil::new(),
+            idle_hook: UnsafeCell::new(idle_hook),
             not_send: PhantomData,
         }
     }
@@ -53,21 +70,49 @@ impl Executor {
         loop {
             unsafe {
                 self.inner.poll();
-                // we do not care about race conditions between the load and store operations, interrupts
-                //will only set this value to true.
-                critical_section::with(|_| {
-                    // if there is work to do, loop back to polling
-                    // TODO can we relax this?
-                    if SIGNAL_WORK_THREAD_MODE.load(Ordering::SeqCst) {
-                        SIGNAL_WORK_THREAD_MODE.store(false, Ordering::SeqCst);
-                    }
-                    // if not, wait for interrupt
-                    else {
-                        core::arch::asm!("wfi");
-                    }
-                });
-                // if an interrupt occurred while waiting, it will be serviced here
-            }
+                idle(&self.inner, self.idle_hook.get_mut(), wait);
+            };
         }
     }
+
+    /// Run the executor until `fut` completes, and return its output.
+    ///
+    /// `fut` is polled from the executor like a task, while the executor also runs the other
+    /// tasks. It may spawn tasks with [`Spawner::for_current_executor()`]. Once `fut` completes,
+    /// this returns, leaving the other tasks suspended: they run again the next time the executor
+    /// is run.
+    ///
+    /// This allows running the executor for a bounded phase, and continuing with synchronous
+    /// code afterwards. Since this takes `&'static self`, it may be called several times: store
+    /// the executor in a `&'static Executor` to do so. This can't be combined with
+    /// [`run()`](Executor::run), which needs exclusive access to the executor.
+    ///
+    /// # Panics
+    ///
+    /// Panics if called from a task running in this executor.
+    pub fn run_until<F: Future>(&'static self, fut: F) -> F::Output {
+        // Safety: `run_until` can't be called reentrantly, and `run` needs `&mut self`,
+        // so nothing else accesses the hook.
+        self.run_until.run(&self.inner, fut, || unsafe {
+            idle(&self.inner, &mut *self.idle_hook.get(), wait)
+        })
+    }
+}
+
+/// Sleep until the executor has work to do.
+fn wait() {
+    // we do not care about race conditions between the load and store operations, interrupts
+    //will only set this value to true.
+    critical_section::with(|_| {
+        // if there is work to do, loop back to polling
+        // TODO can we relax this?
+        if SIGNAL_WORK_THREAD_MODE.load(Ordering::SeqCst) {
+            SIGNAL_WORK_THREAD_MODE.store(false, Ordering::SeqCst);
+        }
+        // if not, wait for interrupt
+        else {
+            unsafe { core::arch::asm!("wfi") };
+        }
+    });
+    // if an interrupt occurred while waiting, it will be serviced here
 }
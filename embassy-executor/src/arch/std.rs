@@ -1,11 +1,16 @@
+use std::collections::VecDeque;
+use std::future::Future;
 use std::marker::PhantomData;
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::{Condvar, Mutex};
+use std::thread;
 
-use super::{raw, Spawner};
+use super::{raw, SendSpawner, Spawner};
 
 /// Single-threaded std-based executor.
 pub struct Executor {
     inner: raw::Executor,
+    run_until: raw::RunUntil,
     not_send: PhantomData<*mut ()>,
     signaler: &'static Signaler,
 }
@@ -22,6 +27,7 @@ impl Executor {
                 },
                 signaler as *const _ as _,
             ),
+            run_until: raw::RunUntil::new(),
             not_send: PhantomData,
             signaler,
         }
@@ -53,6 +59,25 @@ impl Executor {
             self.signaler.wait()
         }
     }
+
+    /// Run the executor until `fut` completes, and return its output.
+    ///
+    /// `fut` is polled from the executor like a task, while the executor also runs the other
+    /// tasks. It may spawn tasks with [`Spawner::for_current_executor()`]. Once `fut` completes,
+    /// this returns, leaving the other tasks suspended: they run again the next time the executor
+    /// is run.
+    ///
+    /// This allows running the executor for a bounded phase, and continuing with synchronous
+    /// code afterwards. Since this takes `&'static self`, it may be called several times: store
+    /// the executor in a `&'static Executor` to do so. This can't be combined with
+    /// [`run()`](Executor::run), which needs exclusive access to the executor.
+    ///
+    /// # Panics
+    ///
+    /// Panics if called from a task running in this executor.
+    pub fn run_until<F: Future>(&'static self, fut: F) -> F::Output {
+        self.run_until.run(&self.inner, fut, || self.signaler.wait())
+    }
 }
 
 struct Signaler {
@@ -82,3 +107,302 @@ impl Signaler {
         self.condvar.notify_one();
     }
 }
+
+/// Multi-threaded std-based executor, with work stealing.
+///
+/// Tasks are run on a fixed number of worker threads. Each worker takes the tasks that are
+/// ready to run from the executor's run queues in batches, into a queue of its own. A worker that
+/// runs out of tasks steals half of the queue of another worker.
+///
+/// A task may run on a different thread every time it's polled, so only `Send` tasks can be
+/// spawned, with a [`SendSpawner`]. Task priorities are best effort: a worker takes higher
+/// priority tasks first, but may be busy with lower priority ones while others wait.
+pub struct ThreadPoolExecutor {
+    inner: raw::Executor,
+    shared: &'static Shared,
+}
+
+// The raw executor is safe to use from several threads once it only holds Send tasks.
+unsafe impl Send for ThreadPoolExecutor {}
+unsafe impl Sync for ThreadPoolExecutor {}
+
+struct Shared {
+    signaler: PoolSignaler,
+    workers: Box<[Mutex<VecDeque<Task>>]>,
+    /// Set to make the workers return. Only tests do so, `run()` never returns.
+    stopped: AtomicBool,
+    /// When the timer alarm is set to fire, in ticks.
+    #[cfg(feature = "integrated-timers")]
+    alarm_at: Mutex<u64>,
+}
+
+/// A spawned task in a worker queue.
+struct Task(raw::TaskRef);
+
+// Only Send tasks are spawned in a ThreadPoolExecutor.
+unsafe impl Send for Task {}
+
+impl ThreadPoolExecutor {
+    /// Create a new ThreadPoolExecutor, running tasks on `threads` worker threads.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `threads` is 0.
+    pub fn new(threads: usize) -> Self {
+        assert!(threads > 0, "a thread pool needs at least one thread");
+
+        let shared = &*Box::leak(Box::new(Shared {
+            signaler: PoolSignaler::new(),
+            workers: (0..threads).map(|_| Mutex::new(VecDeque::new())).collect(),
+            stopped: AtomicBool::new(false),
+            #[cfg(feature = "integrated-timers")]
+            alarm_at: Mutex::new(u64::MAX),
+        }));
+
+        let mut inner = raw::Executor::new(
+            |p| unsafe {
+                let s = &*(p as *const () as *const PoolSignaler);
+                s.signal()
+            },
+            &shared.signaler as *const _ as _,
+        );
+        inner.send_only = true;
+
+        Self { inner, shared }
+    }
+
+    /// Run the executor.
+    ///
+    /// The `init` closure is called with a [`SendSpawner`] that spawns tasks on
+    /// this executor. Use it to spawn the initial task(s). After `init` returns,
+    /// the worker threads start running the tasks. The current thread is used as one
+    /// of the workers.
+    ///
+    /// Tasks can get a [`SendSpawner`] to spawn more tasks with
+    /// [`SendSpawner::for_current_executor()`]. [`Spawner`] can't be used with this executor.
+    ///
+    /// This function requires `&'static mut self`, see [`Executor::run()`] for how to get one.
+    ///
+    /// This function never returns.
+    pub fn run(&'static mut self, init: impl FnOnce(SendSpawner)) -> ! {
+        let this: &'static Self = self;
+        init(this.inner.spawner().make_send());
+
+        for worker in 1..this.shared.workers.len() {
+            this.spawn_worker(worker);
+        }
+        this.run_worker(0);
+        unreachable!("the thread pool was stopped")
+    }
+
+    /// Run all the workers on new threads, until [`stop()`](Self::stop) is called.
+    #[cfg(all(test, feature = "test-executor"))]
+    fn start(&'static self, init: impl FnOnce(SendSpawner)) -> Vec<thread::JoinHandle<()>> {
+        init(self.inner.spawner().make_send());
+        (0..self.shared.workers.len())
+            .map(|worker| self.spawn_worker(worker))
+            .collect()
+    }
+
+    /// Make the workers return once done with the task they're running.
+    #[cfg(all(test, feature = "test-executor"))]
+    fn stop(&self) {
+        self.shared.stopped.store(true, Ordering::SeqCst);
+        self.shared.signaler.signal();
+    }
+
+    fn spawn_worker(&'static self, worker: usize) -> thread::JoinHandle<()> {
+        thread::Builder::new()
+            .name(format!("embassy-worker-{}", worker))
+            .spawn(move || self.run_worker(worker))
+            .unwrap()
+    }
+
+    fn run_worker(&'static self, worker: usize) {
+        while !self.shared.stopped.load(Ordering::SeqCst) {
+            // Read the generation before looking for work, so that a signal arriving
+            // in the meantime is not lost.
+            let generation = self.shared.signaler.generation();
+
+            #[cfg(feature = "integrated-timers")]
+            if embassy_time::Instant::now().as_ticks() >= *self.shared.alarm_at.lock().unwrap() {
+                self.process_timers();
+            }
+
+            match self.next_task(worker) {
+                Some(task) => {
+                    let _expires_at = unsafe { self.inner.poll_task_shared(task.0) };
+
+                    // The alarm has to be moved earlier if the task now has the earliest timer.
+                    #[cfg(feature = "integrated-timers")]
+                    if let Some(expires_at) = _expires_at {
+                        if expires_at < *self.shared.alarm_at.lock().unwrap() {
+                            self.process_timers();
+                        }
+                    }
+                }
+                None => self.shared.signaler.wait(generation),
+            }
+        }
+    }
+
+    #[cfg(feature = "integrated-timers")]
+    fn process_timers(&'static self) {
+        let mut alarm_at = self.shared.alarm_at.lock().unwrap();
+        *alarm_at = self.inner.process_timers();
+    }
+
+    /// Find the next task for `worker` to poll: from its own queue, from the executor's
+    /// run queues, or from another worker.
+    fn next_task(&'static self, worker: usize) -> Option<Task> {
+        let workers = &self.shared.workers;
+
+        let mut queue = workers[worker].lock().unwrap();
+        if let Some(task) = queue.pop_front() {
+            return Some(task);
+        }
+
+        self.inner.dequeue_batch(|task| queue.push_back(Task(task)));
+        if queue.len() > 1 {
+            // Let idle workers steal some of the batch.
+            self.shared.signaler.signal();
+        }
+        if let Some(task) = queue.pop_front() {
+            return Some(task);
+        }
+        drop(queue);
+
+        // Only one queue is locked at a time, so workers stealing from each other can't deadlock.
+        for victim in (1..workers.len()).map(|i| (worker + i) % workers.len()) {
+            let stolen = {
+                let mut victim = workers[victim].lock().unwrap();
+                let len = victim.len();
+                victim.split_off(len / 2)
+            };
+            if !stolen.is_empty() {
+                let mut queue = workers[worker].lock().unwrap();
+                queue.extend(stolen);
+                return queue.pop_front();
+            }
+        }
+
+        None
+    }
+}
+
+/// Signaler waking up all idle workers of a [`ThreadPoolExecutor`].
+struct PoolSignaler {
+    generation: Mutex<u32>,
+    condvar: Condvar,
+}
+
+impl PoolSignaler {
+    fn new() -> Self {
+        Self {
+            generation: Mutex::new(0),
+            condvar: Condvar::new(),
+        }
+    }
+
+    fn generation(&self) -> u32 {
+        *self.generation.lock().unwrap()
+    }
+
+    /// Wait until signaled, if not signaled since `generation` was read.
+    fn wait(&self, generation: u32) {
+        let mut current = self.generation.lock().unwrap();
+        while *current == generation {
+            current = self.condvar.wait(current).unwrap();
+        }
+    }
+
+    fn signal(&self) {
+        let mut generation = self.generation.lock().unwrap();
+        *generation = generation.wrapping_add(1);
+        self.condvar.notify_all();
+    }
+}
+
+#[cfg(all(test, feature = "test-executor"))]
+mod tests {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+    use std::task::{Poll, Waker};
+    use std::time::{Duration as StdDuration, Instant as StdInstant};
+
+    use embassy_time::{Duration, Instant, MockDriver, Timer};
+    use futures_util::future::poll_fn;
+
+    use super::*;
+    use crate::raw::TaskPool;
+    use crate::test_util::{leak, take_mock_driver};
+
+    const TASKS: usize = 4;
+    const ROUNDS: usize = 5;
+
+    /// Token passed around a ring of tasks, each waking the next one.
+    struct Ring {
+        holder: Mutex<usize>,
+        wakers: Mutex<[Option<Waker>; TASKS]>,
+        hops: AtomicUsize,
+    }
+
+    async fn ring_task(ring: Arc<Ring>, index: usize) {
+        for _ in 0..ROUNDS {
+            poll_fn(|cx| {
+                if *ring.holder.lock().unwrap() == index {
+                    Poll::Ready(())
+                } else {
+                    ring.wakers.lock().unwrap()[index] = Some(cx.waker().clone());
+                    Poll::Pending
+                }
+            })
+            .await;
+
+            Timer::after(Duration::from_millis(1)).await;
+            ring.hops.fetch_add(1, Ordering::SeqCst);
+
+            let next = (index + 1) % TASKS;
+            *ring.holder.lock().unwrap() = next;
+            if let Some(waker) = ring.wakers.lock().unwrap()[next].take() {
+                waker.wake();
+            }
+        }
+    }
+
+    #[test]
+    fn thread_pool_tasks_wake_each_other() {
+        let _driver = take_mock_driver();
+
+        let ring = Arc::new(Ring {
+            holder: Mutex::new(0),
+            wakers: Mutex::new(Default::default()),
+            hops: AtomicUsize::new(0),
+        });
+
+        let executor = leak(ThreadPoolExecutor::new(3));
+        let pool: &'static TaskPool<_, TASKS> = leak(TaskPool::new());
+        let workers = executor.start(|spawner| {
+            for index in 0..TASKS {
+                let ring = ring.clone();
+                spawner.spawn(pool.spawn(move || ring_task(ring, index))).unwrap();
+            }
+        });
+
+        // Every hop waits for a timer, so time has to move for the tasks to make progress.
+        let deadline = StdInstant::now() + StdDuration::from_secs(10);
+        while ring.hops.load(Ordering::SeqCst) < TASKS * ROUNDS {
+            assert!(StdInstant::now() < deadline, "tasks stopped making progress");
+            MockDriver::get().advance(Duration::from_millis(1));
+            thread::sleep(StdDuration::from_micros(100));
+        }
+
+        assert!(Instant::now() >= Instant::from_millis((TASKS * ROUNDS) as u64));
+
+        // Don't leave workers running once the next test takes the MockDriver.
+        executor.stop();
+        for worker in workers {
+            worker.join().unwrap();
+        }
+    }
+}
@@ -0,0 +1,85 @@
+use super::raw;
+
+/// Decides how a thread-mode executor waits when it has no work to do.
+///
+/// By default, executors sleep with their architecture's wait instruction, such as `WFE` on
+/// Cortex-M, see [`DefaultIdle`]. An `IdleHook`, set with the executor's `with_idle_hook`
+/// constructor, can instead pick a deeper sleep mode when the next timer expiration is far
+/// enough away, or keep the CPU running.
+///
+/// ```ignore
+/// struct StopMode;
+///
+/// impl IdleHook for StopMode {
+///     fn idle(&mut self, next_expiration: Option<u64>, wait: fn()) -> u64 {
+///         match next_expiration {
+///             Some(at) if at < embassy_time::driver::now() + MIN_STOP_TICKS => {
+///                 wait();
+///                 0
+///             }
+///             _ => {
+///                 let rtc_start = rtc_ticks();
+///                 enter_stop_mode(wait);
+///                 rtc_ticks() - rtc_start
+///             }
+///         }
+///     }
+/// }
+///
+/// let executor = Executor::with_idle_hook(StopMode);
+/// ```
+pub trait IdleHook {
+    /// Wait until the executor may have work to do.
+    ///
+    /// `next_expiration` is the time of the next timer expiration, in ticks of the time driver,
+    /// or `None` if no timer is pending. It's always `None` without the `integrated-timers` feature.
+    ///
+    /// `wait` is the executor's default way of waiting: it sleeps until an interrupt occurs, or
+    /// returns immediately if the executor has been signaled since it last polled. To sleep,
+    /// call it, possibly after preparing a low-power mode and restoring the run mode after. To
+    /// keep the CPU busy polling, return without calling it.
+    ///
+    /// Returns the number of ticks slept while the time driver's timer was stopped, if the
+    /// sleep mode used stops it, so that the time driver can catch up. See
+    /// `embassy_time::driver::Driver::compensate_sleep()`. Returns 0 otherwise. This is ignored without
+    /// the `integrated-timers` feature.
+    fn idle(&mut self, next_expiration: Option<u64>, wait: fn()) -> u64;
+}
+
+/// The default [`IdleHook`]: sleep with the architecture's wait instruction.
+pub struct DefaultIdle;
+
+impl IdleHook for DefaultIdle {
+    fn idle(&mut self, _next_expiration: Option<u64>, wait: fn()) -> u64 {
+        wait();
+        0
+    }
+}
+
+/// Wait with `hook` until `executor` may have work to do.
+///
+/// # Safety
+///
+/// Must be called from the thread that polls the executor, between calls to `poll`.
+#[allow(dead_code)]
+pub(crate) unsafe fn idle(executor: &raw::Executor, hook: &mut impl IdleHook, wait: fn()) {
+    #[cfg(feature = "integrated-timers")]
+    let next_expiration = match executor.next_expiration() {
+        u64::MAX => None,
+        at => Some(at),
+    };
+    #[cfg(not(feature = "integrated-timers"))]
+    let next_expiration = {
+        let _ = executor;
+        None
+    };
+
+    let slept = hook.idle(next_expiration, wait);
+
+    #[cfg(feature = "integrated-timers")]
+    if slept != 0 {
+        embassy_time::driver::compensate_sleep(slept);
+    }
+    #[cfg(not(feature = "integrated-timers"))]
+    let _ = slept;
+}
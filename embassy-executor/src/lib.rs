@@ -69,13 +69,75 @@ pub mod export {
     macro_rules! rtos_trace_interrupt {
         ($($tt:tt)*) => {};
     }
+
+    /// Records the size of a task, for `task_sizes()`, when `embassy-executor` is compiled
+    /// with the `task-sizes` feature.
+    ///
+    /// The feature fails to compile on targets that are not ELF, so the link section is valid.
+    #[doc(hidden)]
+    #[macro_export]
+    #[cfg(feature = "task-sizes")]
+    macro_rules! task_size_record {
+        ($name:expr, $fut:ty, $pool_size:expr) => {
+            #[used]
+            #[link_section = "embassy_task_sizes"]
+            static TASK_SIZE: $crate::TaskSize = $crate::TaskSize {
+                name: $name,
+                future_size: $crate::raw::TaskPool::<$fut, { $pool_size }>::FUTURE_SIZE,
+                pool_size: $pool_size,
+                total_size: ::core::mem::size_of::<$crate::raw::TaskPool<$fut, { $pool_size }>>(),
+            };
+        };
+    }
+
+    /// Does not record the size of a task when `embassy-executor` is compiled without
+    /// the `task-sizes` feature.
+    #[doc(hidden)]
+    #[macro_export]
+    #[cfg(not(feature = "task-sizes"))]
+    macro_rules! task_size_record {
+        ($($tt:tt)*) => {};
+    }
 }
 
 pub mod raw;
 
+mod idle;
+pub use idle::{DefaultIdle, IdleHook};
+
 mod spawner;
 pub use spawner::*;
 
+#[cfg(feature = "test-executor")]
+mod test_executor;
+#[cfg(feature = "test-executor")]
+pub use test_executor::*;
+
+#[cfg(all(test, feature = "test-executor"))]
+mod test_util;
+
+cfg_if::cfg_if! {
+    if #[cfg(all(feature = "task-sizes", any(
+        target_os = "linux",
+        target_os = "android",
+        target_os = "freebsd",
+        target_os = "netbsd",
+        target_os = "openbsd",
+        target_os = "dragonfly",
+        target_os = "illumos",
+        target_os = "solaris",
+    )))] {
+        mod task_sizes;
+        pub use task_sizes::*;
+    }
+    else if #[cfg(feature = "task-sizes")] {
+        // Task sizes are collected in a linker section, found with the `__start_` and `__stop_`
+        // symbols that ELF linkers define. Mach-O (macOS) needs a "segment,section" name
+        // instead, and neither Mach-O nor COFF (Windows) linkers define these symbols.
+        compile_error!("the `task-sizes` feature is only supported on ELF targets, such as Linux");
+    }
+}
+
 /// Do not use. Used for macros and HALs only. Not covered by semver guarantees.
 #[doc(hidden)]
 pub mod _export {
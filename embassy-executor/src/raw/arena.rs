@@ -0,0 +1,295 @@
+use core::alloc::Layout;
+use core::cell::{Cell, UnsafeCell};
+use core::future::Future;
+use core::mem::{self, MaybeUninit};
+use core::pin::Pin;
+use core::ptr;
+use core::task::{Context, Poll};
+
+use atomic_polyfill::Ordering;
+use critical_section::CriticalSection;
+
+use super::{waker, TaskHeader, TaskRef, STATE_ABORTED};
+use crate::SpawnToken;
+
+/// A task slot of a [`TaskArena`].
+///
+/// The header lives as long as the arena, like a [`TaskStorage`](super::TaskStorage): wakers and
+/// `JoinHandle`s may still point to it after the task has finished. Only the future's memory
+/// in the arena is reclaimed.
+// repr(C) is needed to guarantee that the header is located at offset 0.
+#[repr(C)]
+struct ArenaTask {
+    raw: TaskHeader,
+    future: Cell<*mut ()>, // Valid if STATE_SPAWNED
+    // Range of arena memory used by the future. Only accessed in a critical section.
+    offset: Cell<usize>,
+    size: Cell<usize>,
+}
+
+impl ArenaTask {
+    #[allow(clippy::declare_interior_mutable_const)]
+    const NEW: Self = Self {
+        raw: TaskHeader::new(),
+        future: Cell::new(ptr::null_mut()),
+        offset: Cell::new(0),
+        size: Cell::new(0),
+    };
+
+    unsafe fn poll<F: Future + 'static>(p: TaskRef) {
+        let this = &*(p.as_ptr() as *const ArenaTask);
+        let future = this.future.get() as *mut F;
+
+        if this.raw.state.load(Ordering::Acquire) & STATE_ABORTED != 0 {
+            this.release(future);
+            return;
+        }
+
+        let waker = waker::from_task(p);
+        let mut cx = Context::from_waker(&waker);
+        match Pin::new_unchecked(&mut *future).poll(&mut cx) {
+            Poll::Ready(_) => this.release(future),
+            Poll::Pending => {}
+        }
+
+        // the compiler is emitting a virtual call for waker drop, but we know
+        // it's a noop for our waker.
+        mem::forget(waker);
+    }
+
+    /// Drop the future, give its memory back to the arena, and finish the task.
+    unsafe fn release<F>(&self, future: *mut F) {
+        ptr::drop_in_place(future);
+        critical_section::with(|_| self.size.set(0));
+        self.raw.finish();
+    }
+}
+
+/// Raw storage for up to `TASKS` tasks whose futures may have different types, sharing
+/// `SIZE` bytes of memory.
+///
+/// Where a [`TaskPool`](super::TaskPool) reserves memory for its largest number of tasks of a
+/// single type, a `TaskArena` fits the futures of whatever tasks are running in a fixed
+/// buffer. This suits many short-lived tasks of different types, such as connection handlers.
+///
+/// Futures are placed at the first free spot in the buffer large enough to hold them. When
+/// a task finishes, or is aborted, its memory becomes free again. Spawning fails with
+/// [`SpawnError::Busy`](crate::SpawnError::Busy) if all `TASKS` slots are in use, or if no
+/// free spot is large enough. Since free memory may be fragmented, leave some headroom when
+/// choosing `SIZE`.
+///
+/// No allocator is needed: the arena is typically placed in a `static`.
+///
+/// ```ignore
+/// static ARENA: TaskArena<4096, 8> = TaskArena::new();
+///
+/// let token = ARENA.spawn(|| handle_connection(socket));
+/// spawner.spawn(token)?;
+/// ```
+pub struct TaskArena<const SIZE: usize, const TASKS: usize> {
+    tasks: [ArenaTask; TASKS],
+    memory: UnsafeCell<[MaybeUninit<u8>; SIZE]>,
+}
+
+impl<const SIZE: usize, const TASKS: usize> TaskArena<SIZE, TASKS> {
+    /// Create a new, empty TaskArena.
+    pub const fn new() -> Self {
+        Self {
+            tasks: [ArenaTask::NEW; TASKS],
+            memory: UnsafeCell::new([MaybeUninit::uninit(); SIZE]),
+        }
+    }
+
+    /// Try to spawn a task in the arena.
+    ///
+    /// See [`TaskStorage::spawn()`](super::TaskStorage::spawn) for details.
+    ///
+    /// If there's no free task slot, or no free memory to fit the future, a "poisoned"
+    /// SpawnToken is returned, which will cause [`Spawner::spawn()`](crate::Spawner::spawn)
+    /// to return the error.
+    pub fn spawn<F: Future + 'static>(&'static self, future: impl FnOnce() -> F) -> SpawnToken<impl Sized> {
+        let task = critical_section::with(|cs| {
+            let task = self.tasks.iter().find(|task| task.raw.spawn_mark_used())?;
+            match self.allocate(cs, Layout::new::<F>()) {
+                Some(offset) => {
+                    task.offset.set(offset);
+                    task.size.set(mem::size_of::<F>());
+                    Some(task)
+                }
+                None => {
+                    task.raw.state.store(0, Ordering::Release);
+                    None
+                }
+            }
+        });
+
+        match task {
+            Some(task) => unsafe {
+                let ptr = (self.memory.get() as *mut u8).add(task.offset.get()) as *mut F;
+                task.raw.spawn_initialize(ArenaTask::poll::<F>);
+                ptr.write(future());
+                task.future.set(ptr as *mut ());
+                SpawnToken::<F>::new(TaskRef::from_header(&task.raw))
+            },
+            None => SpawnToken::<F>::new_failed(),
+        }
+    }
+
+    /// Find the first free range of memory that fits `layout`, and return its offset.
+    fn allocate(&self, _cs: CriticalSection, layout: Layout) -> Option<usize> {
+        // Align addresses rather than offsets: the buffer itself is only byte-aligned.
+        let base = self.memory.get() as usize;
+        let align = |offset: usize| (base + offset + layout.align() - 1) / layout.align() * layout.align() - base;
+
+        let mut start = align(0);
+        'search: loop {
+            let end = start.checked_add(layout.size())?;
+            if end > SIZE {
+                return None;
+            }
+            for task in &self.tasks {
+                let (offset, size) = (task.offset.get(), task.size.get());
+                if size != 0 && offset < end && start < offset + size {
+                    start = align(offset + size);
+                    continue 'search;
+                }
+            }
+            return Some(start);
+        }
+    }
+}
+
+unsafe impl<const SIZE: usize, const TASKS: usize> Sync for TaskArena<SIZE, TASKS> {}
+
+#[cfg(all(test, feature = "test-executor"))]
+mod tests {
+
+    use super::*;
+    use crate::test_util::leak;
+    use crate::{SpawnError, TestExecutor};
+
+    #[repr(align(16))]
+    struct Align16([u8; 16]);
+
+    /// Future holding a `T`, which never completes unless `finish` is set.
+    struct Blob<T> {
+        _data: MaybeUninit<T>,
+        finish: bool,
+    }
+
+    impl<T> Future for Blob<T> {
+        type Output = ();
+
+        fn poll(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<()> {
+            if self.finish {
+                Poll::Ready(())
+            } else {
+                Poll::Pending
+            }
+        }
+    }
+
+    fn blob<T>(finish: bool) -> Blob<T> {
+        Blob {
+            _data: MaybeUninit::uninit(),
+            finish,
+        }
+    }
+
+    #[test]
+    fn aligns_futures() {
+        let executor = TestExecutor::new();
+        let arena = leak(TaskArena::<256, 4>::new());
+        let spawner = executor.spawner();
+        spawner.spawn(arena.spawn(|| blob::<u8>(false))).unwrap();
+        spawner.spawn(arena.spawn(|| blob::<u64>(false))).unwrap();
+        spawner.spawn(arena.spawn(|| blob::<Align16>(false))).unwrap();
+        spawner.spawn(arena.spawn(|| blob::<[u8; 3]>(false))).unwrap();
+        executor.run_until_stalled();
+
+        let aligns = [
+            mem::align_of::<Blob<u8>>(),
+            mem::align_of::<Blob<u64>>(),
+            mem::align_of::<Blob<Align16>>(),
+            mem::align_of::<Blob<[u8; 3]>>(),
+        ];
+        for (task, align) in arena.tasks.iter().zip(aligns) {
+            assert_eq!(task.future.get() as usize % align, 0);
+        }
+
+        // The futures don't overlap.
+        for (i, a) in arena.tasks.iter().enumerate() {
+            for b in &arena.tasks[i + 1..] {
+                let (a_start, b_start) = (a.offset.get(), b.offset.get());
+                assert!(a_start + a.size.get() <= b_start || b_start + b.size.get() <= a_start);
+            }
+        }
+    }
+
+    #[test]
+    fn busy_without_free_slot() {
+        let executor = TestExecutor::new();
+        let arena = leak(TaskArena::<256, 2>::new());
+        let spawner = executor.spawner();
+        spawner.spawn(arena.spawn(|| blob::<u8>(false))).unwrap();
+        spawner.spawn(arena.spawn(|| blob::<u8>(false))).unwrap();
+        assert!(matches!(
+            spawner.spawn(arena.spawn(|| blob::<u8>(false))),
+            Err(SpawnError::Busy)
+        ));
+    }
+
+    #[test]
+    fn busy_without_free_memory() {
+        let executor = TestExecutor::new();
+        let arena = leak(TaskArena::<64, 4>::new());
+        let spawner = executor.spawner();
+        spawner.spawn(arena.spawn(|| blob::<[u8; 40]>(false))).unwrap();
+        assert!(matches!(
+            spawner.spawn(arena.spawn(|| blob::<[u8; 40]>(false))),
+            Err(SpawnError::Busy)
+        ));
+        assert!(matches!(
+            spawner.spawn(arena.spawn(|| blob::<[u8; 100]>(false))),
+            Err(SpawnError::Busy)
+        ));
+
+        // The slot taken by a failed spawn is given back.
+        spawner.spawn(arena.spawn(|| blob::<[u8; 8]>(false))).unwrap();
+        spawner.spawn(arena.spawn(|| blob::<u8>(false))).unwrap();
+        spawner.spawn(arena.spawn(|| blob::<u8>(false))).unwrap();
+    }
+
+    #[test]
+    fn reuse_after_finish() {
+        let executor = TestExecutor::new();
+        let arena = leak(TaskArena::<64, 1>::new());
+        let spawner = executor.spawner();
+        let handle = spawner
+            .spawn_with_handle(arena.spawn(|| blob::<[u8; 40]>(true)))
+            .unwrap();
+        assert!(spawner.spawn(arena.spawn(|| blob::<u8>(false))).is_err());
+
+        executor.run_until_stalled();
+        assert!(handle.is_finished());
+        assert_eq!(arena.tasks[0].size.get(), 0);
+        spawner.spawn(arena.spawn(|| blob::<[u8; 40]>(false))).unwrap();
+    }
+
+    #[test]
+    fn reuse_after_abort() {
+        let executor = TestExecutor::new();
+        let arena = leak(TaskArena::<64, 2>::new());
+        let spawner = executor.spawner();
+        let handle = spawner
+            .spawn_with_handle(arena.spawn(|| blob::<[u8; 40]>(false)))
+            .unwrap();
+        executor.run_until_stalled();
+        assert!(spawner.spawn(arena.spawn(|| blob::<[u8; 40]>(false))).is_err());
+
+        handle.abort();
+        executor.run_until_stalled();
+        assert!(handle.is_finished());
+        spawner.spawn(arena.spawn(|| blob::<[u8; 40]>(false))).unwrap();
+    }
+}
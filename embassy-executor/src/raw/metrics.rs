@@ -0,0 +1,221 @@
+use core::cell::Cell;
+use core::ptr;
+
+use atomic_polyfill::{AtomicPtr, Ordering};
+use critical_section::CriticalSection;
+use embassy_time::{Duration, Instant};
+
+use super::{Executor, TaskHeader, TaskRef, STATE_SPAWNED};
+
+/// Head of the list of all tasks that have ever been spawned, in any executor.
+///
+/// Tasks are only ever added to the list, never removed: task storage lives forever,
+/// and may be spawned again later. This keeps iterating the list simple and safe.
+static TASKS: AtomicPtr<TaskHeader> = AtomicPtr::new(ptr::null_mut());
+
+/// Runtime statistics of a task, stored in its header.
+///
+/// Only accessed in a critical section.
+pub(crate) struct TaskMetrics {
+    next: Cell<Option<TaskRef>>,
+    registered: Cell<bool>,
+    name: Cell<Option<&'static str>>,
+    poll_count: Cell<u32>,
+    poll_time: Cell<Duration>,
+    longest_poll: Cell<Duration>,
+    last_woken: Cell<Option<Instant>>,
+
+    /// The task is being polled.
+    #[cfg(feature = "watchdog")]
+    polling: Cell<bool>,
+    /// A poll of the task has completed since the last progress check.
+    #[cfg(feature = "watchdog")]
+    polled: Cell<bool>,
+    /// The task was waiting to be polled, or being polled, at the last progress check.
+    #[cfg(feature = "watchdog")]
+    pending_at_check: Cell<bool>,
+}
+
+impl TaskMetrics {
+    pub(crate) const fn new() -> Self {
+        Self {
+            next: Cell::new(None),
+            registered: Cell::new(false),
+            name: Cell::new(None),
+            poll_count: Cell::new(0),
+            poll_time: Cell::new(Duration::from_ticks(0)),
+            longest_poll: Cell::new(Duration::from_ticks(0)),
+            last_woken: Cell::new(None),
+
+            #[cfg(feature = "watchdog")]
+            polling: Cell::new(false),
+            #[cfg(feature = "watchdog")]
+            polled: Cell::new(false),
+            #[cfg(feature = "watchdog")]
+            pending_at_check: Cell::new(false),
+        }
+    }
+
+    /// Clear the statistics of a previous run of the task, when it's spawned again.
+    pub(crate) fn reset(&self, _cs: CriticalSection) {
+        self.name.set(None);
+        self.poll_count.set(0);
+        self.poll_time.set(Duration::from_ticks(0));
+        self.longest_poll.set(Duration::from_ticks(0));
+        self.last_woken.set(None);
+
+        #[cfg(feature = "watchdog")]
+        {
+            self.polling.set(false);
+            self.polled.set(false);
+            self.pending_at_check.set(false);
+        }
+    }
+
+    pub(crate) fn set_name(&self, _cs: CriticalSection, name: &'static str) {
+        self.name.set(Some(name));
+    }
+
+    pub(crate) fn record_wake(&self, _cs: CriticalSection) {
+        self.last_woken.set(Some(Instant::now()));
+    }
+
+    #[cfg(feature = "watchdog")]
+    pub(crate) fn record_poll_start(&self, _cs: CriticalSection) {
+        self.polling.set(true);
+    }
+
+    pub(crate) fn record_poll(&self, _cs: CriticalSection, duration: Duration) {
+        self.poll_count.set(self.poll_count.get().wrapping_add(1));
+        self.poll_time.set(self.poll_time.get() + duration);
+        self.longest_poll.set(self.longest_poll.get().max(duration));
+
+        #[cfg(feature = "watchdog")]
+        {
+            self.polling.set(false);
+            self.polled.set(true);
+        }
+    }
+
+    pub(crate) fn name(&self, _cs: CriticalSection) -> Option<&'static str> {
+        self.name.get()
+    }
+
+    /// Returns whether the task has made progress since the last check, and start a new check.
+    ///
+    /// A task has made progress unless it was already waiting to be polled, or being polled,
+    /// at the last check, and no poll of it has completed since.
+    #[cfg(feature = "watchdog")]
+    pub(crate) fn check_progress(&self, _cs: CriticalSection, run_queued: bool) -> bool {
+        let progressed = !self.pending_at_check.get() || self.polled.get();
+        self.pending_at_check.set(run_queued || self.polling.get());
+        self.polled.set(false);
+        progressed
+    }
+}
+
+/// Add a task to the list of all tasks, if it's not there yet.
+pub(crate) fn register(_cs: CriticalSection, task: TaskRef) {
+    let metrics = &task.header().metrics;
+    if metrics.registered.get() {
+        return;
+    }
+    metrics.registered.set(true);
+
+    let head = TASKS.load(Ordering::Relaxed);
+    metrics.next.set(if head.is_null() {
+        None
+    } else {
+        Some(unsafe { TaskRef::from_ptr(head) })
+    });
+    TASKS.store(task.as_ptr() as _, Ordering::Relaxed);
+}
+
+/// Iterate over all tasks that have ever been spawned, in any executor.
+#[cfg(any(feature = "watchdog", feature = "rtos-trace"))]
+pub(crate) fn all_tasks(_cs: CriticalSection) -> impl Iterator<Item = TaskRef> {
+    let head = TASKS.load(Ordering::Acquire);
+    let head = if head.is_null() {
+        None
+    } else {
+        Some(unsafe { TaskRef::from_ptr(head) })
+    };
+    core::iter::successors(head, |task| task.header().metrics.next.get())
+}
+
+/// Snapshot of the runtime statistics of a task, returned by [`Executor::tasks()`].
+#[derive(Clone, Copy)]
+pub struct TaskInfo {
+    /// The task.
+    pub task: TaskRef,
+    /// Name of the task.
+    ///
+    /// Tasks declared with the [`embassy_executor::task`](embassy_macros::task) macro are named
+    /// after their function. Others have no name, unless given one with
+    /// [`SpawnToken::with_name()`](crate::SpawnToken::with_name).
+    pub name: Option<&'static str>,
+    /// Number of times the task has been polled.
+    pub poll_count: u32,
+    /// Total time spent polling the task.
+    pub poll_time: Duration,
+    /// Duration of the longest single poll of the task.
+    ///
+    /// No other task in the executor can run while a task is being polled, so
+    /// a long poll indicates a task blocking the executor.
+    pub longest_poll: Duration,
+    /// Last time the task was woken, or spawned if it hasn't been woken since.
+    pub last_woken: Option<Instant>,
+}
+
+/// Iterator over the tasks of an executor, returned by [`Executor::tasks()`].
+pub struct Tasks {
+    executor: &'static Executor,
+    next: Option<TaskRef>,
+}
+
+impl Tasks {
+    pub(crate) fn new(executor: &'static Executor) -> Self {
+        let head = TASKS.load(Ordering::Acquire);
+        Self {
+            executor,
+            next: if head.is_null() {
+                None
+            } else {
+                Some(unsafe { TaskRef::from_ptr(head) })
+            },
+        }
+    }
+}
+
+impl Iterator for Tasks {
+    type Item = TaskInfo;
+
+    fn next(&mut self) -> Option<TaskInfo> {
+        while let Some(task) = self.next {
+            let info = critical_section::with(|cs| {
+                let header = task.header();
+                let metrics = &header.metrics;
+                self.next = metrics.next.get();
+
+                let spawned = header.state.load(Ordering::Relaxed) & STATE_SPAWNED != 0;
+                if !spawned || !ptr::eq(header.executor.get(), self.executor) {
+                    return None;
+                }
+
+                Some(TaskInfo {
+                    task,
+                    name: metrics.name(cs),
+                    poll_count: metrics.poll_count.get(),
+                    poll_time: metrics.poll_time.get(),
+                    longest_poll: metrics.longest_poll.get(),
+                    last_woken: metrics.last_woken.get(),
+                })
+            });
+
+            if info.is_some() {
+                return info;
+            }
+        }
+        None
+    }
+}
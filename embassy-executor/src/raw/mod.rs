@@ -7,33 +7,55 @@
 //! Using this module requires respecting subtle safety contracts. If you can, prefer using the safe
 //! [executor wrappers](crate::Executor) and the [`embassy_executor::task`](embassy_macros::task) macro, which are fully safe.
 
+mod arena;
+#[cfg(feature = "metrics")]
+mod metrics;
 mod run_queue;
+mod run_until;
 #[cfg(feature = "integrated-timers")]
 mod timer_queue;
 pub(crate) mod util;
 mod waker;
+#[cfg(feature = "watchdog")]
+mod watchdog;
 
 use core::cell::Cell;
 use core::future::Future;
 use core::pin::Pin;
 use core::ptr::NonNull;
-use core::task::{Context, Poll};
+use core::task::{Context, Poll, Waker};
 use core::{mem, ptr};
 
 use atomic_polyfill::{AtomicU32, Ordering};
 use critical_section::CriticalSection;
 #[cfg(feature = "integrated-timers")]
 use embassy_time::driver::{self, AlarmHandle};
-#[cfg(feature = "integrated-timers")]
+#[cfg(any(feature = "integrated-timers", feature = "metrics"))]
 use embassy_time::Instant;
 #[cfg(feature = "rtos-trace")]
 use rtos_trace::trace;
 
+pub use self::arena::TaskArena;
+#[cfg(feature = "metrics")]
+pub use self::metrics::{TaskInfo, Tasks};
 use self::run_queue::{RunQueue, RunQueueItem};
+// Not used on architectures without a thread-mode executor, such as wasm.
+#[allow(unused_imports)]
+pub(crate) use self::run_until::RunUntil;
 use self::util::UninitCell;
 pub use self::waker::task_from_waker;
+#[cfg(feature = "watchdog")]
+pub use self::watchdog::{
+    all_tasks_progressed, clear_slow_poll_hook, feed_if_progressed, set_slow_poll_hook, SlowPoll,
+};
 use super::SpawnToken;
 
+/// Number of priority levels supported by the [`Executor`].
+///
+/// Tasks are given a priority in `0..PRIORITY_LEVELS` when spawning, with
+/// [`SpawnToken::with_priority()`]. Tasks with a higher priority are polled first.
+pub const PRIORITY_LEVELS: usize = 4;
+
 /// Task is spawned (has a future)
 pub(crate) const STATE_SPAWNED: u32 = 1 << 0;
 /// Task is in the executor run queue
@@ -41,18 +63,32 @@ pub(crate) const STATE_RUN_QUEUED: u32 = 1 << 1;
 /// Task is in the executor timer queue
 #[cfg(feature = "integrated-timers")]
 pub(crate) const STATE_TIMER_QUEUED: u32 = 1 << 2;
+/// Task has been aborted, its future must be dropped instead of polled
+pub(crate) const STATE_ABORTED: u32 = 1 << 3;
+/// Task is being polled by a thread pool worker. Wakes don't enqueue it, the worker does once done.
+#[cfg(feature = "std")]
+pub(crate) const STATE_POLLING: u32 = 1 << 4;
 
 /// Raw task header for use in task pointers.
 pub(crate) struct TaskHeader {
     pub(crate) state: AtomicU32,
     pub(crate) run_queue_item: RunQueueItem,
     pub(crate) executor: Cell<*const Executor>,         // Valid if state != 0
+    pub(crate) priority: Cell<u8>,                      // Valid if STATE_SPAWNED
     pub(crate) poll_fn: UninitCell<unsafe fn(TaskRef)>, // Valid if STATE_SPAWNED
+    /// Incremented every time the task finishes. Only accessed in a critical section,
+    /// or while the task is spawned.
+    pub(crate) generation: Cell<u32>,
+    /// Waker of the `JoinHandle` waiting for the task to finish. Only accessed in a critical section.
+    pub(crate) join_waker: Cell<Option<Waker>>,
 
     #[cfg(feature = "integrated-timers")]
     pub(crate) expires_at: Cell<Instant>,
     #[cfg(feature = "integrated-timers")]
     pub(crate) timer_queue_item: timer_queue::TimerQueueItem,
+
+    #[cfg(feature = "metrics")]
+    pub(crate) metrics: metrics::TaskMetrics,
 }
 
 impl TaskHeader {
@@ -61,12 +97,49 @@ impl TaskHeader {
             state: AtomicU32::new(0),
             run_queue_item: RunQueueItem::new(),
             executor: Cell::new(ptr::null()),
+            priority: Cell::new(0),
             poll_fn: UninitCell::uninit(),
+            generation: Cell::new(0),
+            join_waker: Cell::new(None),
 
             #[cfg(feature = "integrated-timers")]
             expires_at: Cell::new(Instant::from_ticks(0)),
             #[cfg(feature = "integrated-timers")]
             timer_queue_item: timer_queue::TimerQueueItem::new(),
+
+            #[cfg(feature = "metrics")]
+            metrics: metrics::TaskMetrics::new(),
+        }
+    }
+
+    /// Claim the task for spawning. Returns false if it is already spawned.
+    fn spawn_mark_used(&self) -> bool {
+        let state = STATE_SPAWNED | STATE_RUN_QUEUED;
+        self.state
+            .compare_exchange(0, state, Ordering::AcqRel, Ordering::Acquire)
+            .is_ok()
+    }
+
+    /// Reset a task claimed with `spawn_mark_used`, before its new future is written.
+    unsafe fn spawn_initialize(&self, poll_fn: unsafe fn(TaskRef)) {
+        self.poll_fn.write(poll_fn);
+        self.priority.set(0);
+        #[cfg(feature = "metrics")]
+        critical_section::with(|cs| self.metrics.reset(cs));
+    }
+
+    /// Mark the task as finished, once its future has been dropped.
+    ///
+    /// This frees the task storage for spawning again, and notifies the `JoinHandle`, if any.
+    fn finish(&self) {
+        let waker = critical_section::with(|_| {
+            self.generation.set(self.generation.get().wrapping_add(1));
+            self.state.fetch_and(!(STATE_SPAWNED | STATE_ABORTED), Ordering::AcqRel);
+            self.join_waker.take()
+        });
+
+        if let Some(waker) = waker {
+            waker.wake();
         }
     }
 }
@@ -84,6 +157,12 @@ impl TaskRef {
         }
     }
 
+    fn from_header(header: &'static TaskHeader) -> Self {
+        Self {
+            ptr: NonNull::from(header),
+        }
+    }
+
     /// Safety: The pointer must have been obtained with `Task::as_ptr`
     pub(crate) unsafe fn from_ptr(ptr: *const TaskHeader) -> Self {
         Self {
@@ -125,6 +204,12 @@ pub struct TaskStorage<F: Future + 'static> {
 impl<F: Future + 'static> TaskStorage<F> {
     const NEW: Self = Self::new();
 
+    /// Size of the task's future, in bytes.
+    ///
+    /// This is the memory needed to hold the task's state across `.await`s, which is most
+    /// of the size of the `TaskStorage`.
+    pub const FUTURE_SIZE: usize = mem::size_of::<F>();
+
     /// Create a new TaskStorage, in not-spawned state.
     pub const fn new() -> Self {
         Self {
@@ -144,7 +229,8 @@ impl<F: Future + 'static> TaskStorage<F> {
     /// In this case, the error is delayed: a "poisoned" SpawnToken is returned, which will
     /// cause [`Spawner::spawn()`](super::Spawner::spawn) to return the error.
     ///
-    /// Once the task has finished running, you may spawn it again. It is allowed to spawn it
+    /// Once the task has finished running, or has been aborted through its
+    /// [`JoinHandle`](super::JoinHandle), you may spawn it again. It is allowed to spawn it
     /// on a different executor.
     pub fn spawn(&'static self, future: impl FnOnce() -> F) -> SpawnToken<impl Sized> {
         if self.spawn_mark_used() {
@@ -155,16 +241,12 @@ impl<F: Future + 'static> TaskStorage<F> {
     }
 
     fn spawn_mark_used(&'static self) -> bool {
-        let state = STATE_SPAWNED | STATE_RUN_QUEUED;
-        self.raw
-            .state
-            .compare_exchange(0, state, Ordering::AcqRel, Ordering::Acquire)
-            .is_ok()
+        self.raw.spawn_mark_used()
     }
 
     unsafe fn spawn_initialize(&'static self, future: impl FnOnce() -> F) -> TaskRef {
         // Initialize the task
-        self.raw.poll_fn.write(Self::poll);
+        self.raw.spawn_initialize(Self::poll);
         self.future.write(future());
         TaskRef::new(self)
     }
@@ -172,13 +254,19 @@ impl<F: Future + 'static> TaskStorage<F> {
     unsafe fn poll(p: TaskRef) {
         let this = &*(p.as_ptr() as *const TaskStorage<F>);
 
+        if this.raw.state.load(Ordering::Acquire) & STATE_ABORTED != 0 {
+            this.future.drop_in_place();
+            this.raw.finish();
+            return;
+        }
+
         let future = Pin::new_unchecked(this.future.as_mut());
         let waker = waker::from_task(p);
         let mut cx = Context::from_waker(&waker);
         match future.poll(&mut cx) {
             Poll::Ready(_) => {
                 this.future.drop_in_place();
-                this.raw.state.fetch_and(!STATE_SPAWNED, Ordering::AcqRel);
+                this.raw.finish();
             }
             Poll::Pending => {}
         }
@@ -199,6 +287,11 @@ pub struct TaskPool<F: Future + 'static, const N: usize> {
 }
 
 impl<F: Future + 'static, const N: usize> TaskPool<F, N> {
+    /// Size of the future of one task of the pool, in bytes.
+    ///
+    /// See [`TaskStorage::FUTURE_SIZE`].
+    pub const FUTURE_SIZE: usize = TaskStorage::<F>::FUTURE_SIZE;
+
     /// Create a new TaskPool, with all tasks in non-spawned state.
     pub const fn new() -> Self {
         Self {
@@ -291,10 +384,19 @@ impl<F: Future + 'static, const N: usize> TaskPool<F, N> {
 ///
 /// In particular, you must NOT call `poll` directly from `signal_fn`, as this violates
 /// the requirement for `poll` to not be called reentrantly.
+///
+/// Each task has a priority, from 0 (the default) to [`PRIORITY_LEVELS`]` - 1`. There is one
+/// run queue per priority level, and `poll()` always handles the queues of higher priority first.
+/// Priorities are not preemptive: a task being polled is never interrupted by a higher priority
+/// task that gets woken. If you need preemption, use several executors running in interrupts of
+/// different priorities.
 pub struct Executor {
-    run_queue: RunQueue,
+    run_queues: [RunQueue; PRIORITY_LEVELS],
     signal_fn: fn(*mut ()),
     signal_ctx: *mut (),
+    /// Tasks may be polled from several threads, so only Send tasks may be spawned.
+    #[cfg(feature = "std")]
+    pub(crate) send_only: bool,
 
     #[cfg(feature = "integrated-timers")]
     pub(crate) timer_queue: timer_queue::TimerQueue,
@@ -316,9 +418,11 @@ impl Executor {
         driver::set_alarm_callback(alarm, signal_fn, signal_ctx);
 
         Self {
-            run_queue: RunQueue::new(),
+            run_queues: [RunQueue::NEW; PRIORITY_LEVELS],
             signal_fn,
             signal_ctx,
+            #[cfg(feature = "std")]
+            send_only: false,
 
             #[cfg(feature = "integrated-timers")]
             timer_queue: timer_queue::TimerQueue::new(),
@@ -338,7 +442,11 @@ impl Executor {
         #[cfg(feature = "rtos-trace")]
         trace::task_ready_begin(task.as_ptr() as u32);
 
-        if self.run_queue.enqueue(cs, task) {
+        #[cfg(feature = "metrics")]
+        task.header().metrics.record_wake(cs);
+
+        let run_queue = &self.run_queues[task.header().priority.get() as usize];
+        if run_queue.enqueue(cs, task) {
             (self.signal_fn)(self.signal_ctx)
         }
     }
@@ -359,6 +467,9 @@ impl Executor {
         trace::task_new(task.as_ptr() as u32);
 
         critical_section::with(|cs| {
+            #[cfg(feature = "metrics")]
+            metrics::register(cs, task);
+
             self.enqueue(cs, task);
         })
     }
@@ -381,39 +492,19 @@ impl Executor {
     /// somehow schedule for `poll()` to be called later, at a time you know for sure there's
     /// no `poll()` already running.
     pub unsafe fn poll(&'static self) {
-        loop {
+        'poll: loop {
             #[cfg(feature = "integrated-timers")]
-            self.timer_queue.dequeue_expired(Instant::now(), |task| wake_task(task));
-
-            self.run_queue.dequeue_all(|p| {
-                let task = p.header();
-
-                #[cfg(feature = "integrated-timers")]
-                task.expires_at.set(Instant::MAX);
-
-                let state = task.state.fetch_and(!STATE_RUN_QUEUED, Ordering::AcqRel);
-                if state & STATE_SPAWNED == 0 {
-                    // If task is not running, ignore it. This can happen in the following scenario:
-                    //   - Task gets dequeued, poll starts
-                    //   - While task is being polled, it gets woken. It gets placed in the queue.
-                    //   - Task poll finishes, returning done=true
-                    //   - RUNNING bit is cleared, but the task is already in the queue.
-                    return;
-                }
-
-                #[cfg(feature = "rtos-trace")]
-                trace::task_exec_begin(p.as_ptr() as u32);
+            self.timer_queue.dequeue_expired(Instant::now(), wake_task);
 
-                // Run the task
-                task.poll_fn.read()(p);
+            // Handle one batch of tasks per priority level, highest first. If tasks of a higher
+            // level were woken in the meantime, start over from the top.
+            for level in (0..PRIORITY_LEVELS).rev() {
+                self.run_queues[level].dequeue_all(|p| self.poll_task(p));
 
-                #[cfg(feature = "rtos-trace")]
-                trace::task_exec_end();
-
-                // Enqueue or update into timer_queue
-                #[cfg(feature = "integrated-timers")]
-                self.timer_queue.update(p);
-            });
+                if self.run_queues[level + 1..].iter().any(|q| !q.is_empty()) {
+                    continue 'poll;
+                }
+            }
 
             #[cfg(feature = "integrated-timers")]
             {
@@ -435,6 +526,153 @@ impl Executor {
         trace::system_idle();
     }
 
+    /// Poll a task that has just been dequeued from a run queue.
+    unsafe fn poll_task(&'static self, p: TaskRef) {
+        let task = p.header();
+
+        #[cfg(feature = "integrated-timers")]
+        task.expires_at.set(Instant::MAX);
+
+        let state = task.state.fetch_and(!STATE_RUN_QUEUED, Ordering::AcqRel);
+        if state & STATE_SPAWNED == 0 {
+            // If task is not running, ignore it. This can happen in the following scenario:
+            //   - Task gets dequeued, poll starts
+            //   - While task is being polled, it gets woken. It gets placed in the queue.
+            //   - Task poll finishes, returning done=true
+            //   - RUNNING bit is cleared, but the task is already in the queue.
+            return;
+        }
+
+        self.run_task(p);
+
+        // Enqueue or update into timer_queue
+        #[cfg(feature = "integrated-timers")]
+        self.timer_queue.update(p);
+    }
+
+    /// Poll a task from a [`ThreadPoolExecutor`](crate::ThreadPoolExecutor) worker thread.
+    ///
+    /// The task must have been taken from a run queue with `dequeue_batch`. If the task gets woken
+    /// while it's being polled, it's enqueued again once the poll is done, so that no other worker
+    /// can poll it at the same time.
+    ///
+    /// Returns when the task's timer expires, if it has one.
+    #[cfg(feature = "std")]
+    pub(crate) unsafe fn poll_task_shared(&'static self, p: TaskRef) -> Option<u64> {
+        let task = p.header();
+
+        let spawned = critical_section::with(|_| {
+            #[cfg(feature = "integrated-timers")]
+            task.expires_at.set(Instant::MAX);
+
+            let state = task.state.load(Ordering::Relaxed) & !STATE_RUN_QUEUED;
+            if state & STATE_SPAWNED == 0 {
+                // Finished while in the queue, see `poll_task`.
+                task.state.store(state, Ordering::Relaxed);
+                return false;
+            }
+            task.state.store(state | STATE_POLLING, Ordering::Relaxed);
+            true
+        });
+        if !spawned {
+            return None;
+        }
+
+        self.run_task(p);
+
+        critical_section::with(|cs| {
+            let state = task.state.fetch_and(!STATE_POLLING, Ordering::AcqRel);
+
+            #[cfg(feature = "integrated-timers")]
+            self.timer_queue.update(p);
+
+            if state & STATE_RUN_QUEUED != 0 {
+                // Woken during the poll.
+                self.enqueue(cs, p);
+            }
+
+            #[cfg(feature = "integrated-timers")]
+            if task.expires_at.get() != Instant::MAX {
+                return Some(task.expires_at.get().as_ticks());
+            }
+            None
+        })
+    }
+
+    /// Take all tasks in the run queues, highest priority first, for `poll_task_shared`.
+    #[cfg(feature = "std")]
+    pub(crate) fn dequeue_batch(&self, mut on_task: impl FnMut(TaskRef)) {
+        for run_queue in self.run_queues.iter().rev() {
+            run_queue.dequeue_all(&mut on_task);
+        }
+    }
+
+    /// Wake the tasks whose timer expired, and set the alarm for the next expiration.
+    ///
+    /// Returns the time of the next expiration. This is the thread pool version of the timer
+    /// handling in `poll`.
+    #[cfg(all(feature = "std", feature = "integrated-timers"))]
+    pub(crate) fn process_timers(&'static self) -> u64 {
+        critical_section::with(|_| unsafe {
+            loop {
+                self.timer_queue.dequeue_expired(Instant::now(), wake_task);
+
+                // If this is already in the past, set_alarm might return false
+                // In that case do another iteration.
+                let next_expiration = self.timer_queue.next_expiration();
+                if driver::set_alarm(self.alarm, next_expiration.as_ticks()) {
+                    return next_expiration.as_ticks();
+                }
+            }
+        })
+    }
+
+    /// Time of the next timer expiration in ticks, or `u64::MAX` if no timer is pending.
+    ///
+    /// # Safety
+    ///
+    /// Must be called from the thread that polls the executor, between calls to [`poll`](Self::poll).
+    #[cfg(feature = "integrated-timers")]
+    pub(crate) unsafe fn next_expiration(&self) -> u64 {
+        self.timer_queue.next_expiration().as_ticks()
+    }
+
+    /// Run the poll function of a spawned task.
+    unsafe fn run_task(&'static self, p: TaskRef) {
+        let task = p.header();
+
+        #[cfg(feature = "rtos-trace")]
+        trace::task_exec_begin(p.as_ptr() as u32);
+
+        #[cfg(feature = "watchdog")]
+        critical_section::with(|cs| task.metrics.record_poll_start(cs));
+        #[cfg(feature = "metrics")]
+        let start = Instant::now();
+
+        // Run the task
+        task.poll_fn.read()(p);
+
+        #[cfg(feature = "metrics")]
+        {
+            let duration = start.elapsed();
+            critical_section::with(|cs| task.metrics.record_poll(cs, duration));
+            #[cfg(feature = "watchdog")]
+            watchdog::check_poll(p, duration);
+        }
+
+        #[cfg(feature = "rtos-trace")]
+        trace::task_exec_end();
+    }
+
+    /// Iterate over the tasks currently spawned in this executor, with their runtime statistics.
+    ///
+    /// Statistics are collected from the moment a task is spawned, and reset when a task
+    /// storage is spawned again.
+    #[cfg(feature = "metrics")]
+    pub fn tasks(&'static self) -> Tasks {
+        Tasks::new(self)
+    }
+
     /// Get a spawner that spawns tasks in this executor.
     ///
     /// It is OK to call this method multiple times to obtain multiple
@@ -460,6 +698,12 @@ pub fn wake_task(task: TaskRef) {
         // Mark it as scheduled
         header.state.store(state | STATE_RUN_QUEUED, Ordering::Relaxed);
 
+        #[cfg(feature = "std")]
+        if state & STATE_POLLING != 0 {
+            // The thread pool worker polling the task enqueues it when done.
+            return;
+        }
+
         // We have just marked the task as scheduled, so enqueue it.
         unsafe {
             let executor = &*header.executor.get();
@@ -468,6 +712,37 @@ pub fn wake_task(task: TaskRef) {
     })
 }
 
+/// Abort a task, if it hasn't finished since `generation`.
+///
+/// The task is enqueued, and the executor drops its future instead of polling it. Doing it
+/// from the executor ensures the future is never dropped while it's being polled, even if
+/// a task aborts itself.
+pub(crate) fn abort_task(task: TaskRef, generation: u32) {
+    critical_section::with(|cs| {
+        let header = task.header();
+        if header.generation.get() != generation {
+            // Already finished, the storage may even hold another task by now.
+            return;
+        }
+
+        let state = header
+            .state
+            .fetch_or(STATE_ABORTED | STATE_RUN_QUEUED, Ordering::AcqRel);
+        // A task being polled by a thread pool worker is enqueued by the worker when done.
+        #[cfg(feature = "std")]
+        let queued = STATE_RUN_QUEUED | STATE_POLLING;
+        #[cfg(not(feature = "std"))]
+        let queued = STATE_RUN_QUEUED;
+        if state & queued == 0 {
+            // The executor will find out about the abort when dequeuing the task.
+            unsafe {
+                let executor = &*header.executor.get();
+                executor.enqueue(cs, task);
+            }
+        }
+    })
+}
+
 #[cfg(feature = "integrated-timers")]
 struct TimerQueue;
 
@@ -476,8 +751,12 @@ impl embassy_time::queue::TimerQueue for TimerQueue {
     fn schedule_wake(&'static self, at: Instant, waker: &core::task::Waker) {
         let task = waker::task_from_waker(waker);
         let task = task.header();
-        let expires_at = task.expires_at.get();
-        task.expires_at.set(expires_at.min(at));
+        // The critical section is needed by the thread pool executor, where other
+        // workers may be reading the expiration.
+        critical_section::with(|_| {
+            let expires_at = task.expires_at.get();
+            task.expires_at.set(expires_at.min(at));
+        })
     }
 }
 
@@ -486,8 +765,27 @@ embassy_time::timer_queue_impl!(static TIMER_QUEUE: TimerQueue = TimerQueue);
 
 #[cfg(feature = "rtos-trace")]
 impl rtos_trace::RtosTraceOSCallbacks for Executor {
+    #[cfg(feature = "metrics")]
+    fn task_list() {
+        critical_section::with(|cs| {
+            for task in metrics::all_tasks(cs) {
+                let header = task.header();
+                if header.state.load(Ordering::Relaxed) & STATE_SPAWNED == 0 {
+                    continue;
+                }
+                let info = rtos_trace::TaskInfo {
+                    name: header.metrics.name(cs).unwrap_or(""),
+                    priority: header.priority.get() as u32,
+                    stack_base: 0,
+                    stack_size: 0,
+                };
+                trace::task_send_info(task.as_ptr() as u32, info);
+            }
+        })
+    }
+    #[cfg(not(feature = "metrics"))]
     fn task_list() {
-        // We don't know what tasks exist, so we can't send them.
+        // Without the `metrics` feature, we don't know what tasks exist, so we can't send them.
     }
     #[cfg(feature = "integrated-timers")]
     fn time() -> u64 {
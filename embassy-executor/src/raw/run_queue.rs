@@ -34,12 +34,20 @@ pub(crate) struct RunQueue {
 }
 
 impl RunQueue {
+    #[allow(clippy::declare_interior_mutable_const)]
+    pub const NEW: Self = Self::new();
+
     pub const fn new() -> Self {
         Self {
             head: AtomicPtr::new(ptr::null_mut()),
         }
     }
 
+    /// Returns true if no task is enqueued.
+    pub(crate) fn is_empty(&self) -> bool {
+        self.head.load(Ordering::Relaxed).is_null()
+    }
+
     /// Enqueues an item. Returns true if the queue was empty.
     ///
     /// # Safety
@@ -56,7 +64,7 @@ impl RunQueue {
     /// Empty the queue, then call `on_task` for each task that was in the queue.
     /// NOTE: It is OK for `on_task` to enqueue more tasks. In this case they're left in the queue
     /// and will be processed by the *next* call to `dequeue_all`, *not* the current one.
-    pub(crate) fn dequeue_all(&self, on_task: impl Fn(TaskRef)) {
+    pub(crate) fn dequeue_all(&self, mut on_task: impl FnMut(TaskRef)) {
         // Atomically empty the queue.
         let mut ptr = self.head.swap(ptr::null_mut(), Ordering::AcqRel);
 
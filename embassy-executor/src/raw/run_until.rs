@@ -0,0 +1,95 @@
+use core::future::Future;
+use core::mem;
+use core::pin::Pin;
+use core::task::{Context, Poll};
+
+use futures_util::pin_mut;
+
+use super::{Executor, TaskStorage};
+use crate::JoinHandle;
+
+/// Pointer to the future passed to `run_until`, with its lifetime erased.
+struct DynFuture(*mut (dyn Future<Output = ()> + 'static));
+
+impl Future for DynFuture {
+    type Output = ();
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<()> {
+        let fut = self.0;
+        // Safety: the future is pinned in `RunUntil::run`, and outlives the task.
+        unsafe { Pin::new_unchecked(&mut *fut).poll(cx) }
+    }
+}
+
+/// Task storage for the future passed to an executor's `run_until`.
+///
+/// Like any task storage, it must live forever: wakers of the task may be kept around
+/// after `run_until` returns. Waking them is harmless then, the task is not spawned.
+// Not every arch has a `run_until`.
+#[allow(dead_code)]
+pub(crate) struct RunUntil {
+    task: TaskStorage<DynFuture>,
+}
+
+#[allow(dead_code)]
+impl RunUntil {
+    pub(crate) const fn new() -> Self {
+        Self {
+            task: TaskStorage::new(),
+        }
+    }
+
+    /// Run `fut` as a task in `executor`, until it completes, and return its output.
+    ///
+    /// This polls the executor, then calls `wait`, until the future has completed. `wait` must
+    /// return when the executor may have work to do, typically when it has been signaled.
+    ///
+    /// The other tasks of the executor are left as they are when the future completes.
+    ///
+    /// # Panics
+    ///
+    /// Panics if called again while already running, for example from a task.
+    pub(crate) fn run<F: Future>(
+        &'static self,
+        executor: &'static Executor,
+        fut: F,
+        mut wait: impl FnMut(),
+    ) -> F::Output {
+        let mut output = None;
+        {
+            let fut = async {
+                output = Some(fut.await);
+            };
+            pin_mut!(fut);
+            let fut: &mut dyn Future<Output = ()> = unsafe { fut.get_unchecked_mut() };
+            // Safety: the task is finished, or aborted by `AbortOnDrop`, before `fut` goes out of scope.
+            // An aborted task is never polled again, and dropping it doesn't touch the future.
+            let fut = DynFuture(unsafe { mem::transmute(fut as *mut dyn Future<Output = ()>) });
+
+            // The storage being busy also prevents polling the executor reentrantly.
+            let handle = unwrap!(
+                executor.spawner().spawn_with_handle(self.task.spawn(|| fut)),
+                "run_until is already running"
+            );
+            let handle = AbortOnDrop(handle);
+
+            loop {
+                unsafe { executor.poll() };
+                if handle.0.is_finished() {
+                    break;
+                }
+                wait();
+            }
+        }
+        unwrap!(output)
+    }
+}
+
+/// Aborts the `run_until` task, in case the future is dropped before completing.
+struct AbortOnDrop(JoinHandle);
+
+impl Drop for AbortOnDrop {
+    fn drop(&mut self) {
+        self.0.abort();
+    }
+}
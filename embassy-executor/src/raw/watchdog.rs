@@ -0,0 +1,109 @@
+use core::cell::Cell;
+
+use atomic_polyfill::Ordering;
+use critical_section::Mutex;
+use embassy_time::Duration;
+
+use super::{metrics, TaskRef, STATE_RUN_QUEUED, STATE_SPAWNED};
+
+/// Hook called for polls longer than the limit, set with [`set_slow_poll_hook()`].
+static SLOW_POLL_HOOK: Mutex<Cell<Option<SlowPollHook>>> = Mutex::new(Cell::new(None));
+
+#[derive(Clone, Copy)]
+struct SlowPollHook {
+    limit: Duration,
+    hook: fn(&SlowPoll),
+}
+
+/// A poll of a task that took longer than the limit set with [`set_slow_poll_hook()`].
+#[derive(Clone, Copy)]
+pub struct SlowPoll {
+    /// The task.
+    pub task: TaskRef,
+    /// Name of the task, see [`TaskInfo::name`](super::TaskInfo::name).
+    pub name: Option<&'static str>,
+    /// Duration of the poll.
+    pub duration: Duration,
+}
+
+/// Call `hook` after any poll of a task, in any executor, that takes longer than `limit`.
+///
+/// No other task of the executor can run while a task is being polled, so a slow poll
+/// usually means a task is doing blocking work, or loops without awaiting.
+///
+/// The hook is called from the executor, right after the slow poll. It should return
+/// quickly, for example by logging the task name or recording it for later.
+///
+/// A task that never returns from its poll can't be reported: use [`feed_if_progressed()`]
+/// to have a hardware watchdog reset the device in that case.
+pub fn set_slow_poll_hook(limit: Duration, hook: fn(&SlowPoll)) {
+    critical_section::with(|cs| SLOW_POLL_HOOK.borrow(cs).set(Some(SlowPollHook { limit, hook })));
+}
+
+/// Stop reporting slow polls.
+pub fn clear_slow_poll_hook() {
+    critical_section::with(|cs| SLOW_POLL_HOOK.borrow(cs).set(None));
+}
+
+/// Report a poll of `task` that took `duration` to the hook, if it's too long.
+pub(crate) fn check_poll(task: TaskRef, duration: Duration) {
+    let report = critical_section::with(|cs| match SLOW_POLL_HOOK.borrow(cs).get() {
+        Some(SlowPollHook { limit, hook }) if duration > limit => {
+            let name = task.header().metrics.name(cs);
+            Some((hook, SlowPoll { task, name, duration }))
+        }
+        _ => None,
+    });
+
+    if let Some((hook, slow_poll)) = report {
+        hook(&slow_poll);
+    }
+}
+
+/// Returns whether all tasks, in all executors, have made progress since the last call.
+///
+/// A task has made progress unless, at the last call, it was waiting to be polled or being
+/// polled, and no poll of it has completed since. When this returns false, some task has been
+/// starved or stuck for the whole time between the two calls: a task of the same executor
+/// has blocked it for too long.
+///
+/// The state of the check is global, so this must be called from a single place,
+/// periodically. See [`feed_if_progressed()`].
+pub fn all_tasks_progressed() -> bool {
+    critical_section::with(|cs| {
+        let mut progressed = true;
+        for task in metrics::all_tasks(cs) {
+            let state = task.header().state.load(Ordering::Relaxed);
+            if state & STATE_SPAWNED == 0 {
+                continue;
+            }
+            // Check all tasks, to start a new check for each of them.
+            progressed &= task.header().metrics.check_progress(cs, state & STATE_RUN_QUEUED != 0);
+        }
+        progressed
+    })
+}
+
+/// Feed a watchdog only if all tasks have made progress since the last call.
+///
+/// `feed` is called if [`all_tasks_progressed()`] returns true. Call this periodically,
+/// with a period shorter than the watchdog timeout, from a timer interrupt or a task of
+/// another, higher priority executor: the watchdog then resets the device if a task
+/// hogs its executor. Returns whether the watchdog was fed.
+///
+/// ```ignore
+/// #[embassy_executor::task]
+/// async fn watchdog_task(mut wdg: IndependentWatchdog<'static, IWDG>) {
+///     loop {
+///         raw::feed_if_progressed(|| wdg.pet());
+///         Timer::after(Duration::from_millis(500)).await;
+///     }
+/// }
+/// ```
+pub fn feed_if_progressed(feed: impl FnOnce()) -> bool {
+    let progressed = all_tasks_progressed();
+    if progressed {
+        feed();
+    }
+    progressed
+}
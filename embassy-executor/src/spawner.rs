@@ -1,7 +1,8 @@
-use core::future::poll_fn;
+use core::future::{poll_fn, Future};
 use core::marker::PhantomData;
 use core::mem;
-use core::task::Poll;
+use core::pin::Pin;
+use core::task::{Context, Poll};
 
 use super::raw;
 
@@ -39,6 +40,38 @@ impl<S> SpawnToken<S> {
             phantom: PhantomData,
         }
     }
+
+    /// Set the priority the task will run with, overriding the default priority 0.
+    ///
+    /// Tasks of a higher priority are polled before tasks of a lower priority in the same executor.
+    /// See [`raw::Executor`] for details.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `priority` is not lower than [`raw::PRIORITY_LEVELS`].
+    pub fn with_priority(self, priority: u8) -> Self {
+        assert!((priority as usize) < raw::PRIORITY_LEVELS, "task priority out of range");
+        if let Some(task) = self.raw_task {
+            // The task is not enqueued in an executor yet, so nothing else accesses its priority.
+            task.header().priority.set(priority);
+        }
+        self
+    }
+
+    /// Set the name of the task, reported in the task statistics.
+    ///
+    /// Tasks declared with the [`task`](embassy_macros::task) macro are named after their function.
+    /// This does nothing unless the `metrics` feature is enabled, see
+    /// [`raw::Executor::tasks()`](raw::Executor).
+    pub fn with_name(self, name: &'static str) -> Self {
+        #[cfg(feature = "metrics")]
+        if let Some(task) = self.raw_task {
+            critical_section::with(|cs| task.header().metrics.set_name(cs, name));
+        }
+        #[cfg(not(feature = "metrics"))]
+        let _ = name;
+        self
+    }
 }
 
 impl<S> Drop for SpawnToken<S> {
@@ -60,6 +93,79 @@ pub enum SpawnError {
     Busy,
 }
 
+/// Handle to a spawned task, returned by [`Spawner::spawn_with_handle()`].
+///
+/// Awaiting the handle waits until the task has finished, either because its future
+/// completed or because it was aborted. Dropping the handle detaches it from the task,
+/// which keeps running.
+///
+/// The handle stays valid after the task finishes: it won't affect a new task spawned
+/// later in the same task storage.
+///
+/// Awaiting the handle returns `()`, not the output of the task's future: the output is
+/// dropped by the executor when the task finishes, and the task storage may already be
+/// running another task when the handle is polled. Tasks declared with the
+/// [`task`](crate::task) macro return `()` anyway. To get a result out of a task, send it
+/// through a channel or signal, for example from `embassy-sync`.
+pub struct JoinHandle {
+    task: raw::TaskRef,
+    generation: u32,
+}
+
+// The task state is only accessed atomically or in a critical section.
+unsafe impl Send for JoinHandle {}
+unsafe impl Sync for JoinHandle {}
+
+impl JoinHandle {
+    /// `task` must be spawned, and not yet enqueued in an executor.
+    fn new(task: raw::TaskRef) -> Self {
+        Self {
+            task,
+            generation: task.header().generation.get(),
+        }
+    }
+
+    fn is_finished_cs(&self) -> bool {
+        self.task.header().generation.get() != self.generation
+    }
+
+    /// Returns whether the task has finished.
+    ///
+    /// An aborted task counts as finished once its future has been dropped.
+    pub fn is_finished(&self) -> bool {
+        critical_section::with(|_| self.is_finished_cs())
+    }
+
+    /// Abort the task.
+    ///
+    /// The task's future is dropped in place, without being polled again, and its task storage
+    /// is freed so it can be spawned again. The drop does not happen synchronously: it's done
+    /// by the executor, the next time it runs. Await the handle to wait for it.
+    ///
+    /// This does nothing if the task has already finished.
+    pub fn abort(&self) {
+        raw::abort_task(self.task, self.generation)
+    }
+}
+
+impl Future for JoinHandle {
+    type Output = ();
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<()> {
+        let (res, old_waker) = critical_section::with(|_| {
+            if self.is_finished_cs() {
+                (Poll::Ready(()), None)
+            } else {
+                let header = self.task.header();
+                (Poll::Pending, header.join_waker.replace(Some(cx.waker().clone())))
+            }
+        });
+        // Drop the previous waker outside the critical section.
+        drop(old_waker);
+        res
+    }
+}
+
 /// Handle to spawn tasks into an executor.
 ///
 /// This Spawner can spawn any task (Send and non-Send ones), but it can
@@ -87,12 +193,18 @@ impl Spawner {
     ///
     /// # Panics
     ///
-    /// Panics if the current executor is not an Embassy executor.
+    /// Panics if the current executor is not an Embassy executor, or if it is a
+    /// [`ThreadPoolExecutor`](crate::ThreadPoolExecutor), which can only spawn Send tasks.
     pub async fn for_current_executor() -> Self {
         poll_fn(|cx| unsafe {
             let task = raw::task_from_waker(cx.waker());
-            let executor = task.header().executor.get();
-            Poll::Ready(Self::new(&*executor))
+            let executor = &*task.header().executor.get();
+            #[cfg(feature = "std")]
+            assert!(
+                !executor.send_only,
+                "Spawner can't be used with a ThreadPoolExecutor, use SendSpawner instead"
+            );
+            Poll::Ready(Self::new(executor))
         })
         .await
     }
@@ -101,13 +213,21 @@ impl Spawner {
     ///
     /// You obtain the `token` by calling a task function (i.e. one marked with `#[embassy_executor::task]`).
     pub fn spawn<S>(&self, token: SpawnToken<S>) -> Result<(), SpawnError> {
+        self.spawn_with_handle(token).map(|_| ())
+    }
+
+    /// Spawn a task into an executor, returning a [`JoinHandle`] to it.
+    ///
+    /// The handle can be used to wait for the task to finish, or to abort it.
+    pub fn spawn_with_handle<S>(&self, token: SpawnToken<S>) -> Result<JoinHandle, SpawnError> {
         let task = token.raw_task;
         mem::forget(token);
 
         match task {
             Some(task) => {
+                let handle = JoinHandle::new(task);
                 unsafe { self.executor.spawn(task) };
-                Ok(())
+                Ok(handle)
             }
             None => Err(SpawnError::Busy),
         }
@@ -177,13 +297,21 @@ impl SendSpawner {
     ///
     /// You obtain the `token` by calling a task function (i.e. one marked with `#[embassy_executor::task]`).
     pub fn spawn<S: Send>(&self, token: SpawnToken<S>) -> Result<(), SpawnError> {
+        self.spawn_with_handle(token).map(|_| ())
+    }
+
+    /// Spawn a task into an executor, returning a [`JoinHandle`] to it.
+    ///
+    /// See [`Spawner::spawn_with_handle()`].
+    pub fn spawn_with_handle<S: Send>(&self, token: SpawnToken<S>) -> Result<JoinHandle, SpawnError> {
         let header = token.raw_task;
         mem::forget(token);
 
         match header {
             Some(header) => {
+                let handle = JoinHandle::new(header);
                 unsafe { self.executor.spawn(header) };
-                Ok(())
+                Ok(handle)
             }
             None => Err(SpawnError::Busy),
         }
@@ -198,3 +326,131 @@ impl SendSpawner {
         unwrap!(self.spawn(token));
     }
 }
+
+#[cfg(all(test, feature = "test-executor"))]
+mod tests {
+    use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+    use std::sync::Mutex;
+
+    use embassy_time::{Duration, Timer};
+
+    use super::*;
+    use crate::raw::TaskStorage;
+    use crate::test_util::leak;
+    use crate::TestExecutor;
+
+    async fn sleep_then_count(count: &'static AtomicUsize) {
+        Timer::after(Duration::from_millis(10)).await;
+        count.fetch_add(1, Ordering::SeqCst);
+    }
+
+    #[test]
+    fn is_finished_after_completion() {
+        let executor = TestExecutor::new();
+        let count = leak(AtomicUsize::new(0));
+        let storage = leak(TaskStorage::new());
+        let handle = executor
+            .spawner()
+            .spawn_with_handle(storage.spawn(|| sleep_then_count(count)))
+            .unwrap();
+
+        executor.run_until_stalled();
+        assert!(!handle.is_finished());
+        executor.advance_time(Duration::from_millis(10));
+        assert!(handle.is_finished());
+        assert_eq!(count.load(Ordering::SeqCst), 1);
+
+        // The handle of the finished task doesn't affect the next task in the same storage.
+        executor
+            .spawner()
+            .spawn(storage.spawn(|| sleep_then_count(count)))
+            .unwrap();
+        handle.abort();
+        executor.advance_time(Duration::from_millis(10));
+        assert_eq!(count.load(Ordering::SeqCst), 2);
+    }
+
+    #[test]
+    fn abort_frees_storage() {
+        let executor = TestExecutor::new();
+        let count = leak(AtomicUsize::new(0));
+        let storage = leak(TaskStorage::new());
+        let handle = executor
+            .spawner()
+            .spawn_with_handle(storage.spawn(|| sleep_then_count(count)))
+            .unwrap();
+        executor.run_until_stalled();
+
+        handle.abort();
+        executor.run_until_stalled();
+        assert!(handle.is_finished());
+        executor.advance_time(Duration::from_millis(10));
+        assert_eq!(count.load(Ordering::SeqCst), 0);
+
+        let handle = executor
+            .spawner()
+            .spawn_with_handle(storage.spawn(|| sleep_then_count(count)))
+            .unwrap();
+        executor.advance_time(Duration::from_millis(10));
+        assert!(handle.is_finished());
+        assert_eq!(count.load(Ordering::SeqCst), 1);
+    }
+
+    #[test]
+    fn await_aborted_task() {
+        let executor = TestExecutor::new();
+        let count = leak(AtomicUsize::new(0));
+        let storage = leak(TaskStorage::new());
+        let handle = executor
+            .spawner()
+            .spawn_with_handle(storage.spawn(|| sleep_then_count(count)))
+            .unwrap();
+
+        executor.run_until(async move {
+            handle.abort();
+            handle.await;
+        });
+        assert_eq!(count.load(Ordering::SeqCst), 0);
+        executor
+            .spawner()
+            .spawn(storage.spawn(|| sleep_then_count(count)))
+            .unwrap();
+    }
+
+    async fn abort_self(handle: &'static Mutex<Option<JoinHandle>>, after_abort: &'static AtomicBool) {
+        Timer::after(Duration::from_millis(10)).await;
+        handle.lock().unwrap().as_ref().unwrap().abort();
+        // The task keeps running until it yields.
+        Timer::after(Duration::from_millis(10)).await;
+        after_abort.store(true, Ordering::SeqCst);
+    }
+
+    #[test]
+    fn self_abort() {
+        let executor = TestExecutor::new();
+        let handle = leak(Mutex::new(None));
+        let after_abort = leak(AtomicBool::new(false));
+        let storage = leak(TaskStorage::new());
+        *handle.lock().unwrap() = Some(
+            executor
+                .spawner()
+                .spawn_with_handle(storage.spawn(|| abort_self(handle, after_abort)))
+                .unwrap(),
+        );
+
+        executor.advance_time(Duration::from_millis(10));
+        assert!(handle.lock().unwrap().as_ref().unwrap().is_finished());
+        executor.advance_time(Duration::from_millis(10));
+        assert!(!after_abort.load(Ordering::SeqCst));
+
+        // The storage can be spawned again.
+        let handle2 = executor
+            .spawner()
+            .spawn_with_handle(storage.spawn(|| abort_self(handle, after_abort)))
+            .unwrap();
+        *handle.lock().unwrap() = Some(handle2);
+        executor.advance_time(Duration::from_millis(20));
+        assert!(handle.lock().unwrap().as_ref().unwrap().is_finished());
+        assert!(!after_abort.load(Ordering::SeqCst));
+    }
+}
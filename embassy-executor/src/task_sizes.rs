@@ -0,0 +1,80 @@
+use core::ptr;
+
+/// Memory used by a task declared with the [`embassy_executor::task`](embassy_macros::task) macro.
+///
+/// Sizes are those of the target the program is built for, so they may differ between the
+/// host and the device. Use the macro's `max_size` parameter to check them on the device at
+/// compile time.
+#[derive(Debug)]
+#[repr(C)]
+pub struct TaskSize {
+    /// Name of the task function.
+    pub name: &'static str,
+    /// Size of the task's future, in bytes.
+    pub future_size: usize,
+    /// Number of tasks in the task's pool, set with the macro's `pool_size` parameter.
+    pub pool_size: usize,
+    /// Size of the task's pool, in bytes. This is the static memory used by the task.
+    pub total_size: usize,
+}
+
+// Records are placed in this section by `task_size_record!`. The linker defines the
+// `__start_` and `__stop_` symbols around it.
+extern "Rust" {
+    #[link_name = "__start_embassy_task_sizes"]
+    static START: TaskSize;
+    #[link_name = "__stop_embassy_task_sizes"]
+    static STOP: TaskSize;
+}
+
+/// Ensures the section exists, so the program links even if it declares no task.
+#[used]
+#[link_section = "embassy_task_sizes"]
+static SENTINEL: TaskSize = TaskSize {
+    name: "",
+    future_size: 0,
+    pool_size: 0,
+    total_size: 0,
+};
+
+/// Iterate over all the tasks of the program, declared with the
+/// [`embassy_executor::task`](embassy_macros::task) macro, and their sizes.
+///
+/// This allows checking the RAM budget of tasks from a host build, for example in a test.
+/// Tasks are listed in no particular order, whether they have been spawned or not.
+///
+/// This relies on the linker defining symbols around the section holding the sizes,
+/// which the GNU and LLVM linkers do for ELF targets, such as Linux.
+pub fn task_sizes() -> impl Iterator<Item = &'static TaskSize> {
+    let (start, stop) = unsafe { (&START as *const TaskSize, &STOP as *const TaskSize) };
+    let len = (stop as usize - start as usize) / core::mem::size_of::<TaskSize>();
+    let all = unsafe { core::slice::from_raw_parts(start, len) };
+    all.iter().filter(|task| !ptr::eq(*task, &SENTINEL))
+}
+
+/// Print a table of all the tasks of the program and their sizes, largest first.
+///
+/// See [`task_sizes()`].
+pub fn print_task_sizes() {
+    let mut tasks: Vec<_> = task_sizes().collect();
+    tasks.sort_by(|a, b| b.total_size.cmp(&a.total_size).then(a.name.cmp(b.name)));
+
+    let width = tasks.iter().map(|t| t.name.len()).max().unwrap_or(0).max(4);
+    println!(
+        "{:width$}  {:>11}  {:>4}  {:>10}",
+        "task", "future size", "pool", "total size"
+    );
+    for t in &tasks {
+        println!(
+            "{:width$}  {:>11}  {:>4}  {:>10}",
+            t.name, t.future_size, t.pool_size, t.total_size
+        );
+    }
+    println!(
+        "{:width$}  {:>11}  {:>4}  {:>10}",
+        "",
+        "",
+        "",
+        tasks.iter().map(|t| t.total_size).sum::<usize>()
+    );
+}
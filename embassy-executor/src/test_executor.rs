@@ -0,0 +1,281 @@
+use core::future::Future;
+use core::marker::PhantomData;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Mutex, MutexGuard};
+
+use embassy_time::{Duration, Instant, MockDriver};
+
+use super::{raw, Spawner};
+
+/// Only one `TestExecutor` may exist at a time, since they all share the global `MockDriver`.
+static LOCK: Mutex<()> = Mutex::new(());
+
+/// Wait until no one else uses the global `MockDriver`, and take it until the guard is dropped.
+pub(crate) fn lock_mock_driver() -> MutexGuard<'static, ()> {
+    // A test panicking with the lock held poisons it. That doesn't matter to other tests.
+    LOCK.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+/// Task polling the future passed to [`TestExecutor::run_until()`].
+static RUN_UNTIL: raw::RunUntil = raw::RunUntil::new();
+
+/// Deterministic executor for tests, with virtual time.
+///
+/// Unlike [`Executor`](crate::Executor), this executor does not run by itself: the test steps
+/// it with [`run_until_stalled()`](TestExecutor::run_until_stalled),
+/// [`run_until()`](TestExecutor::run_until) and [`advance_time()`](TestExecutor::advance_time).
+/// Tasks run on the test's thread, in a well-defined order, so runs are reproducible.
+///
+/// Time is virtual: it is provided by embassy-time's [`MockDriver`], and only moves forward when
+/// the test advances it. Creating a `TestExecutor` resets the time to zero.
+///
+/// All `TestExecutor`s share the global time driver, so only one of them exists at a time:
+/// creating one waits until the previous one is dropped. This makes tests using it run one
+/// after the other, even when the test harness runs tests in parallel.
+///
+/// When the executor is dropped, the tasks still running are aborted, so that they can be spawned
+/// again by the next test.
+///
+/// ```ignore
+/// #[embassy_executor::task]
+/// async fn blink(led: &'static Led) {
+///     loop {
+///         led.toggle();
+///         Timer::after(Duration::from_millis(500)).await;
+///     }
+/// }
+///
+/// #[test]
+/// fn blinks() {
+///     let executor = TestExecutor::new();
+///     executor.spawner().spawn(blink(&LED)).unwrap();
+///     executor.run_until_stalled();
+///     assert!(LED.is_on());
+///
+///     executor.advance_time(Duration::from_millis(500));
+///     assert!(!LED.is_on());
+/// }
+/// ```
+pub struct TestExecutor {
+    inner: &'static raw::Executor,
+    signaled: &'static AtomicBool,
+    _lock: MutexGuard<'static, ()>,
+    not_send: PhantomData<*mut ()>,
+}
+
+impl TestExecutor {
+    /// Create a new TestExecutor, and reset the virtual time to zero.
+    ///
+    /// This waits until any other `TestExecutor` has been dropped.
+    pub fn new() -> Self {
+        let lock = lock_mock_driver();
+        MockDriver::get().reset();
+
+        let signaled = &*Box::leak(Box::new(AtomicBool::new(false)));
+        let inner = &*Box::leak(Box::new(raw::Executor::new(
+            |p| unsafe {
+                let s = &*(p as *const () as *const AtomicBool);
+                s.store(true, Ordering::SeqCst)
+            },
+            signaled as *const _ as _,
+        )));
+
+        Self {
+            inner,
+            signaled,
+            _lock: lock,
+            not_send: PhantomData,
+        }
+    }
+
+    /// Get a spawner that spawns tasks in this executor.
+    ///
+    /// Spawned tasks don't run until the executor is stepped.
+    pub fn spawner(&self) -> Spawner {
+        self.inner.spawner()
+    }
+
+    /// Poll tasks until none of them is ready to make progress.
+    ///
+    /// When this returns, all tasks are waiting for something, such as a timer or an event
+    /// from the test. Virtual time does not move.
+    ///
+    /// This never returns if a task keeps waking itself.
+    pub fn run_until_stalled(&self) {
+        while self.signaled.swap(false, Ordering::SeqCst) {
+            unsafe { self.inner.poll() };
+        }
+    }
+
+    /// Advance the virtual time by `duration`, running the tasks that are woken by timers.
+    ///
+    /// Time moves forward one timer expiration at a time, and the tasks are run until stalled
+    /// at each step. A task waiting for 1 ms in a loop therefore runs once per millisecond,
+    /// just like it would on real hardware.
+    pub fn advance_time(&self, duration: Duration) {
+        let driver = MockDriver::get();
+        let target = Instant::now() + duration;
+
+        loop {
+            self.run_until_stalled();
+            let next_expiration = self.inner.process_timers();
+            if next_expiration > target.as_ticks() {
+                break;
+            }
+            driver.set_now(Instant::from_ticks(next_expiration));
+        }
+
+        driver.set_now(target);
+        self.run_until_stalled();
+    }
+
+    /// Run the executor until `fut` completes, and return its output.
+    ///
+    /// `fut` is polled from the executor, like a task, so it can use timers and call
+    /// [`Spawner::for_current_executor()`]. The spawned tasks are run at the same time.
+    ///
+    /// Whenever all tasks and `fut` are waiting, the virtual time jumps forward to the
+    /// next timer expiration.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `fut` can never complete: all tasks and `fut` are waiting, and no timer
+    /// is pending.
+    pub fn run_until<F: Future>(&self, fut: F) -> F::Output {
+        let driver = MockDriver::get();
+        RUN_UNTIL.run(self.inner, fut, || {
+            if self.signaled.swap(false, Ordering::SeqCst) {
+                return;
+            }
+
+            let next_expiration = self.inner.process_timers();
+            if next_expiration == u64::MAX {
+                panic!("run_until: the future can't complete, all tasks are stalled and no timer is pending");
+            }
+            driver.set_now(Instant::from_ticks(next_expiration));
+        })
+    }
+
+    /// Iterate over the tasks that are spawned and not finished yet, with their statistics.
+    ///
+    /// See [`raw::Executor::tasks()`] for details.
+    pub fn pending_tasks(&self) -> raw::Tasks {
+        self.inner.tasks()
+    }
+
+    /// Returns whether no task has been woken since the executor last ran.
+    pub fn is_stalled(&self) -> bool {
+        !self.signaled.load(Ordering::SeqCst)
+    }
+}
+
+impl Drop for TestExecutor {
+    fn drop(&mut self) {
+        for task in self.inner.tasks() {
+            let generation = critical_section::with(|_| task.task.header().generation.get());
+            raw::abort_task(task.task, generation);
+        }
+        self.run_until_stalled();
+    }
+}
+
+#[cfg(all(test, feature = "test-executor"))]
+mod tests {
+    use core::future::pending;
+    use std::sync::atomic::AtomicUsize;
+
+    use embassy_time::Timer;
+
+    use super::*;
+    use crate::raw::TaskStorage;
+    use crate::test_util::leak;
+
+    async fn ticker(ticks: &'static AtomicUsize) {
+        loop {
+            ticks.fetch_add(1, Ordering::SeqCst);
+            Timer::after(Duration::from_millis(10)).await;
+        }
+    }
+
+    #[test]
+    fn advance_time_runs_timers_in_order() {
+        let executor = TestExecutor::new();
+        let ticks = leak(AtomicUsize::new(0));
+        let storage = leak(TaskStorage::new());
+        executor.spawner().spawn(storage.spawn(|| ticker(ticks))).unwrap();
+
+        assert!(!executor.is_stalled());
+        executor.run_until_stalled();
+        assert!(executor.is_stalled());
+        assert_eq!(ticks.load(Ordering::SeqCst), 1);
+        assert_eq!(Instant::now(), Instant::from_ticks(0));
+
+        executor.advance_time(Duration::from_millis(9));
+        assert_eq!(ticks.load(Ordering::SeqCst), 1);
+
+        executor.advance_time(Duration::from_millis(26));
+        assert_eq!(ticks.load(Ordering::SeqCst), 4);
+        assert_eq!(Instant::now(), Instant::from_millis(35));
+    }
+
+    #[test]
+    fn run_until_jumps_to_next_timer() {
+        let executor = TestExecutor::new();
+        let ticks = leak(AtomicUsize::new(0));
+        let storage = leak(TaskStorage::new());
+        executor.spawner().spawn(storage.spawn(|| ticker(ticks))).unwrap();
+
+        let output = executor.run_until(async {
+            Timer::after(Duration::from_millis(25)).await;
+            42
+        });
+
+        assert_eq!(output, 42);
+        assert_eq!(Instant::now(), Instant::from_millis(25));
+        assert_eq!(ticks.load(Ordering::SeqCst), 3);
+    }
+
+    #[test]
+    #[should_panic(expected = "all tasks are stalled")]
+    fn run_until_panics_when_stalled() {
+        let executor = TestExecutor::new();
+        executor.run_until(pending::<()>());
+    }
+
+    #[test]
+    fn abort_and_respawn() {
+        let ticks = leak(AtomicUsize::new(0));
+        let storage = leak(TaskStorage::new());
+
+        let executor = TestExecutor::new();
+        let handle = executor
+            .spawner()
+            .spawn_with_handle(storage.spawn(|| ticker(ticks)))
+            .unwrap();
+        executor.run_until_stalled();
+        assert!(!handle.is_finished());
+        assert!(executor.spawner().spawn(storage.spawn(|| ticker(ticks))).is_err());
+
+        // The future is dropped when the executor runs next.
+        handle.abort();
+        assert!(!handle.is_finished());
+        executor.run_until_stalled();
+        assert!(handle.is_finished());
+        assert_eq!(executor.pending_tasks().count(), 0);
+
+        // An aborted task doesn't run anymore, and its storage can be reused.
+        executor.advance_time(Duration::from_millis(100));
+        assert_eq!(ticks.load(Ordering::SeqCst), 1);
+        executor.spawner().spawn(storage.spawn(|| ticker(ticks))).unwrap();
+        executor.run_until_stalled();
+        assert_eq!(ticks.load(Ordering::SeqCst), 2);
+        assert_eq!(executor.pending_tasks().count(), 1);
+
+        // Dropping the executor aborts its tasks, so the next one can spawn them again.
+        drop(executor);
+        let executor = TestExecutor::new();
+        executor.spawner().spawn(storage.spawn(|| ticker(ticks))).unwrap();
+        executor.run_until_stalled();
+        assert_eq!(ticks.load(Ordering::SeqCst), 3);
+    }
+}
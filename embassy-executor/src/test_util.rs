@@ -0,0 +1,22 @@
+//! Helpers shared by the unit tests.
+
+use std::sync::MutexGuard;
+
+use embassy_time::MockDriver;
+
+use crate::test_executor::lock_mock_driver;
+
+/// Leak `value`, to get the `'static` references that task storage and task arguments need.
+pub(crate) fn leak<T>(value: T) -> &'static T {
+    Box::leak(Box::new(value))
+}
+
+/// Take the global [`MockDriver`] for a test that doesn't use a [`TestExecutor`](crate::TestExecutor),
+/// and reset the time to zero.
+///
+/// The driver is released when the returned guard is dropped.
+pub(crate) fn take_mock_driver() -> MutexGuard<'static, ()> {
+    let lock = lock_mock_driver();
+    MockDriver::get().reset();
+    lock
+}
@@ -8,7 +8,9 @@ mod util;
 use macros::*;
 
 /// Declares an async task that can be run by `embassy-executor`. The optional `pool_size` parameter can be used to specify how
-/// many concurrent tasks can be spawned (default is 1) for the function.
+/// many concurrent tasks can be spawned (default is 1) for the function. The optional `priority` parameter sets the priority
+/// the task runs with in its executor (default is 0), see `embassy_executor::SpawnToken::with_priority`. The optional
+/// `max_size` parameter makes compilation fail if the task's future is larger than the given number of bytes.
 ///
 ///
 /// The following restrictions apply:
@@ -16,6 +18,8 @@ use macros::*;
 /// * The function must be declared `async`.
 /// * The function must not use generics.
 /// * The optional `pool_size` attribute must be 1 or greater.
+/// * The optional `priority` attribute must be lower than `embassy_executor::raw::PRIORITY_LEVELS`.
+/// * The task's future must not be larger than the optional `max_size` attribute.
 ///
 ///
 /// ## Examples
@@ -37,6 +41,24 @@ use macros::*;
 ///     // Function body
 /// }
 /// ```
+///
+/// Declaring a task with a higher priority:
+///
+/// ``` rust
+/// #[embassy_executor::task(priority = 2)]
+/// async fn mytask() {
+///     // Function body
+/// }
+/// ```
+///
+/// Declaring a task whose future must fit in 1 KiB:
+///
+/// ``` rust
+/// #[embassy_executor::task(max_size = 1024)]
+/// async fn mytask() {
+///     // Function body
+/// }
+/// ```
 #[proc_macro_attribute]
 pub fn task(args: TokenStream, item: TokenStream) -> TokenStream {
     let args = syn::parse_macro_input!(args as syn::AttributeArgs);
@@ -9,6 +9,10 @@ use crate::util::ctxt::Ctxt;
 struct Args {
     #[darling(default)]
     pool_size: Option<usize>,
+    #[darling(default)]
+    priority: Option<u8>,
+    #[darling(default)]
+    max_size: Option<usize>,
 }
 
 pub fn run(args: syn::AttributeArgs, f: syn::ItemFn) -> Result<TokenStream, TokenStream> {
@@ -58,11 +62,44 @@ pub fn run(args: syn::AttributeArgs, f: syn::ItemFn) -> Result<TokenStream, Toke
     task_inner.vis = syn::Visibility::Inherited;
     task_inner.sig.ident = task_inner_ident.clone();
 
+    let task_name = task_ident.to_string();
+    let spawn = quote! {
+        (unsafe { POOL._spawn_async_fn(move || #task_inner_ident(#(#arg_names,)*)) }).with_name(#task_name)
+    };
+    let spawn = match args.priority {
+        Some(priority) => quote! {
+            const _: () = ::core::assert!(
+                (#priority as usize) < ::embassy_executor::raw::PRIORITY_LEVELS,
+                "task priority out of range"
+            );
+            #spawn.with_priority(#priority)
+        },
+        None => spawn,
+    };
+
+    let size_check = match args.max_size {
+        Some(max_size) => {
+            let message = format!(
+                "the future of task `{}` is larger than max_size = {}",
+                task_name, max_size
+            );
+            quote! {
+                const _: () = ::core::assert!(
+                    ::embassy_executor::raw::TaskPool::<Fut, #pool_size>::FUTURE_SIZE <= #max_size,
+                    #message
+                );
+            }
+        }
+        None => quote! {},
+    };
+
     let mut task_outer: ItemFn = parse_quote! {
         #visibility fn #task_ident(#fargs) -> ::embassy_executor::SpawnToken<impl Sized> {
             type Fut = impl ::core::future::Future + 'static;
             static POOL: ::embassy_executor::raw::TaskPool<Fut, #pool_size> = ::embassy_executor::raw::TaskPool::new();
-            unsafe { POOL._spawn_async_fn(move || #task_inner_ident(#(#arg_names,)*)) }
+            #size_check
+            ::embassy_executor::task_size_record!(#task_name, Fut, #pool_size);
+            #spawn
         }
     };
 
@@ -0,0 +1,160 @@
+//! DNS client, see [`Stack::dns_query()`].
+use embassy_net_driver::Driver;
+use embassy_time::{Duration, Instant};
+use heapless::{String, Vec};
+pub(crate) use smoltcp::socket::dns::{DnsQuery, GetQueryResultError, Socket, StartQueryError};
+pub use smoltcp::wire::DnsQueryType;
+
+use crate::{IpAddress, Stack};
+
+/// Maximum number of addresses returned by a query.
+///
+/// smoltcp itself keeps at most `DNS_MAX_RESULT_COUNT` addresses per query, one by default.
+/// It can be raised with the `SMOLTCP_DNS_MAX_RESULT_COUNT` environment variable at build time.
+pub const MAX_ADDRESSES: usize = 4;
+
+/// Time after which a query fails with [`Error::TimedOut`], if no server answered.
+pub const QUERY_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Number of query results kept in the cache.
+const CACHE_SIZE: usize = 4;
+/// Longest name whose query results are cached.
+const CACHE_NAME_LEN: usize = 64;
+/// Time query results are cached for. smoltcp doesn't report the TTL of the records.
+const CACHE_TTL: Duration = Duration::from_secs(60);
+
+/// Errors returned by DNS queries.
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Error {
+    /// The name is not a valid DNS name.
+    InvalidName,
+    /// The name is too long.
+    NameTooLong,
+    /// The name could not be resolved: no server is configured, the name doesn't exist,
+    /// or it has no address of the requested type.
+    Failed,
+    /// No server answered in time, see [`QUERY_TIMEOUT`].
+    TimedOut,
+}
+
+impl From<StartQueryError> for Error {
+    fn from(e: StartQueryError) -> Self {
+        match e {
+            StartQueryError::NoFreeSlot => Self::Failed,
+            StartQueryError::InvalidName => Self::InvalidName,
+            StartQueryError::NameTooLong => Self::NameTooLong,
+        }
+    }
+}
+
+impl From<GetQueryResultError> for Error {
+    fn from(_: GetQueryResultError) -> Self {
+        Self::Failed
+    }
+}
+
+/// Cache of recent query results, cleared when the IP configuration changes.
+pub(crate) struct DnsCache {
+    entries: [Option<CacheEntry>; CACHE_SIZE],
+}
+
+struct CacheEntry {
+    name: String<CACHE_NAME_LEN>,
+    qtype: DnsQueryType,
+    addrs: Vec<IpAddress, MAX_ADDRESSES>,
+    expires_at: Instant,
+}
+
+impl DnsCache {
+    const EMPTY: Option<CacheEntry> = None;
+
+    pub(crate) const fn new() -> Self {
+        Self {
+            entries: [Self::EMPTY; CACHE_SIZE],
+        }
+    }
+
+    pub(crate) fn get(&self, name: &str, qtype: DnsQueryType) -> Option<Vec<IpAddress, MAX_ADDRESSES>> {
+        let now = Instant::now();
+        self.entries
+            .iter()
+            .flatten()
+            .find(|e| e.qtype == qtype && e.expires_at > now && e.name.eq_ignore_ascii_case(name))
+            .map(|e| e.addrs.clone())
+    }
+
+    pub(crate) fn insert(&mut self, name: &str, qtype: DnsQueryType, addrs: &Vec<IpAddress, MAX_ADDRESSES>) {
+        let mut cached_name = String::new();
+        if cached_name.push_str(name).is_err() {
+            // Too long to be cached.
+            return;
+        }
+
+        // Replace a free entry if any, else the one expiring first.
+        let slot = self
+            .entries
+            .iter_mut()
+            .min_by_key(|e| e.as_ref().map(|e| e.expires_at))
+            .unwrap();
+        *slot = Some(CacheEntry {
+            name: cached_name,
+            qtype,
+            addrs: addrs.clone(),
+            expires_at: Instant::now() + CACHE_TTL,
+        });
+    }
+
+    pub(crate) fn clear(&mut self) {
+        self.entries = [Self::EMPTY; CACHE_SIZE];
+    }
+}
+
+/// DNS client, for use through the [`embedded_nal_async::Dns`] trait.
+///
+/// This is a thin wrapper around [`Stack::dns_query()`]: all `DnsSocket`s of a stack
+/// share its DNS socket and cache.
+pub struct DnsSocket<'a, D: Driver + 'static> {
+    stack: &'a Stack<D>,
+}
+
+impl<'a, D: Driver + 'static> DnsSocket<'a, D> {
+    /// Create a new DNS client using the given stack.
+    pub fn new(stack: &'a Stack<D>) -> Self {
+        Self { stack }
+    }
+
+    /// Resolve a name, see [`Stack::dns_query()`].
+    pub async fn query(&self, name: &str, qtype: DnsQueryType) -> Result<Vec<IpAddress, MAX_ADDRESSES>, Error> {
+        self.stack.dns_query(name, qtype).await
+    }
+}
+
+#[cfg(feature = "nightly")]
+impl<'a, D: Driver + 'static> embedded_nal_async::Dns for DnsSocket<'a, D> {
+    type Error = Error;
+
+    async fn get_host_by_name(
+        &self,
+        host: &str,
+        addr_type: embedded_nal_async::AddrType,
+    ) -> Result<embedded_nal_async::IpAddr, Self::Error> {
+        use embedded_nal_async::{AddrType, IpAddr};
+
+        let qtype = match addr_type {
+            AddrType::IPv6 => DnsQueryType::Aaaa,
+            AddrType::IPv4 | AddrType::Either => DnsQueryType::A,
+        };
+        let addrs = self.query(host, qtype).await?;
+        match addrs.first().ok_or(Error::Failed)? {
+            IpAddress::Ipv4(addr) => Ok(IpAddr::V4(addr.0.into())),
+            #[cfg(feature = "proto-ipv6")]
+            IpAddress::Ipv6(addr) => Ok(IpAddr::V6(addr.0.into())),
+        }
+    }
+
+    async fn get_host_by_address(&self, _addr: embedded_nal_async::IpAddr) -> Result<String<256>, Self::Error> {
+        // smoltcp doesn't support reverse lookups.
+        Err(Error::Failed)
+    }
+}
@@ -0,0 +1,149 @@
+use core::cell::RefCell;
+use core::future::poll_fn;
+use core::mem;
+use core::task::Poll;
+
+use embassy_net_driver::Driver;
+use smoltcp::iface::{Interface, SocketHandle};
+use smoltcp::socket::icmp;
+pub use smoltcp::socket::icmp::{Endpoint, PacketMetadata};
+use smoltcp::wire::IpAddress;
+
+use crate::{SocketStack, Stack};
+
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum BindError {
+    /// The socket was already open.
+    InvalidState,
+    /// The endpoint is unspecified, or has an unspecified port.
+    Unaddressable,
+}
+
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Error {
+    /// No route to host.
+    NoRoute,
+    /// The message is too large for the transmit buffer.
+    Truncated,
+}
+
+/// ICMP socket, to send and receive ICMP messages such as echo requests.
+///
+/// The socket must be bound before use, see [`IcmpSocket::bind()`]. Messages are sent and
+/// received without their IP header.
+pub struct IcmpSocket<'a> {
+    stack: &'a RefCell<SocketStack>,
+    handle: SocketHandle,
+}
+
+impl<'a> IcmpSocket<'a> {
+    pub fn new<D: Driver>(
+        stack: &'a Stack<D>,
+        rx_meta: &'a mut [PacketMetadata],
+        rx_buffer: &'a mut [u8],
+        tx_meta: &'a mut [PacketMetadata],
+        tx_buffer: &'a mut [u8],
+    ) -> Self {
+        let s = &mut *stack.socket.borrow_mut();
+
+        let rx_meta: &'static mut [PacketMetadata] = unsafe { mem::transmute(rx_meta) };
+        let rx_buffer: &'static mut [u8] = unsafe { mem::transmute(rx_buffer) };
+        let tx_meta: &'static mut [PacketMetadata] = unsafe { mem::transmute(tx_meta) };
+        let tx_buffer: &'static mut [u8] = unsafe { mem::transmute(tx_buffer) };
+        let handle = s.sockets.add(icmp::Socket::new(
+            icmp::PacketBuffer::new(rx_meta, rx_buffer),
+            icmp::PacketBuffer::new(tx_meta, tx_buffer),
+        ));
+
+        Self {
+            stack: &stack.socket,
+            handle,
+        }
+    }
+
+    /// Bind the socket to an endpoint.
+    ///
+    /// Bind to [`Endpoint::Ident`] to receive the echo replies with that identifier, or to
+    /// [`Endpoint::Udp`] to receive the ICMP errors caused by packets sent from a UDP port.
+    pub fn bind(&mut self, endpoint: Endpoint) -> Result<(), BindError> {
+        match self.with_mut(|s, _| s.bind(endpoint)) {
+            Ok(()) => Ok(()),
+            Err(icmp::BindError::InvalidState) => Err(BindError::InvalidState),
+            Err(icmp::BindError::Unaddressable) => Err(BindError::Unaddressable),
+        }
+    }
+
+    fn with<R>(&self, f: impl FnOnce(&icmp::Socket, &Interface) -> R) -> R {
+        let s = &*self.stack.borrow();
+        let socket = s.sockets.get::<icmp::Socket>(self.handle);
+        f(socket, &s.iface)
+    }
+
+    fn with_mut<R>(&self, f: impl FnOnce(&mut icmp::Socket, &mut Interface) -> R) -> R {
+        let s = &mut *self.stack.borrow_mut();
+        let socket = s.sockets.get_mut::<icmp::Socket>(self.handle);
+        let res = f(socket, &mut s.iface);
+        s.waker.wake();
+        res
+    }
+
+    /// Receive an ICMP message, returning its length and the address of its sender.
+    pub async fn recv_from(&self, buf: &mut [u8]) -> Result<(usize, IpAddress), Error> {
+        poll_fn(move |cx| {
+            self.with_mut(|s, _| match s.recv_slice(buf) {
+                Ok(x) => Poll::Ready(Ok(x)),
+                // No data ready
+                Err(icmp::RecvError::Exhausted) => {
+                    s.register_recv_waker(cx.waker());
+                    Poll::Pending
+                }
+            })
+        })
+        .await
+    }
+
+    /// Send an ICMP message to `remote`. `buf` holds the whole ICMP message, including its header.
+    ///
+    /// Returns [`Error::Truncated`] if the message can never fit in the transmit buffer.
+    pub async fn send_to<T>(&self, buf: &[u8], remote: T) -> Result<(), Error>
+    where
+        T: Into<IpAddress>,
+    {
+        let remote = remote.into();
+        poll_fn(move |cx| {
+            self.with_mut(|s, _| match s.send_slice(buf, remote) {
+                // Entire message has been sent
+                Ok(()) => Poll::Ready(Ok(())),
+                Err(icmp::SendError::BufferFull) if buf.len() > s.payload_send_capacity() => {
+                    Poll::Ready(Err(Error::Truncated))
+                }
+                Err(icmp::SendError::BufferFull) => {
+                    s.register_send_waker(cx.waker());
+                    Poll::Pending
+                }
+                Err(icmp::SendError::Unaddressable) => Poll::Ready(Err(Error::NoRoute)),
+            })
+        })
+        .await
+    }
+
+    pub fn is_open(&self) -> bool {
+        self.with(|s, _| s.is_open())
+    }
+
+    pub fn may_send(&self) -> bool {
+        self.with(|s, _| s.can_send())
+    }
+
+    pub fn may_recv(&self) -> bool {
+        self.with(|s, _| s.can_recv())
+    }
+}
+
+impl Drop for IcmpSocket<'_> {
+    fn drop(&mut self) {
+        self.stack.borrow_mut().sockets.remove(self.handle);
+    }
+}
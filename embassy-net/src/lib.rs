@@ -9,6 +9,14 @@
 pub(crate) mod fmt;
 
 pub mod device;
+#[cfg(feature = "dns")]
+pub mod dns;
+#[cfg(feature = "icmp")]
+pub mod icmp;
+#[cfg(feature = "raw")]
+pub mod raw;
+#[cfg(feature = "slaac")]
+mod slaac;
 #[cfg(feature = "tcp")]
 pub mod tcp;
 #[cfg(feature = "udp")]
@@ -16,19 +24,21 @@ pub mod udp;
 
 use core::cell::RefCell;
 use core::future::{poll_fn, Future};
-use core::task::{Context, Poll};
+use core::task::{Context, Poll, Waker};
 
 use embassy_net_driver::{Driver, LinkState, Medium};
-use embassy_sync::waitqueue::WakerRegistration;
+use embassy_sync::waitqueue::{MultiWakerRegistration, WakerRegistration};
 use embassy_time::{Instant, Timer};
 use futures::pin_mut;
 use heapless::Vec;
-#[cfg(feature = "dhcpv4")]
+#[cfg(any(feature = "dhcpv4", feature = "dns", feature = "slaac"))]
 use smoltcp::iface::SocketHandle;
 use smoltcp::iface::{Interface, SocketSet, SocketStorage};
 #[cfg(feature = "dhcpv4")]
 use smoltcp::socket::dhcpv4;
 use smoltcp::socket::dhcpv4::RetryConfig;
+#[cfg(feature = "slaac")]
+use smoltcp::socket::raw;
 use smoltcp::time::Duration;
 // smoltcp reexports
 pub use smoltcp::time::{Duration as SmolDuration, Instant as SmolInstant};
@@ -44,24 +54,58 @@ use crate::device::DriverAdapter;
 
 const LOCAL_PORT_MIN: u16 = 1025;
 const LOCAL_PORT_MAX: u16 = 65535;
+/// Number of tasks that can wait for the link or the configuration without being woken spuriously.
+const STATE_WAITERS: usize = 4;
+/// Number of tasks that can wait for a DNS query slot without being woken spuriously.
+#[cfg(feature = "dns")]
+const DNS_WAITERS: usize = 4;
 
 pub struct StackResources<const SOCK: usize> {
     sockets: [SocketStorage<'static>; SOCK],
+    #[cfg(feature = "dns")]
+    queries: [Option<dns::DnsQuery>; 1],
+    #[cfg(feature = "slaac")]
+    slaac: slaac::SlaacResources,
 }
 
 impl<const SOCK: usize> StackResources<SOCK> {
+    #[cfg(feature = "dns")]
+    const NO_QUERY: Option<dns::DnsQuery> = None;
+
     pub fn new() -> Self {
         Self {
             sockets: [SocketStorage::EMPTY; SOCK],
+            #[cfg(feature = "dns")]
+            queries: [Self::NO_QUERY; 1],
+            #[cfg(feature = "slaac")]
+            slaac: slaac::SlaacResources::new(),
         }
     }
 }
 
+/// IP configuration of the stack.
+///
+/// With the `proto-ipv6` feature, IPv6 addresses can be configured alongside the IPv4 address.
+/// On Ethernet, the stack also has a link-local IPv6 address derived from the
+/// [Ethernet address](Stack::ethernet_address), and with the `slaac` feature, an address
+/// autoconfigured from router advertisements.
+///
+/// smoltcp supports 2 addresses per interface by default, which only fits the IPv4 address
+/// and one IPv6 address: addresses beyond that are dropped with a warning. Raise this limit
+/// with the `SMOLTCP_IFACE_MAX_ADDR_COUNT` environment variable at build time.
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub struct StaticConfig {
     pub address: Ipv4Cidr,
     pub gateway: Option<Ipv4Address>,
     pub dns_servers: Vec<Ipv4Address, 3>,
+    #[cfg(feature = "proto-ipv6")]
+    pub ipv6_addresses: Vec<Ipv6Cidr, 2>,
+    /// IPv6 default gateway. Takes precedence over the router learned with SLAAC.
+    #[cfg(feature = "proto-ipv6")]
+    pub ipv6_gateway: Option<Ipv6Address>,
+    /// IPv6 DNS servers, queried after the IPv4 ones.
+    #[cfg(feature = "proto-ipv6")]
+    pub ipv6_dns_servers: Vec<Ipv6Address, 3>,
 }
 
 #[derive(Debug, Clone, PartialEq, Eq)]
@@ -88,6 +132,29 @@ impl Default for DhcpConfig {
     }
 }
 
+/// Error returned by [`Stack::ping()`].
+#[cfg(feature = "icmp")]
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum PingError {
+    /// No route to host.
+    NoRoute,
+    /// No reply was received in time.
+    TimedOut,
+    /// The ICMP socket could not be bound.
+    InvalidState,
+    /// No socket slot is free for the ICMP socket.
+    NoFreeSocket,
+}
+
+/// Error returned by [`Stack::set_config()`].
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum ConfigError {
+    /// No socket slot is free for the DHCP client.
+    NoFreeSocket,
+}
+
 pub enum Config {
     Static(StaticConfig),
     #[cfg(feature = "dhcpv4")]
@@ -103,12 +170,26 @@ struct Inner<D: Driver> {
     device: D,
     link_up: bool,
     config: Option<StaticConfig>,
+    /// Woken when the link or the IP configuration goes up or down.
+    state_wakers: MultiWakerRegistration<STATE_WAITERS>,
     #[cfg(feature = "dhcpv4")]
     dhcp_socket: Option<SocketHandle>,
+    #[cfg(feature = "dns")]
+    dns_socket: SocketHandle,
+    /// Woken when a DNS query slot is freed.
+    #[cfg(feature = "dns")]
+    dns_wakers: MultiWakerRegistration<DNS_WAITERS>,
+    #[cfg(feature = "dns")]
+    dns_cache: dns::DnsCache,
+    /// SLAAC client, on Ethernet only.
+    #[cfg(feature = "slaac")]
+    slaac: Option<slaac::Slaac>,
 }
 
 pub(crate) struct SocketStack {
     pub(crate) sockets: SocketSet<'static>,
+    /// Number of socket slots in `sockets`.
+    slots: usize,
     pub(crate) iface: Interface,
     pub(crate) waker: WakerRegistration,
     next_local_port: u16,
@@ -139,7 +220,20 @@ impl<D: Driver + 'static> Stack<D> {
             },
         );
 
-        let sockets = SocketSet::new(&mut resources.sockets[..]);
+        let mut sockets = SocketSet::new(&mut resources.sockets[..]);
+        // The DNS socket takes one of the socket slots.
+        #[cfg(feature = "dns")]
+        let dns_socket = sockets.add(dns::Socket::new(
+            &[],
+            managed::ManagedSlice::Borrowed(&mut resources.queries),
+        ));
+
+        // The SLAAC socket too, on Ethernet.
+        #[cfg(feature = "slaac")]
+        let slaac = match medium {
+            Medium::Ethernet => Some(slaac::Slaac::new(sockets.add(resources.slaac.socket()))),
+            _ => None,
+        };
 
         let next_local_port = (random_seed % (LOCAL_PORT_MAX - LOCAL_PORT_MIN) as u64) as u16 + LOCAL_PORT_MIN;
 
@@ -147,26 +241,29 @@ impl<D: Driver + 'static> Stack<D> {
             device,
             link_up: false,
             config: None,
+            state_wakers: MultiWakerRegistration::new(),
             #[cfg(feature = "dhcpv4")]
             dhcp_socket: None,
+            #[cfg(feature = "dns")]
+            dns_socket,
+            #[cfg(feature = "dns")]
+            dns_wakers: MultiWakerRegistration::new(),
+            #[cfg(feature = "dns")]
+            dns_cache: dns::DnsCache::new(),
+            #[cfg(feature = "slaac")]
+            slaac,
         };
         let mut socket = SocketStack {
             sockets,
+            slots: SOCK,
             iface,
             waker: WakerRegistration::new(),
             next_local_port,
         };
 
-        match config {
-            Config::Static(config) => inner.apply_config(&mut socket, config),
-            #[cfg(feature = "dhcpv4")]
-            Config::Dhcp(config) => {
-                let mut dhcp_socket = smoltcp::socket::dhcpv4::Socket::new();
-                inner.apply_dhcp_config(&mut dhcp_socket, config);
-                let handle = socket.sockets.add(dhcp_socket);
-                inner.dhcp_socket = Some(handle);
-            }
-        }
+        unwrap!(inner.set_config(&mut socket, config));
+        // Add the link-local address, if no configuration was applied.
+        inner.update_ip_addrs(&mut socket);
 
         Self {
             socket: RefCell::new(socket),
@@ -198,6 +295,231 @@ impl<D: Driver + 'static> Stack<D> {
         self.with(|_s, i| i.config.clone())
     }
 
+    /// The IPv6 address autoconfigured from router advertisements, if any.
+    ///
+    /// Routers are solicited when the link goes up. The address expires unless the router keeps
+    /// advertising its prefix.
+    #[cfg(feature = "slaac")]
+    pub fn slaac_address(&self) -> Option<Ipv6Cidr> {
+        self.with(|_s, i| i.slaac.as_ref().and_then(|slaac| slaac.address()))
+    }
+
+    /// Replace the IP configuration of the stack.
+    ///
+    /// The current configuration is dropped, along with its routes and DNS servers, and
+    /// the DHCP client is stopped if running. Then `config` is applied: a static configuration
+    /// takes effect immediately, while DHCP starts acquiring a configuration, see
+    /// [`wait_config_up()`](Self::wait_config_up).
+    ///
+    /// Open sockets are kept, but connections using the previous address will likely fail.
+    ///
+    /// Switching to DHCP uses a socket slot for the DHCP client. If none is free, this returns
+    /// [`ConfigError::NoFreeSocket`] and leaves the current configuration untouched.
+    pub fn set_config(&self, config: Config) -> Result<(), ConfigError> {
+        self.with_mut(|s, i| {
+            i.set_config(s, config)?;
+            s.waker.wake();
+            Ok(())
+        })
+    }
+
+    /// Wait until the link is up.
+    pub async fn wait_link_up(&self) {
+        poll_fn(|cx| {
+            self.with_mut(|_s, i| {
+                if i.link_up {
+                    Poll::Ready(())
+                } else {
+                    register_waker(&mut i.state_wakers, cx.waker());
+                    Poll::Pending
+                }
+            })
+        })
+        .await
+    }
+
+    /// Wait until the stack has an IP configuration.
+    ///
+    /// This returns immediately with a static configuration. With DHCP, this waits until an
+    /// address has been acquired.
+    pub async fn wait_config_up(&self) {
+        poll_fn(|cx| {
+            self.with_mut(|_s, i| {
+                if i.config.is_some() {
+                    Poll::Ready(())
+                } else {
+                    register_waker(&mut i.state_wakers, cx.waker());
+                    Poll::Pending
+                }
+            })
+        })
+        .await
+    }
+
+    /// Resolve a host name to its IP addresses of type `qtype`, with DNS.
+    ///
+    /// The DNS servers of the current configuration are used: either those obtained with DHCP,
+    /// or those of the [`StaticConfig`]. IP addresses in text form are returned as is.
+    ///
+    /// Results are cached for a while: querying the same name again returns the cached addresses,
+    /// without sending a query. Only one query is sent at a time, other calls wait for it to complete.
+    #[cfg(feature = "dns")]
+    pub async fn dns_query(
+        &self,
+        name: &str,
+        qtype: dns::DnsQueryType,
+    ) -> Result<Vec<IpAddress, { dns::MAX_ADDRESSES }>, dns::Error> {
+        if let Ok(ip) = name.parse::<IpAddress>() {
+            let mut addrs = Vec::new();
+            unwrap!(addrs.push(ip));
+            return Ok(addrs);
+        }
+
+        if let Some(addrs) = self.with(|_s, i| i.dns_cache.get(name, qtype)) {
+            return Ok(addrs);
+        }
+
+        let addrs = match embassy_time::with_timeout(dns::QUERY_TIMEOUT, self.dns_query_uncached(name, qtype)).await {
+            Ok(res) => res?,
+            Err(_) => return Err(dns::Error::TimedOut),
+        };
+        self.with_mut(|_s, i| i.dns_cache.insert(name, qtype, &addrs));
+        Ok(addrs)
+    }
+
+    #[cfg(feature = "dns")]
+    async fn dns_query_uncached(
+        &self,
+        name: &str,
+        qtype: dns::DnsQueryType,
+    ) -> Result<Vec<IpAddress, { dns::MAX_ADDRESSES }>, dns::Error> {
+        let query = poll_fn(|cx| {
+            self.with_mut(|s, i| {
+                let socket = s.sockets.get_mut::<dns::Socket>(i.dns_socket);
+                match socket.start_query(s.iface.context(), name, qtype) {
+                    Ok(handle) => {
+                        s.waker.wake();
+                        Poll::Ready(Ok(handle))
+                    }
+                    Err(dns::StartQueryError::NoFreeSlot) => {
+                        register_waker(&mut i.dns_wakers, cx.waker());
+                        Poll::Pending
+                    }
+                    Err(e) => Poll::Ready(Err(dns::Error::from(e))),
+                }
+            })
+        })
+        .await?;
+
+        // Cancel the query if this future is dropped before it completes, on timeout.
+        let cancel = OnDrop(Some(|| {
+            self.with_mut(|s, i| {
+                let socket = s.sockets.get_mut::<dns::Socket>(i.dns_socket);
+                socket.cancel_query(query);
+                i.dns_wakers.wake();
+            })
+        }));
+
+        let res = poll_fn(|cx| {
+            self.with_mut(|s, i| {
+                let socket = s.sockets.get_mut::<dns::Socket>(i.dns_socket);
+                match socket.get_query_result(query) {
+                    Ok(addrs) => Poll::Ready(Ok(addrs.into_iter().take(dns::MAX_ADDRESSES).collect())),
+                    Err(dns::GetQueryResultError::Pending) => {
+                        socket.register_query_waker(query, cx.waker());
+                        Poll::Pending
+                    }
+                    Err(e) => Poll::Ready(Err(dns::Error::from(e))),
+                }
+            })
+        })
+        .await;
+
+        // The query slot has been freed by getting its result.
+        cancel.defuse();
+        self.with_mut(|_s, i| i.dns_wakers.wake());
+        res
+    }
+
+    /// Send an ICMPv4 echo request to `addr`, and wait for the reply.
+    ///
+    /// Returns the round-trip time, or [`PingError::TimedOut`] if no reply is received
+    /// within `timeout`. This is handy to check the link to the gateway, for example.
+    ///
+    /// Only IPv4 hosts can be pinged: ICMPv6 echo is not supported.
+    ///
+    /// This temporarily uses a socket slot of the stack. If none is free, this returns
+    /// [`PingError::NoFreeSocket`]: size the [`StackResources`] to leave one for pinging.
+    #[cfg(feature = "icmp")]
+    pub async fn ping(
+        &self,
+        addr: Ipv4Address,
+        timeout: embassy_time::Duration,
+    ) -> Result<embassy_time::Duration, PingError> {
+        use smoltcp::phy::ChecksumCapabilities;
+        use smoltcp::wire::{Icmpv4Packet, Icmpv4Repr};
+
+        const DATA: &[u8] = b"embassy-net ping";
+        const SEQ_NO: u16 = 1;
+
+        if !self.socket.borrow().has_free_slot() {
+            return Err(PingError::NoFreeSocket);
+        }
+
+        let mut rx_meta = [icmp::PacketMetadata::EMPTY; 1];
+        let mut rx_buffer = [0; 64];
+        let mut tx_meta = [icmp::PacketMetadata::EMPTY; 1];
+        let mut tx_buffer = [0; 64];
+        let mut socket = icmp::IcmpSocket::new(self, &mut rx_meta, &mut rx_buffer, &mut tx_meta, &mut tx_buffer);
+
+        let ident = self.socket.borrow_mut().get_local_port();
+        socket
+            .bind(icmp::Endpoint::Ident(ident))
+            .map_err(|_| PingError::InvalidState)?;
+
+        let caps = ChecksumCapabilities::default();
+        let request = Icmpv4Repr::EchoRequest {
+            ident,
+            seq_no: SEQ_NO,
+            data: DATA,
+        };
+        let mut packet = [0; 64];
+        let packet = &mut packet[..request.buffer_len()];
+        request.emit(&mut Icmpv4Packet::new_unchecked(&mut packet[..]), &caps);
+
+        let start = Instant::now();
+        socket.send_to(packet, addr).await.map_err(|_| PingError::NoRoute)?;
+
+        let reply = async {
+            let mut buf = [0; 64];
+            loop {
+                let (n, from) = match socket.recv_from(&mut buf).await {
+                    Ok(x) => x,
+                    Err(_) => continue,
+                };
+                if from != IpAddress::Ipv4(addr) {
+                    continue;
+                }
+                let packet = match Icmpv4Packet::new_checked(&buf[..n]) {
+                    Ok(packet) => packet,
+                    Err(_) => continue,
+                };
+                match Icmpv4Repr::parse(&packet, &caps) {
+                    Ok(Icmpv4Repr::EchoReply {
+                        ident: reply_ident,
+                        seq_no: SEQ_NO,
+                        ..
+                    }) if reply_ident == ident => return start.elapsed(),
+                    _ => {}
+                }
+            }
+        };
+
+        embassy_time::with_timeout(timeout, reply)
+            .await
+            .map_err(|_| PingError::TimedOut)
+    }
+
     pub async fn run(&self) -> ! {
         poll_fn(|cx| {
             self.with_mut(|s, i| i.poll(cx, s));
@@ -215,9 +537,45 @@ impl SocketStack {
         self.next_local_port = if res >= LOCAL_PORT_MAX { LOCAL_PORT_MIN } else { res + 1 };
         res
     }
+
+    /// Returns whether a socket can be added without running out of slots.
+    #[allow(dead_code)]
+    pub(crate) fn has_free_slot(&self) -> bool {
+        self.sockets.iter().count() < self.slots
+    }
 }
 
 impl<D: Driver + 'static> Inner<D> {
+    fn set_config(&mut self, s: &mut SocketStack, config: Config) -> Result<(), ConfigError> {
+        // Check before dropping anything, so that the current configuration is kept on error.
+        // The DHCP client socket is reused when already running.
+        #[cfg(feature = "dhcpv4")]
+        if matches!(config, Config::Dhcp(_)) && self.dhcp_socket.is_none() && !s.has_free_slot() {
+            return Err(ConfigError::NoFreeSocket);
+        }
+
+        #[cfg(feature = "dhcpv4")]
+        if let Some(handle) = self.dhcp_socket.take() {
+            s.sockets.remove(handle);
+        }
+        if self.config.is_some() {
+            self.unapply_config(s);
+        }
+
+        match config {
+            Config::Static(config) => self.apply_config(s, config),
+            #[cfg(feature = "dhcpv4")]
+            Config::Dhcp(config) => {
+                let mut dhcp_socket = smoltcp::socket::dhcpv4::Socket::new();
+                self.apply_dhcp_config(&mut dhcp_socket, config);
+                let handle = s.sockets.add(dhcp_socket);
+                self.dhcp_socket = Some(handle);
+            }
+        }
+
+        Ok(())
+    }
+
     fn apply_config(&mut self, s: &mut SocketStack, config: StaticConfig) {
         #[cfg(feature = "medium-ethernet")]
         let medium = self.device.capabilities().medium;
@@ -225,13 +583,10 @@ impl<D: Driver + 'static> Inner<D> {
         debug!("Acquired IP configuration:");
 
         debug!("   IP address:      {}", config.address);
-        s.iface.update_ip_addrs(|addrs| {
-            if addrs.is_empty() {
-                addrs.push(IpCidr::Ipv4(config.address)).unwrap();
-            } else {
-                addrs[0] = IpCidr::Ipv4(config.address);
-            }
-        });
+        #[cfg(feature = "proto-ipv6")]
+        for (i, a) in config.ipv6_addresses.iter().enumerate() {
+            debug!("   IPv6 address {}:  {}", i, a);
+        }
 
         #[cfg(feature = "medium-ethernet")]
         if medium == Medium::Ethernet {
@@ -243,11 +598,35 @@ impl<D: Driver + 'static> Inner<D> {
                 s.iface.routes_mut().remove_default_ipv4_route();
             }
         }
+        #[cfg(feature = "proto-ipv6")]
+        if let Some(gateway) = config.ipv6_gateway {
+            debug!("   IPv6 gateway:    {}", gateway);
+        }
         for (i, s) in config.dns_servers.iter().enumerate() {
             debug!("   DNS server {}:    {}", i, s);
         }
+        #[cfg(feature = "proto-ipv6")]
+        for (i, s) in config.ipv6_dns_servers.iter().enumerate() {
+            debug!("   IPv6 DNS server {}: {}", i, s);
+        }
+
+        #[cfg(feature = "dns")]
+        {
+            let servers = config.dns_servers.iter().map(|a| IpAddress::Ipv4(*a));
+            #[cfg(feature = "proto-ipv6")]
+            let servers = servers.chain(config.ipv6_dns_servers.iter().map(|a| IpAddress::Ipv6(*a)));
+            let servers: Vec<IpAddress, 6> = servers.collect();
+            s.sockets
+                .get_mut::<dns::Socket>(self.dns_socket)
+                .update_servers(&servers[..]);
+            self.dns_cache.clear();
+        }
 
-        self.config = Some(config)
+        self.config = Some(config);
+        self.update_ip_addrs(s);
+        #[cfg(all(feature = "proto-ipv6", feature = "medium-ethernet"))]
+        self.update_ipv6_route(s);
+        self.state_wakers.wake();
     }
 
     fn apply_dhcp_config(&self, socket: &mut smoltcp::socket::dhcpv4::Socket, config: DhcpConfig) {
@@ -257,18 +636,75 @@ impl<D: Driver + 'static> Inner<D> {
         socket.set_retry_config(config.retry_config);
     }
 
-    #[allow(unused)] // used only with dhcp
     fn unapply_config(&mut self, s: &mut SocketStack) {
         #[cfg(feature = "medium-ethernet")]
         let medium = self.device.capabilities().medium;
 
         debug!("Lost IP configuration");
-        s.iface.update_ip_addrs(|ip_addrs| ip_addrs.clear());
+        self.config = None;
+        self.update_ip_addrs(s);
         #[cfg(feature = "medium-ethernet")]
         if medium == Medium::Ethernet {
             s.iface.routes_mut().remove_default_ipv4_route();
         }
-        self.config = None
+        #[cfg(all(feature = "proto-ipv6", feature = "medium-ethernet"))]
+        self.update_ipv6_route(s);
+        #[cfg(feature = "dns")]
+        {
+            s.sockets.get_mut::<dns::Socket>(self.dns_socket).update_servers(&[]);
+            self.dns_cache.clear();
+        }
+        self.state_wakers.wake();
+    }
+
+    /// Set the interface addresses: those of the configuration, then the autoconfigured ones.
+    fn update_ip_addrs(&self, s: &mut SocketStack) {
+        s.iface.update_ip_addrs(|addrs| {
+            addrs.clear();
+            let mut push = |cidr: IpCidr| {
+                if addrs.push(cidr).is_err() {
+                    warn!("Too many IP addresses, dropping {}", cidr);
+                }
+            };
+
+            if let Some(config) = &self.config {
+                push(IpCidr::Ipv4(config.address));
+                #[cfg(feature = "proto-ipv6")]
+                for a in &config.ipv6_addresses {
+                    push(IpCidr::Ipv6(*a));
+                }
+            }
+
+            #[cfg(feature = "slaac")]
+            if let Some(a) = self.slaac.as_ref().and_then(|slaac| slaac.address()) {
+                push(IpCidr::Ipv6(a));
+            }
+
+            // Last, so that it's not picked as source address for other destinations.
+            #[cfg(all(feature = "proto-ipv6", feature = "medium-ethernet"))]
+            if self.device.capabilities().medium == Medium::Ethernet {
+                let a = link_local_address(self.device.ethernet_address());
+                push(IpCidr::Ipv6(Ipv6Cidr::new(a, 64)));
+            }
+        });
+    }
+
+    /// Set the IPv6 default route: the configured gateway, else the router learned with SLAAC.
+    #[cfg(all(feature = "proto-ipv6", feature = "medium-ethernet"))]
+    fn update_ipv6_route(&self, s: &mut SocketStack) {
+        if self.device.capabilities().medium != Medium::Ethernet {
+            return;
+        }
+
+        let gateway = self.config.as_ref().and_then(|c| c.ipv6_gateway);
+        #[cfg(feature = "slaac")]
+        let gateway = gateway.or_else(|| self.slaac.as_ref().and_then(|slaac| slaac.router()));
+
+        if let Some(gateway) = gateway {
+            s.iface.routes_mut().add_default_ipv6_route(gateway).unwrap();
+        } else {
+            s.iface.routes_mut().remove_default_ipv6_route();
+        }
     }
 
     fn poll(&mut self, cx: &mut Context<'_>, s: &mut SocketStack) {
@@ -295,6 +731,7 @@ impl<D: Driver + 'static> Inner<D> {
         // Print when changed
         if old_link_up != self.link_up {
             info!("link_up = {:?}", self.link_up);
+            self.state_wakers.wake();
         }
 
         #[cfg(feature = "dhcpv4")]
@@ -310,6 +747,12 @@ impl<D: Driver + 'static> Inner<D> {
                             address: config.address,
                             gateway: config.router,
                             dns_servers: config.dns_servers,
+                            #[cfg(feature = "proto-ipv6")]
+                            ipv6_addresses: Vec::new(),
+                            #[cfg(feature = "proto-ipv6")]
+                            ipv6_gateway: None,
+                            #[cfg(feature = "proto-ipv6")]
+                            ipv6_dns_servers: Vec::new(),
                         };
                         self.apply_config(s, config)
                     }
@@ -323,8 +766,34 @@ impl<D: Driver + 'static> Inner<D> {
         //    self.poll_configurator(timestamp)
         //}
 
-        if let Some(poll_at) = s.iface.poll_at(timestamp, &mut s.sockets) {
-            let t = Timer::at(instant_from_smoltcp(poll_at));
+        #[cfg(feature = "slaac")]
+        if let Some(slaac) = &mut self.slaac {
+            let mut changed = false;
+            if self.link_up != old_link_up {
+                slaac.reset(self.link_up);
+                changed = true;
+            }
+            let socket = s.sockets.get_mut::<raw::Socket>(slaac.socket);
+            changed |= slaac.poll(socket, self.device.ethernet_address());
+
+            if changed {
+                if let Some(a) = slaac.address() {
+                    debug!("SLAAC address: {}", a);
+                }
+                self.update_ip_addrs(s);
+                self.update_ipv6_route(s);
+            }
+        }
+
+        let poll_at = s.iface.poll_at(timestamp, &mut s.sockets).map(instant_from_smoltcp);
+        #[cfg(feature = "slaac")]
+        let poll_at = match (poll_at, self.slaac.as_ref().and_then(|slaac| slaac.poll_at())) {
+            (Some(a), Some(b)) => Some(a.min(b)),
+            (a, b) => a.or(b),
+        };
+
+        if let Some(poll_at) = poll_at {
+            let t = Timer::at(poll_at);
             pin_mut!(t);
             if t.poll(cx).is_ready() {
                 cx.waker().wake_by_ref();
@@ -333,6 +802,51 @@ impl<D: Driver + 'static> Inner<D> {
     }
 }
 
+/// Register a waker with `wakers`, waking all the others if there's no free slot.
+///
+/// The tasks woken that are still waiting will simply register again.
+fn register_waker<const N: usize>(wakers: &mut MultiWakerRegistration<N>, waker: &Waker) {
+    if wakers.register(waker).is_err() {
+        wakers.wake();
+        // Can't fail, all the slots are free now.
+        let _ = wakers.register(waker);
+    }
+}
+
+/// Runs a closure when dropped, unless defused.
+#[cfg(feature = "dns")]
+struct OnDrop<F: FnOnce()>(Option<F>);
+
+#[cfg(feature = "dns")]
+impl<F: FnOnce()> OnDrop<F> {
+    fn defuse(mut self) {
+        self.0 = None;
+    }
+}
+
+#[cfg(feature = "dns")]
+impl<F: FnOnce()> Drop for OnDrop<F> {
+    fn drop(&mut self) {
+        if let Some(f) = self.0.take() {
+            f()
+        }
+    }
+}
+
+/// Link-local address derived from an Ethernet address, as specified by RFC 4862.
+#[cfg(all(feature = "proto-ipv6", feature = "medium-ethernet"))]
+pub(crate) fn link_local_address(mac: [u8; 6]) -> Ipv6Address {
+    ipv6_address_with_interface_id(Ipv6Address::new(0xfe80, 0, 0, 0, 0, 0, 0, 0), mac)
+}
+
+/// Address made of the 64-bit prefix of `prefix`, and the modified EUI-64 identifier of `mac`.
+#[cfg(all(feature = "proto-ipv6", feature = "medium-ethernet"))]
+pub(crate) fn ipv6_address_with_interface_id(prefix: Ipv6Address, mac: [u8; 6]) -> Ipv6Address {
+    let mut bytes = prefix.0;
+    bytes[8..].copy_from_slice(&[mac[0] ^ 0x02, mac[1], mac[2], 0xff, 0xfe, mac[3], mac[4], mac[5]]);
+    Ipv6Address(bytes)
+}
+
 fn instant_to_smoltcp(instant: Instant) -> SmolInstant {
     SmolInstant::from_millis(instant.as_millis() as i64)
 }
@@ -0,0 +1,127 @@
+use core::cell::RefCell;
+use core::future::poll_fn;
+use core::mem;
+use core::task::Poll;
+
+use embassy_net_driver::Driver;
+use smoltcp::iface::{Interface, SocketHandle};
+use smoltcp::socket::raw;
+pub use smoltcp::socket::raw::PacketMetadata;
+pub use smoltcp::wire::{IpProtocol, IpVersion};
+
+use crate::{SocketStack, Stack};
+
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Error {
+    /// The packet is too large for the transmit buffer.
+    Truncated,
+}
+
+/// Raw IP socket, to send and receive the packets of a given IP protocol.
+///
+/// Unlike other sockets, packets are sent and received whole, with their IP header: the
+/// source and destination addresses are read from, and written to, the header.
+pub struct RawSocket<'a> {
+    stack: &'a RefCell<SocketStack>,
+    handle: SocketHandle,
+}
+
+impl<'a> RawSocket<'a> {
+    pub fn new<D: Driver>(
+        stack: &'a Stack<D>,
+        ip_version: IpVersion,
+        ip_protocol: IpProtocol,
+        rx_meta: &'a mut [PacketMetadata],
+        rx_buffer: &'a mut [u8],
+        tx_meta: &'a mut [PacketMetadata],
+        tx_buffer: &'a mut [u8],
+    ) -> Self {
+        let s = &mut *stack.socket.borrow_mut();
+
+        let rx_meta: &'static mut [PacketMetadata] = unsafe { mem::transmute(rx_meta) };
+        let rx_buffer: &'static mut [u8] = unsafe { mem::transmute(rx_buffer) };
+        let tx_meta: &'static mut [PacketMetadata] = unsafe { mem::transmute(tx_meta) };
+        let tx_buffer: &'static mut [u8] = unsafe { mem::transmute(tx_buffer) };
+        let handle = s.sockets.add(raw::Socket::new(
+            ip_version,
+            ip_protocol,
+            raw::PacketBuffer::new(rx_meta, rx_buffer),
+            raw::PacketBuffer::new(tx_meta, tx_buffer),
+        ));
+
+        Self {
+            stack: &stack.socket,
+            handle,
+        }
+    }
+
+    fn with<R>(&self, f: impl FnOnce(&raw::Socket, &Interface) -> R) -> R {
+        let s = &*self.stack.borrow();
+        let socket = s.sockets.get::<raw::Socket>(self.handle);
+        f(socket, &s.iface)
+    }
+
+    fn with_mut<R>(&self, f: impl FnOnce(&mut raw::Socket, &mut Interface) -> R) -> R {
+        let s = &mut *self.stack.borrow_mut();
+        let socket = s.sockets.get_mut::<raw::Socket>(self.handle);
+        let res = f(socket, &mut s.iface);
+        s.waker.wake();
+        res
+    }
+
+    /// Receive a packet, including its IP header. Returns its length.
+    pub async fn recv(&self, buf: &mut [u8]) -> Result<usize, Error> {
+        poll_fn(move |cx| {
+            self.with_mut(|s, _| match s.recv_slice(buf) {
+                Ok(n) => Poll::Ready(Ok(n)),
+                // No data ready
+                Err(raw::RecvError::Exhausted) => {
+                    s.register_recv_waker(cx.waker());
+                    Poll::Pending
+                }
+            })
+        })
+        .await
+    }
+
+    /// Send a packet. `buf` holds the whole packet, including its IP header.
+    pub async fn send(&self, buf: &[u8]) -> Result<(), Error> {
+        poll_fn(move |cx| {
+            self.with_mut(|s, _| match s.send_slice(buf) {
+                // Entire packet has been sent
+                Ok(()) => Poll::Ready(Ok(())),
+                Err(raw::SendError::BufferFull) if buf.len() > s.payload_send_capacity() => {
+                    Poll::Ready(Err(Error::Truncated))
+                }
+                Err(raw::SendError::BufferFull) => {
+                    s.register_send_waker(cx.waker());
+                    Poll::Pending
+                }
+            })
+        })
+        .await
+    }
+
+    pub fn ip_version(&self) -> IpVersion {
+        self.with(|s, _| s.ip_version())
+    }
+
+    pub fn ip_protocol(&self) -> IpProtocol {
+        self.with(|s, _| s.ip_protocol())
+    }
+
+    pub fn may_send(&self) -> bool {
+        self.with(|s, _| s.can_send())
+    }
+
+    pub fn may_recv(&self) -> bool {
+        self.with(|s, _| s.can_recv())
+    }
+}
+
+impl Drop for RawSocket<'_> {
+    fn drop(&mut self) {
+        self.stack.borrow_mut().sockets.remove(self.handle);
+    }
+}
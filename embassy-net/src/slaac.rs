@@ -0,0 +1,495 @@
+//! IPv6 stateless address autoconfiguration (SLAAC), from router advertisements.
+use embassy_time::{Duration, Instant};
+use smoltcp::iface::SocketHandle;
+use smoltcp::phy::ChecksumCapabilities;
+use smoltcp::socket::raw;
+use smoltcp::wire::{
+    EthernetAddress, HardwareAddress, Icmpv6Packet, Icmpv6Repr, IpAddress, IpProtocol, Ipv6Address, Ipv6Cidr,
+    Ipv6Packet, Ipv6Repr, NdiscPrefixInfoFlags, NdiscPrefixInformation, NdiscRepr,
+};
+
+use crate::{ipv6_address_with_interface_id, link_local_address};
+
+/// Interval between router solicitations, until a router advertisement is received.
+const SOLICIT_INTERVAL: Duration = Duration::from_secs(4);
+/// Number of router solicitations sent when the link comes up, as recommended by RFC 4861.
+const MAX_SOLICITS: u8 = 3;
+/// Lifetime below which router advertisements can't shorten the lifetime of an address, see
+/// RFC 4862 section 5.5.3 (e).
+const MIN_VALID_LIFETIME: Duration = Duration::from_secs(2 * 60 * 60);
+
+/// Buffers of the raw ICMPv6 socket receiving router advertisements.
+pub(crate) struct SlaacResources {
+    rx_meta: [raw::PacketMetadata; 2],
+    rx_buffer: [u8; 256],
+    tx_meta: [raw::PacketMetadata; 1],
+    tx_buffer: [u8; 64],
+}
+
+impl SlaacResources {
+    pub(crate) const fn new() -> Self {
+        Self {
+            rx_meta: [raw::PacketMetadata::EMPTY; 2],
+            rx_buffer: [0; 256],
+            tx_meta: [raw::PacketMetadata::EMPTY; 1],
+            tx_buffer: [0; 64],
+        }
+    }
+
+    pub(crate) fn socket(&'static mut self) -> raw::Socket<'static> {
+        raw::Socket::new(
+            smoltcp::wire::IpVersion::Ipv6,
+            IpProtocol::Icmpv6,
+            raw::PacketBuffer::new(&mut self.rx_meta[..], &mut self.rx_buffer[..]),
+            raw::PacketBuffer::new(&mut self.tx_meta[..], &mut self.tx_buffer[..]),
+        )
+    }
+}
+
+/// SLAAC client state.
+pub(crate) struct Slaac {
+    pub(crate) socket: SocketHandle,
+    /// Autoconfigured address, and when it expires.
+    address: Option<(Ipv6Cidr, Instant)>,
+    /// Default router, and when it expires.
+    router: Option<(Ipv6Address, Instant)>,
+    next_solicit: Option<Instant>,
+    solicits_left: u8,
+}
+
+impl Slaac {
+    pub(crate) fn new(socket: SocketHandle) -> Self {
+        Self {
+            socket,
+            address: None,
+            router: None,
+            next_solicit: None,
+            solicits_left: 0,
+        }
+    }
+
+    pub(crate) fn address(&self) -> Option<Ipv6Cidr> {
+        self.address.map(|(a, _)| a)
+    }
+
+    pub(crate) fn router(&self) -> Option<Ipv6Address> {
+        self.router.map(|(r, _)| r)
+    }
+
+    /// Start over, soliciting routers right away if the link is up.
+    pub(crate) fn reset(&mut self, link_up: bool) {
+        self.address = None;
+        self.router = None;
+        self.solicits_left = if link_up { MAX_SOLICITS } else { 0 };
+        self.next_solicit = link_up.then(Instant::now);
+    }
+
+    /// Time at which `poll` must be called again.
+    pub(crate) fn poll_at(&self) -> Option<Instant> {
+        [
+            self.next_solicit,
+            self.address.map(|(_, t)| t),
+            self.router.map(|(_, t)| t),
+        ]
+        .into_iter()
+        .flatten()
+        .min()
+    }
+
+    /// Handle received router advertisements, expirations, and send router solicitations.
+    ///
+    /// Returns whether the address or router changed.
+    pub(crate) fn poll(&mut self, socket: &mut raw::Socket, mac: [u8; 6]) -> bool {
+        let now = Instant::now();
+        let old = (self.address(), self.router());
+
+        let mut buf = [0; 256];
+        while let Ok(n) = socket.recv_slice(&mut buf) {
+            self.process(&buf[..n], mac, now);
+        }
+
+        self.expire(now);
+
+        if self.solicit_due(now) {
+            self.solicit(socket, mac);
+        }
+
+        old != (self.address(), self.router())
+    }
+
+    /// Drop the address and router whose lifetime has ended at `now`.
+    fn expire(&mut self, now: Instant) {
+        if matches!(self.address, Some((_, t)) if t <= now) {
+            debug!("SLAAC address expired");
+            self.address = None;
+        }
+        if matches!(self.router, Some((_, t)) if t <= now) {
+            debug!("SLAAC router expired");
+            self.router = None;
+        }
+    }
+
+    /// Returns whether a router solicitation is due at `now`, and schedules the next one.
+    fn solicit_due(&mut self, now: Instant) -> bool {
+        if !matches!(self.next_solicit, Some(t) if t <= now) {
+            return false;
+        }
+
+        self.solicits_left -= 1;
+        self.next_solicit = (self.solicits_left > 0).then(|| now + SOLICIT_INTERVAL);
+        true
+    }
+
+    fn process(&mut self, packet: &[u8], mac: [u8; 6], now: Instant) {
+        let (router, router_lifetime, prefix_info) = match parse_router_advert(packet) {
+            Some(advert) => advert,
+            None => return,
+        };
+
+        // Got an answer, stop soliciting.
+        self.next_solicit = None;
+
+        if router_lifetime.total_millis() == 0 {
+            self.router = None;
+        } else {
+            let expires_at = now + Duration::from_millis(router_lifetime.total_millis());
+            self.router = Some((router, expires_at));
+        }
+
+        if let Some(info) = prefix_info {
+            // Addresses are formed from a 64-bit prefix and the 64-bit interface identifier.
+            if info.flags.contains(NdiscPrefixInfoFlags::ADDRCONF) && info.prefix_len == 64 {
+                let address = Ipv6Cidr::new(ipv6_address_with_interface_id(info.prefix, mac), 64);
+                let valid_lifetime = Duration::from_millis(info.valid_lifetime.total_millis());
+
+                match self.address {
+                    Some((current, expires_at)) if current == address => {
+                        let expires_at = address_expiration(expires_at, valid_lifetime, now);
+                        self.address = Some((address, expires_at));
+                    }
+                    // No address is formed from a prefix advertised with a zero lifetime.
+                    _ if valid_lifetime.as_ticks() == 0 => {}
+                    _ => self.address = Some((address, now + valid_lifetime)),
+                }
+            }
+        }
+    }
+
+    fn solicit(&mut self, socket: &mut raw::Socket, mac: [u8; 6]) {
+        let caps = ChecksumCapabilities::default();
+        let src = link_local_address(mac);
+        let dst = Ipv6Address::LINK_LOCAL_ALL_ROUTERS;
+        let icmp_repr = Icmpv6Repr::Ndisc(NdiscRepr::RouterSolicit {
+            lladdr: Some(HardwareAddress::Ethernet(EthernetAddress(mac)).into()),
+        });
+        let ip_repr = Ipv6Repr {
+            src_addr: src,
+            dst_addr: dst,
+            next_header: IpProtocol::Icmpv6,
+            payload_len: icmp_repr.buffer_len(),
+            hop_limit: 255,
+        };
+
+        let mut buf = [0; 64];
+        let buf = &mut buf[..ip_repr.buffer_len() + icmp_repr.buffer_len()];
+        let mut packet = Ipv6Packet::new_unchecked(&mut buf[..]);
+        ip_repr.emit(&mut packet);
+        icmp_repr.emit(
+            &IpAddress::Ipv6(src),
+            &IpAddress::Ipv6(dst),
+            &mut Icmpv6Packet::new_unchecked(packet.payload_mut()),
+            &caps,
+        );
+
+        debug!("Sending router solicitation");
+        if socket.send_slice(buf).is_err() {
+            warn!("Failed to send router solicitation");
+        }
+    }
+}
+
+/// New expiration of an address expiring at `expires_at`, after its prefix is advertised again
+/// with `valid_lifetime` at `now`.
+///
+/// Following RFC 4862 section 5.5.3 (e), an advertisement can only shorten the lifetime of an
+/// address down to two hours, so that spoofed advertisements can't make it expire right away.
+fn address_expiration(expires_at: Instant, valid_lifetime: Duration, now: Instant) -> Instant {
+    let remaining = expires_at.saturating_duration_since(now);
+    if valid_lifetime > MIN_VALID_LIFETIME || valid_lifetime > remaining {
+        now + valid_lifetime
+    } else if remaining <= MIN_VALID_LIFETIME {
+        expires_at
+    } else {
+        now + MIN_VALID_LIFETIME
+    }
+}
+
+/// Parse a router advertisement, returning the router's address, its lifetime as a default
+/// router, and the advertised prefix.
+fn parse_router_advert(
+    packet: &[u8],
+) -> Option<(Ipv6Address, smoltcp::time::Duration, Option<NdiscPrefixInformation>)> {
+    let ip = Ipv6Packet::new_checked(packet).ok()?;
+    let ip_repr = Ipv6Repr::parse(&ip).ok()?;
+
+    // Router advertisements come from link-local addresses, with the maximum hop limit.
+    if !ip_repr.src_addr.is_link_local() || ip_repr.hop_limit != 255 {
+        return None;
+    }
+
+    let icmp = Icmpv6Packet::new_checked(ip.payload()).ok()?;
+    let src = IpAddress::Ipv6(ip_repr.src_addr);
+    let dst = IpAddress::Ipv6(ip_repr.dst_addr);
+    match Icmpv6Repr::parse(&src, &dst, &icmp, &ChecksumCapabilities::default()).ok()? {
+        Icmpv6Repr::Ndisc(NdiscRepr::RouterAdvert {
+            router_lifetime,
+            prefix_info,
+            ..
+        }) => Some((ip_repr.src_addr, router_lifetime, prefix_info)),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use smoltcp::time::Duration as SmolDuration;
+    use smoltcp::wire::NdiscRouterFlags;
+
+    use super::*;
+
+    const MAC: [u8; 6] = [0x00, 0x1a, 0x2b, 0x3c, 0x4d, 0x5e];
+    const ROUTER: Ipv6Address = Ipv6Address::new(0xfe80, 0, 0, 0, 0x0211, 0x22ff, 0xfe33, 0x4455);
+    const PREFIX: Ipv6Address = Ipv6Address::new(0x2001, 0xdb8, 0x1, 0x2, 0, 0, 0, 0);
+
+    fn prefix_info() -> NdiscPrefixInformation {
+        NdiscPrefixInformation {
+            prefix_len: 64,
+            flags: NdiscPrefixInfoFlags::ON_LINK | NdiscPrefixInfoFlags::ADDRCONF,
+            valid_lifetime: SmolDuration::from_secs(86400),
+            preferred_lifetime: SmolDuration::from_secs(14400),
+            prefix: PREFIX,
+        }
+    }
+
+    /// Emit an IPv6 packet holding `icmp_repr` into `buf`, returning its length.
+    fn emit(buf: &mut [u8], src: Ipv6Address, hop_limit: u8, icmp_repr: &Icmpv6Repr) -> usize {
+        let dst = Ipv6Address::LINK_LOCAL_ALL_NODES;
+        let ip_repr = Ipv6Repr {
+            src_addr: src,
+            dst_addr: dst,
+            next_header: IpProtocol::Icmpv6,
+            payload_len: icmp_repr.buffer_len(),
+            hop_limit,
+        };
+        let len = ip_repr.buffer_len() + icmp_repr.buffer_len();
+        let mut packet = Ipv6Packet::new_unchecked(&mut buf[..len]);
+        ip_repr.emit(&mut packet);
+        icmp_repr.emit(
+            &IpAddress::Ipv6(src),
+            &IpAddress::Ipv6(dst),
+            &mut Icmpv6Packet::new_unchecked(packet.payload_mut()),
+            &ChecksumCapabilities::default(),
+        );
+        len
+    }
+
+    fn router_advert(buf: &mut [u8], src: Ipv6Address, hop_limit: u8) -> usize {
+        advert(buf, src, hop_limit, prefix_info())
+    }
+
+    /// Router advertisement from `ROUTER`, with `PREFIX` valid for `valid_lifetime` seconds.
+    fn advert_valid_for(buf: &mut [u8], valid_lifetime: u64) -> usize {
+        let prefix = NdiscPrefixInformation {
+            valid_lifetime: SmolDuration::from_secs(valid_lifetime),
+            preferred_lifetime: SmolDuration::from_secs(valid_lifetime.min(14400)),
+            ..prefix_info()
+        };
+        advert(buf, ROUTER, 255, prefix)
+    }
+
+    fn advert(buf: &mut [u8], src: Ipv6Address, hop_limit: u8, prefix: NdiscPrefixInformation) -> usize {
+        let icmp_repr = Icmpv6Repr::Ndisc(NdiscRepr::RouterAdvert {
+            hop_limit: 64,
+            flags: NdiscRouterFlags::empty(),
+            router_lifetime: SmolDuration::from_secs(1800),
+            reachable_time: SmolDuration::from_millis(0),
+            retrans_time: SmolDuration::from_millis(0),
+            lladdr: None,
+            mtu: None,
+            prefix_info: Some(prefix),
+        });
+        emit(buf, src, hop_limit, &icmp_repr)
+    }
+
+    fn address() -> Ipv6Cidr {
+        Ipv6Cidr::new(ipv6_address_with_interface_id(PREFIX, MAC), 64)
+    }
+
+    fn expiration(slaac: &Slaac) -> Option<Instant> {
+        slaac.address.map(|(_, t)| t)
+    }
+
+    #[test]
+    fn parses_router_advert() {
+        let mut buf = [0; 128];
+        let len = router_advert(&mut buf, ROUTER, 255);
+
+        let (router, lifetime, prefix) = parse_router_advert(&buf[..len]).unwrap();
+        assert_eq!(router, ROUTER);
+        assert_eq!(lifetime, SmolDuration::from_secs(1800));
+        assert_eq!(prefix, Some(prefix_info()));
+    }
+
+    #[test]
+    fn ignores_router_advert_from_other_link() {
+        let mut buf = [0; 128];
+
+        // Routers advertise from their link-local address.
+        let len = router_advert(&mut buf, Ipv6Address::new(0x2001, 0xdb8, 0, 0, 0, 0, 0, 1), 255);
+        assert!(parse_router_advert(&buf[..len]).is_none());
+
+        // A packet that went through a router has a decremented hop limit.
+        let len = router_advert(&mut buf, ROUTER, 254);
+        assert!(parse_router_advert(&buf[..len]).is_none());
+    }
+
+    #[test]
+    fn ignores_other_messages() {
+        let mut buf = [0; 128];
+        let icmp_repr = Icmpv6Repr::Ndisc(NdiscRepr::RouterSolicit { lladdr: None });
+        let len = emit(&mut buf, ROUTER, 255, &icmp_repr);
+        assert!(parse_router_advert(&buf[..len]).is_none());
+
+        assert!(parse_router_advert(&[]).is_none());
+        let len = router_advert(&mut buf, ROUTER, 255);
+        assert!(parse_router_advert(&buf[..len - 1]).is_none());
+    }
+
+    #[test]
+    fn configures_address_and_router() {
+        let mut slaac = Slaac::new(SocketHandle::default());
+        slaac.reset(true);
+        let now = Instant::now();
+
+        let mut buf = [0; 128];
+        let len = advert_valid_for(&mut buf, 86400);
+        slaac.process(&buf[..len], MAC, now);
+
+        assert_eq!(slaac.address(), Some(address()));
+        assert_eq!(slaac.router(), Some(ROUTER));
+        assert_eq!(slaac.poll_at(), Some(now + Duration::from_secs(1800)));
+
+        // Soliciting stops once a router answered.
+        assert!(!slaac.solicit_due(now + Duration::from_secs(60)));
+    }
+
+    #[test]
+    fn expires_address_and_router() {
+        let mut slaac = Slaac::new(SocketHandle::default());
+        let now = Instant::now();
+
+        let mut buf = [0; 128];
+        let len = advert_valid_for(&mut buf, 86400);
+        slaac.process(&buf[..len], MAC, now);
+
+        slaac.expire(now + Duration::from_secs(1799));
+        assert_eq!(slaac.router(), Some(ROUTER));
+
+        slaac.expire(now + Duration::from_secs(1800));
+        assert_eq!(slaac.router(), None);
+        assert_eq!(slaac.address(), Some(address()));
+        assert_eq!(slaac.poll_at(), Some(now + Duration::from_secs(86400)));
+
+        slaac.expire(now + Duration::from_secs(86400));
+        assert_eq!(slaac.address(), None);
+        assert_eq!(slaac.poll_at(), None);
+    }
+
+    #[test]
+    fn ignores_zero_lifetime_for_new_prefix() {
+        let mut slaac = Slaac::new(SocketHandle::default());
+        let now = Instant::now();
+
+        let mut buf = [0; 128];
+        let len = advert_valid_for(&mut buf, 0);
+        slaac.process(&buf[..len], MAC, now);
+
+        assert_eq!(slaac.address(), None);
+        assert_eq!(slaac.router(), Some(ROUTER));
+    }
+
+    #[test]
+    fn applies_two_hour_rule() {
+        let mut slaac = Slaac::new(SocketHandle::default());
+        let now = Instant::now();
+        let mut buf = [0; 128];
+
+        let len = advert_valid_for(&mut buf, 86400);
+        slaac.process(&buf[..len], MAC, now);
+
+        // A zero or short lifetime can't make the address expire in less than two hours.
+        let len = advert_valid_for(&mut buf, 0);
+        slaac.process(&buf[..len], MAC, now);
+        assert_eq!(expiration(&slaac), Some(now + MIN_VALID_LIFETIME));
+
+        // Once less than two hours are left, it doesn't shorten the lifetime at all.
+        let later = now + Duration::from_secs(3600);
+        let len = advert_valid_for(&mut buf, 600);
+        slaac.process(&buf[..len], MAC, later);
+        assert_eq!(expiration(&slaac), Some(now + MIN_VALID_LIFETIME));
+
+        // A lifetime longer than the remaining one extends it.
+        let len = advert_valid_for(&mut buf, 5400);
+        slaac.process(&buf[..len], MAC, later);
+        assert_eq!(expiration(&slaac), Some(later + Duration::from_secs(5400)));
+
+        // So does one longer than two hours, even if shorter than the remaining one.
+        let len = advert_valid_for(&mut buf, 86400);
+        slaac.process(&buf[..len], MAC, later);
+        let len = advert_valid_for(&mut buf, 3 * 3600);
+        slaac.process(&buf[..len], MAC, later);
+        assert_eq!(expiration(&slaac), Some(later + Duration::from_secs(3 * 3600)));
+    }
+
+    #[test]
+    fn solicits_until_answered() {
+        let mut slaac = Slaac::new(SocketHandle::default());
+        slaac.reset(false);
+        assert!(!slaac.solicit_due(Instant::now()));
+
+        slaac.reset(true);
+        let now = Instant::now();
+        assert!(slaac.solicit_due(now));
+        assert!(!slaac.solicit_due(now));
+        assert_eq!(slaac.poll_at(), Some(now + SOLICIT_INTERVAL));
+
+        assert!(slaac.solicit_due(now + SOLICIT_INTERVAL));
+        assert!(slaac.solicit_due(now + SOLICIT_INTERVAL * 2));
+
+        // Only `MAX_SOLICITS` solicitations are sent.
+        assert_eq!(slaac.poll_at(), None);
+        assert!(!slaac.solicit_due(now + SOLICIT_INTERVAL * 10));
+    }
+
+    #[test]
+    fn derives_interface_id_from_mac() {
+        // The universal/local bit of the MAC is flipped, and ff:fe is inserted in the middle.
+        assert_eq!(
+            link_local_address(MAC),
+            Ipv6Address::new(0xfe80, 0, 0, 0, 0x021a, 0x2bff, 0xfe3c, 0x4d5e)
+        );
+        assert_eq!(
+            link_local_address([0x02, 0, 0, 0, 0, 0x01]),
+            Ipv6Address::new(0xfe80, 0, 0, 0, 0, 0x00ff, 0xfe00, 0x0001)
+        );
+    }
+
+    #[test]
+    fn keeps_prefix_of_address() {
+        let prefix = Ipv6Address::new(0x2001, 0xdb8, 0x1, 0x2, 0xaaaa, 0xbbbb, 0xcccc, 0xdddd);
+        assert_eq!(
+            ipv6_address_with_interface_id(prefix, MAC),
+            Ipv6Address::new(0x2001, 0xdb8, 0x1, 0x2, 0x021a, 0x2bff, 0xfe3c, 0x4d5e)
+        );
+    }
+}
@@ -1,8 +1,11 @@
-use core::cell::RefCell;
+use core::cell::{RefCell, UnsafeCell};
 use core::future::poll_fn;
 use core::mem;
+use core::mem::MaybeUninit;
+use core::ptr::NonNull;
 use core::task::Poll;
 
+use atomic_polyfill::{AtomicBool, Ordering};
 use embassy_net_driver::Driver;
 use smoltcp::iface::{Interface, SocketHandle};
 use smoltcp::socket::tcp;
@@ -146,6 +149,16 @@ impl<'a> TcpSocket<'a> {
         self.io.write(buf).await
     }
 
+    /// Wait until all the data written has been sent and acknowledged by the peer.
+    ///
+    /// This blocks for at least a round trip whenever there's unacknowledged data, and for as
+    /// long as the peer doesn't acknowledge it (bounded by [`set_timeout()`](Self::set_timeout)
+    /// if one is set). It returns right away once the socket is closed, even if data was left
+    /// unsent. `embedded_io` `flush()` on [`TcpSocket`] and [`TcpWriter`] behaves the same way.
+    pub async fn flush(&mut self) -> Result<(), Error> {
+        self.io.flush().await
+    }
+
     pub fn set_timeout(&mut self, duration: Option<Duration>) {
         self.io.with_mut(|s, _| s.set_timeout(duration))
     }
@@ -254,10 +267,20 @@ impl<'d> TcpIo<'d> {
         .await
     }
 
-    #[allow(unused)]
+    /// Wait until all the data written has been sent and acknowledged by the peer, or the
+    /// connection is closed.
     async fn flush(&mut self) -> Result<(), Error> {
-        poll_fn(move |_| {
-            Poll::Ready(Ok(())) // TODO: Is there a better implementation for this?
+        poll_fn(move |cx| {
+            self.with_mut(|s, _| {
+                // smoltcp wakes the send waker when acknowledged data is dequeued from the
+                // send buffer, and when the socket changes state.
+                if s.send_queue() > 0 && s.state() != tcp::State::Closed {
+                    s.register_send_waker(cx.waker());
+                    Poll::Pending
+                } else {
+                    Poll::Ready(Ok(()))
+                }
+            })
         })
         .await
     }
@@ -326,11 +349,6 @@ mod embedded_io_impls {
 
 #[cfg(all(feature = "unstable-traits", feature = "nightly"))]
 pub mod client {
-    use core::cell::UnsafeCell;
-    use core::mem::MaybeUninit;
-    use core::ptr::NonNull;
-
-    use atomic_polyfill::{AtomicBool, Ordering};
     use embedded_nal_async::IpAddr;
 
     use super::*;
@@ -443,42 +461,431 @@ pub mod client {
     }
 
     unsafe impl<const N: usize, const TX_SZ: usize, const RX_SZ: usize> Sync for TcpClientState<N, TX_SZ, RX_SZ> {}
+}
+
+/// TCP server, accepting concurrent connections on a local endpoint.
+pub mod server {
+    use core::task::Context;
+
+    use embassy_sync::waitqueue::AtomicWaker;
+    use heapless::Vec;
+
+    use super::*;
+
+    /// TCP listener, keeping a backlog of sockets listening on a local endpoint.
+    ///
+    /// Each socket of the backlog takes buffers from the [`TcpListenerState`] pool. When one of
+    /// them accepts a connection, it's handed out by [`accept()`](Self::accept) and a new socket
+    /// starts listening, as long as the pool has free buffers. Dropping a [`TcpConnection`] frees
+    /// its buffers, so the listener can listen again with them.
+    ///
+    /// With N buffers in the pool and a backlog of K sockets, up to N - K connections can be
+    /// served while still accepting new ones. Each socket also takes one of the stack's socket
+    /// slots, see [`StackResources`](crate::StackResources).
+    pub struct TcpListener<'d, D: Driver, const N: usize, const TX_SZ: usize = 1024, const RX_SZ: usize = 1024> {
+        stack: &'d Stack<D>,
+        state: &'d TcpListenerState<N, TX_SZ, RX_SZ>,
+        endpoint: IpListenEndpoint,
+        backlog: usize,
+        listening: Vec<TcpConnection<'d, N, TX_SZ, RX_SZ>, N>,
+    }
+
+    impl<'d, D: Driver, const N: usize, const TX_SZ: usize, const RX_SZ: usize> TcpListener<'d, D, N, TX_SZ, RX_SZ> {
+        /// Create a new TcpListener, keeping `backlog` sockets listening on `endpoint`.
+        ///
+        /// Sockets start listening on the first call to [`accept()`](Self::accept).
+        ///
+        /// # Panics
+        ///
+        /// Panics if `backlog` is 0 or greater than `N`.
+        pub fn new<T>(
+            stack: &'d Stack<D>,
+            state: &'d TcpListenerState<N, TX_SZ, RX_SZ>,
+            endpoint: T,
+            backlog: usize,
+        ) -> Self
+        where
+            T: Into<IpListenEndpoint>,
+        {
+            assert!(backlog > 0 && backlog <= N, "backlog must be between 1 and N");
+            Self {
+                stack,
+                state,
+                endpoint: endpoint.into(),
+                backlog,
+                listening: Vec::new(),
+            }
+        }
+
+        /// Wait for a connection, and return it.
+        pub async fn accept(&mut self) -> Result<TcpConnection<'d, N, TX_SZ, RX_SZ>, AcceptError> {
+            poll_fn(|cx| {
+                if let Err(e) = self.listen(cx) {
+                    return Poll::Ready(Err(e));
+                }
+
+                for i in 0..self.listening.len() {
+                    let accepted = self.listening[i].socket.io.with_mut(|s, _| match s.state() {
+                        tcp::State::Listen | tcp::State::SynReceived => {
+                            s.register_send_waker(cx.waker());
+                            false
+                        }
+                        _ => true,
+                    });
+                    if accepted {
+                        let connection = self.listening.swap_remove(i);
+                        // Listen again right away, not to miss the next connection. Errors are
+                        // returned by the next call.
+                        let _ = self.listen(cx);
+                        return Poll::Ready(Ok(connection));
+                    }
+                }
+                Poll::Pending
+            })
+            .await
+        }
+
+        /// Fill the backlog with listening sockets, as long as buffers are available.
+        fn listen(&mut self, cx: &mut Context<'_>) -> Result<(), AcceptError> {
+            while self.listening.len() < self.backlog {
+                let mut connection = match TcpConnection::new(self.stack, self.state) {
+                    Some(connection) => connection,
+                    None => {
+                        // Try again when a connection is dropped.
+                        self.state.waker.register(cx.waker());
+                        return Ok(());
+                    }
+                };
+                match connection.socket.io.with_mut(|s, _| s.listen(self.endpoint)) {
+                    Ok(()) => {}
+                    Err(tcp::ListenError::InvalidState) => return Err(AcceptError::InvalidState),
+                    Err(tcp::ListenError::Unaddressable) => return Err(AcceptError::InvalidPort),
+                }
+                if self.listening.push(connection).is_err() {
+                    // The backlog is at most N.
+                    unreachable!()
+                }
+            }
+            Ok(())
+        }
+    }
+
+    /// Connection accepted by a [`TcpListener`].
+    ///
+    /// Dropping the connection removes its socket from the stack immediately, so data still in
+    /// its send buffer is lost. Call [`flush()`](Self::flush) first to make sure it's been sent.
+    pub struct TcpConnection<'d, const N: usize, const TX_SZ: usize, const RX_SZ: usize> {
+        socket: TcpSocket<'d>,
+        state: &'d TcpListenerState<N, TX_SZ, RX_SZ>,
+        bufs: NonNull<([u8; TX_SZ], [u8; RX_SZ])>,
+    }
+
+    impl<'d, const N: usize, const TX_SZ: usize, const RX_SZ: usize> TcpConnection<'d, N, TX_SZ, RX_SZ> {
+        fn new<D: Driver>(stack: &'d Stack<D>, state: &'d TcpListenerState<N, TX_SZ, RX_SZ>) -> Option<Self> {
+            let mut bufs = state.pool.alloc()?;
+            Some(Self {
+                socket: unsafe { TcpSocket::new(stack, &mut bufs.as_mut().1, &mut bufs.as_mut().0) },
+                state,
+                bufs,
+            })
+        }
+
+        /// Wait until data is received, and read it into `buf`.
+        ///
+        /// Returns the number of bytes read, or 0 once the peer has closed its write half of the
+        /// connection.
+        pub async fn read(&mut self, buf: &mut [u8]) -> Result<usize, Error> {
+            self.socket.read(buf).await
+        }
+
+        /// Wait until there's room in the send buffer, and write as much of `buf` as fits.
+        ///
+        /// Returns the number of bytes written, which may be less than `buf.len()`. The data is
+        /// sent in the background, see [`flush()`](Self::flush) to wait until it's acknowledged.
+        pub async fn write(&mut self, buf: &[u8]) -> Result<usize, Error> {
+            self.socket.write(buf).await
+        }
+
+        /// Split the connection into a reader and a writer, to read and write concurrently.
+        pub fn split(&mut self) -> (TcpReader<'_>, TcpWriter<'_>) {
+            self.socket.split()
+        }
+
+        /// Reset the connection if the peer doesn't acknowledge sent data or keep-alives within
+        /// `duration`, see [`TcpSocket::set_timeout()`]. `None` disables the timeout.
+        pub fn set_timeout(&mut self, duration: Option<Duration>) {
+            self.socket.set_timeout(duration)
+        }
+
+        /// Send keep-alive packets every `interval` while the connection is idle, see
+        /// [`TcpSocket::set_keep_alive()`]. `None` disables keep-alives.
+        pub fn set_keep_alive(&mut self, interval: Option<Duration>) {
+            self.socket.set_keep_alive(interval)
+        }
+
+        /// Local address and port of the connection.
+        pub fn local_endpoint(&self) -> Option<IpEndpoint> {
+            self.socket.local_endpoint()
+        }
+
+        /// Address and port of the peer.
+        pub fn remote_endpoint(&self) -> Option<IpEndpoint> {
+            self.socket.remote_endpoint()
+        }
+
+        /// Current TCP state of the connection.
+        pub fn state(&self) -> tcp::State {
+            self.socket.state()
+        }
+
+        /// Wait until all the data written has been sent and acknowledged by the peer.
+        ///
+        /// Dropping the connection removes its socket right away, discarding any data that
+        /// hasn't been sent yet. Call this, and [`close()`](Self::close) if the peer should see
+        /// the connection closed gracefully, before dropping it.
+        pub async fn flush(&mut self) -> Result<(), Error> {
+            self.socket.io.flush().await
+        }
+
+        /// Close the write half of the connection, see [`TcpSocket::close()`].
+        pub fn close(&mut self) {
+            self.socket.close()
+        }
+
+        /// Reset the connection, see [`TcpSocket::abort()`].
+        pub fn abort(&mut self) {
+            self.socket.abort()
+        }
+    }
+
+    impl<'d, const N: usize, const TX_SZ: usize, const RX_SZ: usize> Drop for TcpConnection<'d, N, TX_SZ, RX_SZ> {
+        fn drop(&mut self) {
+            unsafe {
+                self.socket.close();
+                self.state.pool.free(self.bufs);
+            }
+            self.state.waker.wake();
+        }
+    }
+
+    #[cfg(feature = "nightly")]
+    mod embedded_io_impls {
+        use super::*;
+
+        impl<'d, const N: usize, const TX_SZ: usize, const RX_SZ: usize> embedded_io::Io
+            for TcpConnection<'d, N, TX_SZ, RX_SZ>
+        {
+            type Error = Error;
+        }
+
+        impl<'d, const N: usize, const TX_SZ: usize, const RX_SZ: usize> embedded_io::asynch::Read
+            for TcpConnection<'d, N, TX_SZ, RX_SZ>
+        {
+            async fn read(&mut self, buf: &mut [u8]) -> Result<usize, Self::Error> {
+                self.socket.read(buf).await
+            }
+        }
+
+        impl<'d, const N: usize, const TX_SZ: usize, const RX_SZ: usize> embedded_io::asynch::Write
+            for TcpConnection<'d, N, TX_SZ, RX_SZ>
+        {
+            async fn write(&mut self, buf: &[u8]) -> Result<usize, Self::Error> {
+                self.socket.write(buf).await
+            }
 
-    struct Pool<T, const N: usize> {
-        used: [AtomicBool; N],
-        data: [UnsafeCell<MaybeUninit<T>>; N],
+            async fn flush(&mut self) -> Result<(), Self::Error> {
+                self.socket.io.flush().await
+            }
+        }
     }
 
-    impl<T, const N: usize> Pool<T, N> {
-        const VALUE: AtomicBool = AtomicBool::new(false);
-        const UNINIT: UnsafeCell<MaybeUninit<T>> = UnsafeCell::new(MaybeUninit::uninit());
+    /// State for TcpListener
+    pub struct TcpListenerState<const N: usize, const TX_SZ: usize, const RX_SZ: usize> {
+        pool: Pool<([u8; TX_SZ], [u8; RX_SZ]), N>,
+        /// Woken when a connection is dropped, freeing its buffers.
+        waker: AtomicWaker,
+    }
 
-        const fn new() -> Self {
+    impl<const N: usize, const TX_SZ: usize, const RX_SZ: usize> TcpListenerState<N, TX_SZ, RX_SZ> {
+        pub const fn new() -> Self {
             Self {
-                used: [Self::VALUE; N],
-                data: [Self::UNINIT; N],
+                pool: Pool::new(),
+                waker: AtomicWaker::new(),
             }
         }
     }
 
-    impl<T, const N: usize> Pool<T, N> {
-        fn alloc(&self) -> Option<NonNull<T>> {
-            for n in 0..N {
-                if self.used[n].swap(true, Ordering::SeqCst) == false {
-                    let p = self.data[n].get() as *mut T;
-                    return Some(unsafe { NonNull::new_unchecked(p) });
-                }
+    unsafe impl<const N: usize, const TX_SZ: usize, const RX_SZ: usize> Sync for TcpListenerState<N, TX_SZ, RX_SZ> {}
+}
+
+// =======================
+
+struct Pool<T, const N: usize> {
+    used: [AtomicBool; N],
+    data: [UnsafeCell<MaybeUninit<T>>; N],
+}
+
+impl<T, const N: usize> Pool<T, N> {
+    const VALUE: AtomicBool = AtomicBool::new(false);
+    const UNINIT: UnsafeCell<MaybeUninit<T>> = UnsafeCell::new(MaybeUninit::uninit());
+
+    const fn new() -> Self {
+        Self {
+            used: [Self::VALUE; N],
+            data: [Self::UNINIT; N],
+        }
+    }
+}
+
+impl<T, const N: usize> Pool<T, N> {
+    fn alloc(&self) -> Option<NonNull<T>> {
+        for n in 0..N {
+            if self.used[n].swap(true, Ordering::SeqCst) == false {
+                let p = self.data[n].get() as *mut T;
+                return Some(unsafe { NonNull::new_unchecked(p) });
             }
-            None
         }
+        None
+    }
+
+    /// safety: p must be a pointer obtained from self.alloc that hasn't been freed yet.
+    unsafe fn free(&self, p: NonNull<T>) {
+        let origin = self.data.as_ptr() as *mut T;
+        let n = p.as_ptr().offset_from(origin);
+        assert!(n >= 0);
+        assert!((n as usize) < N);
+        self.used[n as usize].store(false, Ordering::SeqCst);
+    }
+}
+
+#[cfg(all(test, feature = "medium-ip"))]
+mod tests {
+    extern crate std;
+
+    use core::task::{Context, Waker};
+    use std::boxed::Box;
+    use std::collections::VecDeque;
+    use std::vec::Vec;
+
+    use embassy_futures::block_on;
+    use embassy_futures::join::join;
+    use embassy_futures::select::select;
+    use embassy_net_driver::{Capabilities, LinkState, Medium};
+
+    use super::server::{TcpListener, TcpListenerState};
+    use super::*;
+    use crate::{Config, Ipv4Address, Ipv4Cidr, StackResources, StaticConfig};
+
+    const ADDRESS: Ipv4Address = Ipv4Address::new(10, 0, 0, 1);
+    const PORT: u16 = 1234;
+
+    /// Driver receiving the packets it sends, so that the stack talks to itself.
+    #[derive(Default)]
+    struct Loopback {
+        packets: VecDeque<Vec<u8>>,
+        waker: Option<Waker>,
+    }
+
+    struct RxToken(Vec<u8>);
 
-        /// safety: p must be a pointer obtained from self.alloc that hasn't been freed yet.
-        unsafe fn free(&self, p: NonNull<T>) {
-            let origin = self.data.as_ptr() as *mut T;
-            let n = p.as_ptr().offset_from(origin);
-            assert!(n >= 0);
-            assert!((n as usize) < N);
-            self.used[n as usize].store(false, Ordering::SeqCst);
+    struct TxToken<'a>(&'a mut Loopback);
+
+    impl embassy_net_driver::RxToken for RxToken {
+        fn consume<R, F: FnOnce(&mut [u8]) -> R>(mut self, f: F) -> R {
+            f(&mut self.0)
         }
     }
+
+    impl<'a> embassy_net_driver::TxToken for TxToken<'a> {
+        fn consume<R, F: FnOnce(&mut [u8]) -> R>(self, len: usize, f: F) -> R {
+            let mut packet = std::vec![0; len];
+            let res = f(&mut packet);
+            self.0.packets.push_back(packet);
+            // The stack has a packet to receive.
+            if let Some(waker) = self.0.waker.take() {
+                waker.wake();
+            }
+            res
+        }
+    }
+
+    impl Driver for Loopback {
+        type RxToken<'a> = RxToken where Self: 'a;
+        type TxToken<'a> = TxToken<'a> where Self: 'a;
+
+        fn receive(&mut self, cx: &mut Context) -> Option<(Self::RxToken<'_>, Self::TxToken<'_>)> {
+            self.waker = Some(cx.waker().clone());
+            let packet = self.packets.pop_front()?;
+            Some((RxToken(packet), TxToken(self)))
+        }
+
+        fn transmit(&mut self, cx: &mut Context) -> Option<Self::TxToken<'_>> {
+            self.waker = Some(cx.waker().clone());
+            Some(TxToken(self))
+        }
+
+        fn link_state(&mut self, _cx: &mut Context) -> LinkState {
+            LinkState::Up
+        }
+
+        fn capabilities(&self) -> Capabilities {
+            let mut caps = Capabilities::default();
+            caps.medium = Medium::Ip;
+            caps.max_transmission_unit = 1500;
+            caps
+        }
+
+        fn ethernet_address(&self) -> [u8; 6] {
+            [0; 6]
+        }
+    }
+
+    fn stack() -> &'static Stack<Loopback> {
+        let config = Config::Static(StaticConfig {
+            address: Ipv4Cidr::new(ADDRESS, 24),
+            gateway: None,
+            dns_servers: heapless::Vec::new(),
+            #[cfg(feature = "proto-ipv6")]
+            ipv6_addresses: heapless::Vec::new(),
+            #[cfg(feature = "proto-ipv6")]
+            ipv6_gateway: None,
+            #[cfg(feature = "proto-ipv6")]
+            ipv6_dns_servers: heapless::Vec::new(),
+        });
+        let resources = Box::leak(Box::new(StackResources::<4>::new()));
+        Box::leak(Box::new(Stack::new(Loopback::default(), config, resources, 0)))
+    }
+
+    #[test]
+    fn listener_accepts_again_after_drop() {
+        let stack = stack();
+        // A single buffer: the listener can only accept again once the connection is dropped.
+        let state = TcpListenerState::<1, 256, 256>::new();
+        let mut listener = TcpListener::new(stack, &state, PORT, 1);
+
+        let test = async {
+            for round in 0..2u8 {
+                let mut rx_buffer = [0; 256];
+                let mut tx_buffer = [0; 256];
+                let mut client = TcpSocket::new(stack, &mut rx_buffer, &mut tx_buffer);
+
+                let (accepted, connected) = join(listener.accept(), client.connect((ADDRESS, PORT))).await;
+                let mut connection = accepted.unwrap();
+                connected.unwrap();
+                assert_eq!(connection.state(), tcp::State::Established);
+                assert_eq!(connection.local_endpoint(), Some((ADDRESS, PORT).into()));
+                assert_eq!(connection.remote_endpoint(), client.local_endpoint());
+
+                client.write(&[round]).await.unwrap();
+                let mut buf = [0; 4];
+                assert_eq!(connection.read(&mut buf).await, Ok(1));
+                assert_eq!(buf[0], round);
+
+                client.abort();
+                drop(connection);
+            }
+        };
+
+        block_on(select(stack.run(), test));
+    }
 }
@@ -255,6 +255,40 @@ impl RtcDriver {
         let f: fn(*mut ()) = unsafe { mem::transmute(alarm.callback.get()) };
         f(alarm.ctx.get());
     }
+
+    /// Program the compare channel of alarm `n` for its timestamp.
+    ///
+    /// Returns `false`, and disarms the alarm, if its timestamp has already passed.
+    fn arm_alarm(&self, n: usize, cs: CriticalSection) -> bool {
+        let r = T::regs_gp16();
+
+        let alarm = &self.alarms.borrow(cs)[n];
+        let timestamp = alarm.timestamp.get();
+
+        let t = self.now();
+        if timestamp <= t {
+            // If alarm timestamp has passed the alarm will not fire.
+            // Disarm the alarm and return `false` to indicate that.
+            unsafe { r.dier().modify(|w| w.set_ccie(n + 1, false)) };
+
+            alarm.timestamp.set(u64::MAX);
+
+            return false;
+        }
+
+        let safe_timestamp = timestamp.max(t + 3);
+
+        // Write the CCR value regardless of whether we're going to enable it now or not.
+        // This way, when we enable it later, the right value is already set.
+        unsafe { r.ccr(n + 1).write(|w| w.set_ccr(safe_timestamp as u16)) };
+
+        // Enable it if it'll happen soon. Otherwise, `next_period` will enable it.
+        let diff = timestamp - t;
+        // NOTE(unsafe) We're in a critical section
+        unsafe { r.dier().modify(|w| w.set_ccie(n + 1, diff < 0xc000)) };
+
+        true
+    }
 }
 
 impl Driver for RtcDriver {
@@ -294,35 +328,42 @@ impl Driver for RtcDriver {
 
     fn set_alarm(&self, alarm: AlarmHandle, timestamp: u64) -> bool {
         critical_section::with(|cs| {
-            let r = T::regs_gp16();
-
             let n = alarm.id() as usize;
-            let alarm = self.get_alarm(cs, alarm);
-            alarm.timestamp.set(timestamp);
-
-            let t = self.now();
-            if timestamp <= t {
-                // If alarm timestamp has passed the alarm will not fire.
-                // Disarm the alarm and return `false` to indicate that.
-                unsafe { r.dier().modify(|w| w.set_ccie(n + 1, false)) };
+            self.get_alarm(cs, alarm).timestamp.set(timestamp);
+            self.arm_alarm(n, cs)
+        })
+    }
 
-                alarm.timestamp.set(u64::MAX);
+    /// The timer doesn't count in STOP modes, move it forward by the time spent in them.
+    fn compensate_sleep(&self, ticks: u64) {
+        let r = T::regs_gp16();
 
-                return false;
+        critical_section::with(|cs| {
+            // NOTE(unsafe) Critical section to use the unsafe methods
+            unsafe {
+                // Stop the counter, so that no overflow races the update.
+                r.cr1().modify(|w| w.set_cen(false));
+
+                // The top bit of the counter always matches the parity of the period,
+                // see `calc_now`, so the low 16 bits of the new time are the new counter value.
+                let now = self.now() + ticks;
+                self.period.store((now >> 15) as u32, Ordering::Relaxed);
+                r.cnt().write(|w| w.set_cnt(now as u16));
+
+                // Pending events refer to the counter before the sleep: overflows are accounted for
+                // by the new period, and the alarms are reprogrammed below.
+                r.sr().write_value(regs::SrGp(0));
+
+                r.cr1().modify(|w| w.set_cen(true));
             }
 
-            let safe_timestamp = timestamp.max(t + 3);
-
-            // Write the CCR value regardless of whether we're going to enable it now or not.
-            // This way, when we enable it later, the right value is already set.
-            unsafe { r.ccr(n + 1).write(|w| w.set_ccr(safe_timestamp as u16)) };
-
-            // Enable it if it'll happen soon. Otherwise, `next_period` will enable it.
-            let diff = timestamp - t;
-            // NOTE(unsafe) We're in a critical section
-            unsafe { r.dier().modify(|w| w.set_ccie(n + 1, diff < 0xc000)) };
-
-            true
+            // Reprogram the alarms for the new counter value, and fire the ones that expired
+            // during the sleep.
+            for n in 0..ALARM_COUNT {
+                if self.alarms.borrow(cs)[n].timestamp.get() != u64::MAX && !self.arm_alarm(n, cs) {
+                    self.trigger_alarm(n, cs);
+                }
+            }
         })
     }
 }
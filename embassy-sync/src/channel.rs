@@ -17,6 +17,12 @@
 //! messages that it can store, and if this limit is reached, trying to send
 //! another message will result in an error being returned.
 //!
+//! A channel can be closed with [`Channel::close`] to signal the end of the
+//! stream. Sending to a closed channel fails, and receivers get an error once
+//! all messages that were sent before closing have been received.
+//!
+//! A [`ReceiverSet`] receives from whichever of several channels has a message first.
+//!
 
 use core::cell::RefCell;
 use core::future::Future;
@@ -64,12 +70,26 @@ where
     pub fn try_send(&self, message: T) -> Result<(), TrySendError<T>> {
         self.channel.try_send(message)
     }
+
+    /// Close the channel.
+    ///
+    /// See [`Channel::close()`]
+    pub fn close(&self) {
+        self.channel.close()
+    }
+
+    /// Returns whether the channel is closed.
+    ///
+    /// See [`Channel::is_closed()`]
+    pub fn is_closed(&self) -> bool {
+        self.channel.is_closed()
+    }
 }
 
 /// Send-only access to a [`Channel`] without knowing channel size.
 #[derive(Copy)]
 pub struct DynamicSender<'ch, T> {
-    channel: &'ch dyn DynamicChannel<T>,
+    pub(crate) channel: &'ch dyn DynamicChannel<T>,
 }
 
 impl<'ch, T> Clone for DynamicSender<'ch, T> {
@@ -104,6 +124,20 @@ impl<'ch, T> DynamicSender<'ch, T> {
     pub fn try_send(&self, message: T) -> Result<(), TrySendError<T>> {
         self.channel.try_send_with_context(message, None)
     }
+
+    /// Close the channel.
+    ///
+    /// See [`Channel::close()`]
+    pub fn close(&self) {
+        self.channel.close()
+    }
+
+    /// Returns whether the channel is closed.
+    ///
+    /// See [`Channel::is_closed()`]
+    pub fn is_closed(&self) -> bool {
+        self.channel.is_closed()
+    }
 }
 
 /// Receive-only access to a [`Channel`].
@@ -141,12 +175,19 @@ where
     pub fn try_recv(&self) -> Result<T, TryRecvError> {
         self.channel.try_recv()
     }
+
+    /// Returns whether the channel is closed.
+    ///
+    /// See [`Channel::is_closed()`]
+    pub fn is_closed(&self) -> bool {
+        self.channel.is_closed()
+    }
 }
 
 /// Receive-only access to a [`Channel`] without knowing channel size.
 #[derive(Copy)]
 pub struct DynamicReceiver<'ch, T> {
-    channel: &'ch dyn DynamicChannel<T>,
+    pub(crate) channel: &'ch dyn DynamicChannel<T>,
 }
 
 impl<'ch, T> Clone for DynamicReceiver<'ch, T> {
@@ -169,6 +210,13 @@ impl<'ch, T> DynamicReceiver<'ch, T> {
     pub fn try_recv(&self) -> Result<T, TryRecvError> {
         self.channel.try_recv_with_context(None)
     }
+
+    /// Returns whether the channel is closed.
+    ///
+    /// See [`Channel::is_closed()`]
+    pub fn is_closed(&self) -> bool {
+        self.channel.is_closed()
+    }
 }
 
 impl<'ch, M, T, const N: usize> From<Receiver<'ch, M, T, N>> for DynamicReceiver<'ch, T>
@@ -180,6 +228,98 @@ where
     }
 }
 
+/// Receive from whichever of several channels has a message first.
+///
+/// The receivers are [`DynamicReceiver`]s, so a set can mix channels of different sizes and kinds,
+/// such as a [`Channel`] and a [`PriorityChannel`](crate::priority_channel::PriorityChannel),
+/// as long as they carry the same message type.
+///
+/// Receiving returns the message together with the index of the receiver it came from. Receivers
+/// are checked in turn starting after the one that last produced a message, so a busy channel can't
+/// starve the others. A message is only taken out of its channel when it is returned, so none are
+/// lost when several channels have messages at the same time.
+///
+/// ```
+/// use embassy_sync::blocking_mutex::raw::NoopRawMutex;
+/// use embassy_sync::channel::{Channel, ReceiverSet};
+///
+/// let a = Channel::<NoopRawMutex, u32, 2>::new();
+/// let b = Channel::<NoopRawMutex, u32, 4>::new();
+/// let mut set = ReceiverSet::new([a.receiver().into(), b.receiver().into()]);
+///
+/// b.try_send(1).unwrap();
+/// assert_eq!(set.try_recv(), Ok((1, 1)));
+/// ```
+pub struct ReceiverSet<'ch, T, const N: usize> {
+    receivers: [DynamicReceiver<'ch, T>; N],
+    /// Index of the receiver to check first.
+    next: usize,
+}
+
+impl<'ch, T, const N: usize> ReceiverSet<'ch, T, N> {
+    /// Create a set from the given receivers.
+    pub fn new(receivers: [DynamicReceiver<'ch, T>; N]) -> Self {
+        Self { receivers, next: 0 }
+    }
+
+    /// Receive the next message from any of the receivers, with the index of its receiver.
+    ///
+    /// If all channels are empty, this method will wait until a message is sent to any of them.
+    /// Closed channels are skipped once they're empty. If all channels are closed and empty,
+    /// [`RecvError::Closed`] is returned.
+    pub fn recv(&mut self) -> ReceiverSetRecvFuture<'_, 'ch, T, N> {
+        ReceiverSetRecvFuture { set: self }
+    }
+
+    /// Attempt to immediately receive a message from any of the receivers, with the index of its receiver.
+    ///
+    /// Returns [`TryRecvError::Closed`] if all channels are closed and empty.
+    pub fn try_recv(&mut self) -> Result<(usize, T), TryRecvError> {
+        self.try_recv_with_context(None)
+    }
+
+    fn try_recv_with_context(&mut self, mut cx: Option<&mut Context<'_>>) -> Result<(usize, T), TryRecvError> {
+        let mut closed = 0;
+        for i in 0..N {
+            let index = (self.next + i) % N;
+            // Registering the waker while checking each channel means no message sent in between
+            // can be missed. Channels checked before a message is found keep the waker registered,
+            // which can only cause a spurious wakeup: it is replaced the next time the channel is polled.
+            match self.receivers[index].channel.try_recv_with_context(cx.as_deref_mut()) {
+                Ok(message) => {
+                    self.next = (index + 1) % N;
+                    return Ok((index, message));
+                }
+                Err(TryRecvError::Empty) => {}
+                Err(TryRecvError::Closed) => closed += 1,
+            }
+        }
+
+        if closed == N {
+            Err(TryRecvError::Closed)
+        } else {
+            Err(TryRecvError::Empty)
+        }
+    }
+}
+
+/// Future returned by [`ReceiverSet::recv`].
+pub struct ReceiverSetRecvFuture<'s, 'ch, T, const N: usize> {
+    set: &'s mut ReceiverSet<'ch, T, N>,
+}
+
+impl<'s, 'ch, T, const N: usize> Future for ReceiverSetRecvFuture<'s, 'ch, T, N> {
+    type Output = Result<(usize, T), RecvError>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        match self.set.try_recv_with_context(Some(cx)) {
+            Ok(v) => Poll::Ready(Ok(v)),
+            Err(TryRecvError::Empty) => Poll::Pending,
+            Err(TryRecvError::Closed) => Poll::Ready(Err(RecvError::Closed)),
+        }
+    }
+}
+
 /// Future returned by [`Channel::recv`] and  [`Receiver::recv`].
 pub struct RecvFuture<'ch, M, T, const N: usize>
 where
@@ -192,12 +332,13 @@ impl<'ch, M, T, const N: usize> Future for RecvFuture<'ch, M, T, N>
 where
     M: RawMutex,
 {
-    type Output = T;
+    type Output = Result<T, RecvError>;
 
-    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<T> {
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
         match self.channel.try_recv_with_context(Some(cx)) {
-            Ok(v) => Poll::Ready(v),
+            Ok(v) => Poll::Ready(Ok(v)),
             Err(TryRecvError::Empty) => Poll::Pending,
+            Err(TryRecvError::Closed) => Poll::Ready(Err(RecvError::Closed)),
         }
     }
 }
@@ -208,12 +349,13 @@ pub struct DynamicRecvFuture<'ch, T> {
 }
 
 impl<'ch, T> Future for DynamicRecvFuture<'ch, T> {
-    type Output = T;
+    type Output = Result<T, RecvError>;
 
-    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<T> {
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
         match self.channel.try_recv_with_context(Some(cx)) {
-            Ok(v) => Poll::Ready(v),
+            Ok(v) => Poll::Ready(Ok(v)),
             Err(TryRecvError::Empty) => Poll::Pending,
+            Err(TryRecvError::Closed) => Poll::Ready(Err(RecvError::Closed)),
         }
     }
 }
@@ -231,16 +373,17 @@ impl<'ch, M, T, const N: usize> Future for SendFuture<'ch, M, T, N>
 where
     M: RawMutex,
 {
-    type Output = ();
+    type Output = Result<(), SendError<T>>;
 
     fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
         match self.message.take() {
             Some(m) => match self.channel.try_send_with_context(m, Some(cx)) {
-                Ok(..) => Poll::Ready(()),
+                Ok(..) => Poll::Ready(Ok(())),
                 Err(TrySendError::Full(m)) => {
                     self.message = Some(m);
                     Poll::Pending
                 }
+                Err(TrySendError::Closed(m)) => Poll::Ready(Err(SendError::Closed(m))),
             },
             None => panic!("Message cannot be None"),
         }
@@ -256,16 +399,17 @@ pub struct DynamicSendFuture<'ch, T> {
 }
 
 impl<'ch, T> Future for DynamicSendFuture<'ch, T> {
-    type Output = ();
+    type Output = Result<(), SendError<T>>;
 
     fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
         match self.message.take() {
             Some(m) => match self.channel.try_send_with_context(m, Some(cx)) {
-                Ok(..) => Poll::Ready(()),
+                Ok(..) => Poll::Ready(Ok(())),
                 Err(TrySendError::Full(m)) => {
                     self.message = Some(m);
                     Poll::Pending
                 }
+                Err(TrySendError::Closed(m)) => Poll::Ready(Err(SendError::Closed(m))),
             },
             None => panic!("Message cannot be None"),
         }
@@ -274,10 +418,14 @@ impl<'ch, T> Future for DynamicSendFuture<'ch, T> {
 
 impl<'ch, T> Unpin for DynamicSendFuture<'ch, T> {}
 
-trait DynamicChannel<T> {
+pub(crate) trait DynamicChannel<T> {
     fn try_send_with_context(&self, message: T, cx: Option<&mut Context<'_>>) -> Result<(), TrySendError<T>>;
 
     fn try_recv_with_context(&self, cx: Option<&mut Context<'_>>) -> Result<T, TryRecvError>;
+
+    fn close(&self);
+
+    fn is_closed(&self) -> bool;
 }
 
 /// Error returned by [`try_recv`](Channel::try_recv).
@@ -286,6 +434,8 @@ trait DynamicChannel<T> {
 pub enum TryRecvError {
     /// A message could not be received because the channel is empty.
     Empty,
+    /// A message could not be received because the channel is empty and closed.
+    Closed,
 }
 
 /// Error returned by [`try_send`](Channel::try_send).
@@ -295,12 +445,31 @@ pub enum TrySendError<T> {
     /// The data could not be sent on the channel because the channel is
     /// currently full and sending would require blocking.
     Full(T),
+    /// The data could not be sent on the channel because the channel is closed.
+    Closed(T),
+}
+
+/// Error returned by [`recv`](Channel::recv).
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum RecvError {
+    /// The channel is closed, and all messages sent before closing it have been received.
+    Closed,
+}
+
+/// Error returned by [`send`](Channel::send).
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum SendError<T> {
+    /// The data could not be sent on the channel because the channel is closed.
+    Closed(T),
 }
 
 struct ChannelState<T, const N: usize> {
     queue: Deque<T, N>,
     receiver_waker: WakerRegistration,
     senders_waker: WakerRegistration,
+    closed: bool,
 }
 
 impl<T, const N: usize> ChannelState<T, N> {
@@ -309,6 +478,7 @@ impl<T, const N: usize> ChannelState<T, N> {
             queue: Deque::new(),
             receiver_waker: WakerRegistration::new(),
             senders_waker: WakerRegistration::new(),
+            closed: false,
         }
     }
 
@@ -323,6 +493,8 @@ impl<T, const N: usize> ChannelState<T, N> {
 
         if let Some(message) = self.queue.pop_front() {
             Ok(message)
+        } else if self.closed {
+            Err(TryRecvError::Closed)
         } else {
             if let Some(cx) = cx {
                 self.receiver_waker.register(cx.waker());
@@ -336,6 +508,10 @@ impl<T, const N: usize> ChannelState<T, N> {
     }
 
     fn try_send_with_context(&mut self, message: T, cx: Option<&mut Context<'_>>) -> Result<(), TrySendError<T>> {
+        if self.closed {
+            return Err(TrySendError::Closed(message));
+        }
+
         match self.queue.push_back(message) {
             Ok(()) => {
                 self.receiver_waker.wake();
@@ -349,6 +525,12 @@ impl<T, const N: usize> ChannelState<T, N> {
             }
         }
     }
+
+    fn close(&mut self) {
+        self.closed = true;
+        self.receiver_waker.wake();
+        self.senders_waker.wake();
+    }
 }
 
 /// A bounded channel for communicating between asynchronous tasks
@@ -411,6 +593,8 @@ where
     ///
     /// Sending completes when the value has been pushed to the channel's queue.
     /// This doesn't mean the value has been received yet.
+    ///
+    /// If the channel is closed, the value is returned in a [`SendError::Closed`].
     pub fn send(&self, message: T) -> SendFuture<'_, M, T, N> {
         SendFuture {
             channel: self,
@@ -427,7 +611,7 @@ where
     ///
     /// If the channel capacity has been reached, i.e., the channel has `n`
     /// buffered values where `n` is the argument passed to [`Channel`], then an
-    /// error is returned.
+    /// error is returned. An error is also returned if the channel is closed.
     pub fn try_send(&self, message: T) -> Result<(), TrySendError<T>> {
         self.lock(|c| c.try_send(message))
     }
@@ -435,7 +619,8 @@ where
     /// Receive the next value.
     ///
     /// If there are no messages in the channel's buffer, this method will
-    /// wait until a message is sent.
+    /// wait until a message is sent, or return [`RecvError::Closed`] if the
+    /// channel is closed.
     pub fn recv(&self) -> RecvFuture<'_, M, T, N> {
         RecvFuture { channel: self }
     }
@@ -447,6 +632,22 @@ where
     pub fn try_recv(&self) -> Result<T, TryRecvError> {
         self.lock(|c| c.try_recv())
     }
+
+    /// Close the channel.
+    ///
+    /// Sending to a closed channel fails, and all pending sends are woken up to fail.
+    /// Messages that are already in the channel can still be received; once the channel
+    /// is empty, receiving fails as well.
+    ///
+    /// A closed channel can't be reopened.
+    pub fn close(&self) {
+        self.lock(|c| c.close())
+    }
+
+    /// Returns whether the channel is closed.
+    pub fn is_closed(&self) -> bool {
+        self.lock(|c| c.closed)
+    }
 }
 
 /// Implements the DynamicChannel to allow creating types that are unaware of the queue size with the
@@ -462,6 +663,14 @@ where
     fn try_recv_with_context(&self, cx: Option<&mut Context<'_>>) -> Result<T, TryRecvError> {
         Channel::try_recv_with_context(self, cx)
     }
+
+    fn close(&self) {
+        Channel::close(self)
+    }
+
+    fn is_closed(&self) -> bool {
+        Channel::is_closed(self)
+    }
 }
 
 #[cfg(test)]
@@ -557,14 +766,14 @@ mod tests {
                 assert!(c2.try_send(1).is_ok());
             })
             .is_ok());
-        assert_eq!(c.recv().await, 1);
+        assert_eq!(c.recv().await, Ok(1));
     }
 
     #[futures_test::test]
     async fn sender_send_completes_if_capacity() {
         let c = Channel::<CriticalSectionRawMutex, u32, 1>::new();
-        c.send(1).await;
-        assert_eq!(c.recv().await, 1);
+        c.send(1).await.unwrap();
+        assert_eq!(c.recv().await, Ok(1));
     }
 
     #[futures_test::test]
@@ -576,21 +785,96 @@ mod tests {
         assert!(c.try_send(1).is_ok());
 
         let c2 = c;
-        let send_task_1 = executor.spawn_with_handle(async move { c2.send(2).await });
+        let send_task_1 = executor.spawn_with_handle(async move { c2.send(2).await.unwrap() });
         let c2 = c;
-        let send_task_2 = executor.spawn_with_handle(async move { c2.send(3).await });
+        let send_task_2 = executor.spawn_with_handle(async move { c2.send(3).await.unwrap() });
         // Wish I could think of a means of determining that the async send is waiting instead.
         // However, I've used the debugger to observe that the send does indeed wait.
         Delay::new(Duration::from_millis(500)).await;
-        assert_eq!(c.recv().await, 1);
+        assert_eq!(c.recv().await, Ok(1));
         assert!(executor
             .spawn(async move {
                 loop {
-                    c.recv().await;
+                    c.recv().await.unwrap();
                 }
             })
             .is_ok());
         send_task_1.unwrap().await;
         send_task_2.unwrap().await;
     }
+
+    #[test]
+    fn closing() {
+        let c = Channel::<NoopRawMutex, u32, 3>::new();
+        assert!(!c.is_closed());
+        assert!(c.try_send(1).is_ok());
+
+        c.sender().close();
+        assert!(c.is_closed());
+        assert!(c.receiver().is_closed());
+        assert_eq!(c.try_send(2), Err(TrySendError::Closed(2)));
+
+        // Messages sent before closing can still be received.
+        assert_eq!(c.try_recv(), Ok(1));
+        assert_eq!(c.try_recv(), Err(TryRecvError::Closed));
+    }
+
+    #[futures_test::test]
+    async fn close_wakes_receiver() {
+        let executor = ThreadPool::new().unwrap();
+
+        static CHANNEL: StaticCell<Channel<CriticalSectionRawMutex, u32, 3>> = StaticCell::new();
+        let c = &*CHANNEL.init(Channel::new());
+        let recv_task = executor.spawn_with_handle(async move { c.recv().await }).unwrap();
+        Delay::new(Duration::from_millis(100)).await;
+        c.close();
+        assert_eq!(recv_task.await, Err(RecvError::Closed));
+        assert_eq!(c.send(1).await, Err(SendError::Closed(1)));
+    }
+
+    #[test]
+    fn receiver_set_is_fair() {
+        let a = Channel::<NoopRawMutex, u32, 3>::new();
+        let b = Channel::<NoopRawMutex, u32, 3>::new();
+        let mut set = ReceiverSet::new([a.receiver().into(), b.receiver().into()]);
+        assert_eq!(set.try_recv(), Err(TryRecvError::Empty));
+
+        for i in 0..3 {
+            a.try_send(i).unwrap();
+            b.try_send(10 + i).unwrap();
+        }
+
+        // Both channels have messages, so they take turns.
+        assert_eq!(set.try_recv(), Ok((0, 0)));
+        assert_eq!(set.try_recv(), Ok((1, 10)));
+        assert_eq!(set.try_recv(), Ok((0, 1)));
+        assert_eq!(set.try_recv(), Ok((1, 11)));
+
+        a.close();
+        assert_eq!(set.try_recv(), Ok((0, 2)));
+        assert_eq!(set.try_recv(), Ok((1, 12)));
+        assert_eq!(set.try_recv(), Err(TryRecvError::Empty));
+        b.close();
+        assert_eq!(set.try_recv(), Err(TryRecvError::Closed));
+    }
+
+    #[futures_test::test]
+    async fn receiver_set_waits_for_any() {
+        let executor = ThreadPool::new().unwrap();
+
+        static A: StaticCell<Channel<CriticalSectionRawMutex, u32, 3>> = StaticCell::new();
+        static B: StaticCell<Channel<CriticalSectionRawMutex, u32, 3>> = StaticCell::new();
+        let a = &*A.init(Channel::new());
+        let b = &*B.init(Channel::new());
+        let mut set = ReceiverSet::new([a.receiver().into(), b.receiver().into()]);
+
+        executor
+            .spawn(async move {
+                Delay::new(Duration::from_millis(100)).await;
+                b.send(42).await.unwrap();
+            })
+            .unwrap();
+        assert_eq!(set.recv().await, Ok((1, 42)));
+        assert_eq!(a.try_recv(), Err(TryRecvError::Empty));
+    }
 }
@@ -15,6 +15,11 @@ pub mod blocking_mutex;
 pub mod channel;
 pub mod mutex;
 pub mod pipe;
+pub mod priority_channel;
 pub mod pubsub;
+pub mod rwlock;
+pub mod semaphore;
 pub mod signal;
 pub mod waitqueue;
+pub mod watch;
+pub mod zerocopy_channel;
@@ -1,7 +1,27 @@
 //! Async byte stream pipe.
-
-use core::cell::RefCell;
-use core::future::Future;
+//!
+//! A pipe can be closed with [`Pipe::close`] to signal the end of the stream. Writing to a
+//! closed pipe fails, and reading fails once all data written before closing has been read.
+//!
+//! With the `nightly` feature, the `embedded_io::asynch` traits are implemented as follows.
+//! Reading from a closed pipe is reported as end of file.
+//!
+//! | Type                 | `Read` | `BufRead` | `Write` |
+//! |----------------------|--------|-----------|---------|
+//! | [`Pipe`]             | yes    | yes       | yes     |
+//! | `&Pipe`              | yes    |           | yes     |
+//! | [`Reader`]           | yes    |           |         |
+//! | [`ExclusiveReader`]  | yes    | yes       |         |
+//! | [`Writer`]           |        |           | yes     |
+//!
+//! `BufRead` lends out the pipe's buffer, so it's only implemented by types that are guaranteed to
+//! be the only reader: a `Pipe` borrowed mutably, or the reader half returned by [`Pipe::split`].
+//!
+//! [`MessagePipe`] preserves the boundaries between writes instead of being a byte stream.
+
+use core::cell::{RefCell, UnsafeCell};
+use core::future::{poll_fn, Future};
+use core::ops::Range;
 use core::pin::Pin;
 use core::task::{Context, Poll};
 
@@ -45,6 +65,20 @@ where
     pub fn try_write(&self, buf: &[u8]) -> Result<usize, TryWriteError> {
         self.pipe.try_write(buf)
     }
+
+    /// Close the pipe.
+    ///
+    /// See [`Pipe::close()`]
+    pub fn close(&self) {
+        self.pipe.close()
+    }
+
+    /// Returns whether the pipe is closed.
+    ///
+    /// See [`Pipe::is_closed()`]
+    pub fn is_closed(&self) -> bool {
+        self.pipe.is_closed()
+    }
 }
 
 /// Future returned by [`Pipe::write`] and  [`Writer::write`].
@@ -60,12 +94,13 @@ impl<'p, M, const N: usize> Future for WriteFuture<'p, M, N>
 where
     M: RawMutex,
 {
-    type Output = usize;
+    type Output = Result<usize, WriteError>;
 
     fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
         match self.pipe.try_write_with_context(Some(cx), self.buf) {
-            Ok(n) => Poll::Ready(n),
+            Ok(n) => Poll::Ready(Ok(n)),
             Err(TryWriteError::Full) => Poll::Pending,
+            Err(TryWriteError::Closed) => Poll::Ready(Err(WriteError::Closed)),
         }
     }
 }
@@ -107,6 +142,73 @@ where
     pub fn try_read(&self, buf: &mut [u8]) -> Result<usize, TryReadError> {
         self.pipe.try_read(buf)
     }
+
+    /// Returns whether the pipe is closed.
+    ///
+    /// See [`Pipe::is_closed()`]
+    pub fn is_closed(&self) -> bool {
+        self.pipe.is_closed()
+    }
+}
+
+/// Read-only access to a [`Pipe`], which is guaranteed to be its only reader.
+///
+/// This is returned by [`Pipe::split`]. Being the only reader allows it to lend out the pipe's buffer
+/// with [`fill_buf`](ExclusiveReader::fill_buf), avoiding a copy.
+pub struct ExclusiveReader<'p, M, const N: usize>
+where
+    M: RawMutex,
+{
+    pipe: &'p Pipe<M, N>,
+}
+
+impl<'p, M, const N: usize> ExclusiveReader<'p, M, N>
+where
+    M: RawMutex,
+{
+    /// Reads a value.
+    ///
+    /// See [`Pipe::read()`]
+    pub fn read<'a>(&'a self, buf: &'a mut [u8]) -> ReadFuture<'a, M, N> {
+        self.pipe.read(buf)
+    }
+
+    /// Attempt to immediately read a message.
+    ///
+    /// See [`Pipe::read()`]
+    pub fn try_read(&self, buf: &mut [u8]) -> Result<usize, TryReadError> {
+        self.pipe.try_read(buf)
+    }
+
+    /// Wait until there are bytes to read, and return them without consuming them.
+    ///
+    /// See [`Pipe::fill_buf()`]
+    pub async fn fill_buf(&mut self) -> Result<&[u8], ReadError> {
+        // Safety: this is the only reader, and `clear` can't be called while the pipe is split.
+        unsafe { self.pipe.fill_buf_inner().await }
+    }
+
+    /// Attempt to immediately get the bytes available for reading, without consuming them.
+    ///
+    /// See [`Pipe::try_fill_buf()`]
+    pub fn try_fill_buf(&mut self) -> Result<&[u8], TryReadError> {
+        // Safety: this is the only reader, and `clear` can't be called while the pipe is split.
+        unsafe { self.pipe.try_fill_buf_with_context(None) }
+    }
+
+    /// Mark `amt` bytes returned by [`fill_buf`](ExclusiveReader::fill_buf) as read.
+    ///
+    /// See [`Pipe::consume()`]
+    pub fn consume(&mut self, amt: usize) {
+        self.pipe.consume_inner(amt)
+    }
+
+    /// Returns whether the pipe is closed.
+    ///
+    /// See [`Pipe::is_closed()`]
+    pub fn is_closed(&self) -> bool {
+        self.pipe.is_closed()
+    }
 }
 
 /// Future returned by [`Pipe::read`] and  [`Reader::read`].
@@ -122,12 +224,13 @@ impl<'p, M, const N: usize> Future for ReadFuture<'p, M, N>
 where
     M: RawMutex,
 {
-    type Output = usize;
+    type Output = Result<usize, ReadError>;
 
     fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
         match self.pipe.try_read_with_context(Some(cx), self.buf) {
-            Ok(n) => Poll::Ready(n),
+            Ok(n) => Poll::Ready(Ok(n)),
             Err(TryReadError::Empty) => Poll::Pending,
+            Err(TryReadError::Closed) => Poll::Ready(Err(ReadError::Closed)),
         }
     }
 }
@@ -141,6 +244,8 @@ pub enum TryReadError {
     /// No data could be read from the pipe because it is currently
     /// empty, and reading would require blocking.
     Empty,
+    /// No data could be read from the pipe because it is empty and closed.
+    Closed,
 }
 
 /// Error returned by [`try_write`](Pipe::try_write).
@@ -150,12 +255,64 @@ pub enum TryWriteError {
     /// No data could be written to the pipe because it is
     /// currently full, and writing would require blocking.
     Full,
+    /// No data could be written to the pipe because it is closed.
+    Closed,
+}
+
+/// Error returned by [`read`](Pipe::read).
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum ReadError {
+    /// The pipe is closed, and all data written before closing it has been read.
+    Closed,
+}
+
+/// Error returned by [`write`](Pipe::write).
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum WriteError {
+    /// No data could be written to the pipe because it is closed.
+    Closed,
+}
+
+/// Byte storage of a pipe.
+///
+/// It is kept outside of the pipe's mutex so that [`Pipe::fill_buf`] can lend out a part of it.
+/// Accesses don't overlap: the ring buffer indices, which are behind the mutex, only hand out
+/// the filled part to readers and the free part to writers.
+struct Buffer<const N: usize>(UnsafeCell<[u8; N]>);
+
+impl<const N: usize> Buffer<N> {
+    const fn new() -> Self {
+        Self(UnsafeCell::new([0; N]))
+    }
+
+    /// # Safety
+    ///
+    /// No mutable reference to bytes in `r` may exist.
+    unsafe fn get(&self, r: Range<usize>) -> &[u8] {
+        let p = self.0.get() as *const u8;
+        core::slice::from_raw_parts(p.add(r.start), r.end - r.start)
+    }
+
+    /// # Safety
+    ///
+    /// No other reference to bytes in `r` may exist.
+    #[allow(clippy::mut_from_ref)]
+    unsafe fn get_mut(&self, r: Range<usize>) -> &mut [u8] {
+        let p = self.0.get() as *mut u8;
+        core::slice::from_raw_parts_mut(p.add(r.start), r.end - r.start)
+    }
 }
 
+unsafe impl<const N: usize> Send for Buffer<N> {}
+unsafe impl<const N: usize> Sync for Buffer<N> {}
+
 struct PipeState<const N: usize> {
     buffer: RingBuffer<N>,
     read_waker: WakerRegistration,
     write_waker: WakerRegistration,
+    closed: bool,
 }
 
 impl<const N: usize> PipeState<N> {
@@ -164,6 +321,7 @@ impl<const N: usize> PipeState<N> {
             buffer: RingBuffer::new(),
             read_waker: WakerRegistration::new(),
             write_waker: WakerRegistration::new(),
+            closed: false,
         }
     }
 
@@ -172,34 +330,31 @@ impl<const N: usize> PipeState<N> {
         self.write_waker.wake();
     }
 
-    fn try_read(&mut self, buf: &mut [u8]) -> Result<usize, TryReadError> {
-        self.try_read_with_context(None, buf)
-    }
-
-    fn try_read_with_context(&mut self, cx: Option<&mut Context<'_>>, buf: &mut [u8]) -> Result<usize, TryReadError> {
+    /// Returns the contiguous range of bytes available for reading, or an error if there are none.
+    fn poll_pop_buf(&mut self, cx: Option<&mut Context<'_>>) -> Result<Range<usize>, TryReadError> {
         if self.buffer.is_full() {
             self.write_waker.wake();
         }
 
         let available = self.buffer.pop_buf();
         if available.is_empty() {
+            if self.closed {
+                return Err(TryReadError::Closed);
+            }
             if let Some(cx) = cx {
                 self.read_waker.register(cx.waker());
             }
             return Err(TryReadError::Empty);
         }
-
-        let n = available.len().min(buf.len());
-        buf[..n].copy_from_slice(&available[..n]);
-        self.buffer.pop(n);
-        Ok(n)
+        Ok(available)
     }
 
-    fn try_write(&mut self, buf: &[u8]) -> Result<usize, TryWriteError> {
-        self.try_write_with_context(None, buf)
-    }
+    /// Returns the contiguous range of bytes available for writing, or an error if there are none.
+    fn poll_push_buf(&mut self, cx: Option<&mut Context<'_>>) -> Result<Range<usize>, TryWriteError> {
+        if self.closed {
+            return Err(TryWriteError::Closed);
+        }
 
-    fn try_write_with_context(&mut self, cx: Option<&mut Context<'_>>, buf: &[u8]) -> Result<usize, TryWriteError> {
         if self.buffer.is_empty() {
             self.read_waker.wake();
         }
@@ -211,11 +366,13 @@ impl<const N: usize> PipeState<N> {
             }
             return Err(TryWriteError::Full);
         }
+        Ok(available)
+    }
 
-        let n = available.len().min(buf.len());
-        available[..n].copy_from_slice(&buf[..n]);
-        self.buffer.push(n);
-        Ok(n)
+    fn close(&mut self) {
+        self.closed = true;
+        self.read_waker.wake();
+        self.write_waker.wake();
     }
 }
 
@@ -231,6 +388,7 @@ pub struct Pipe<M, const N: usize>
 where
     M: RawMutex,
 {
+    buf: Buffer<N>,
     inner: Mutex<M, RefCell<PipeState<N>>>,
 }
 
@@ -249,20 +407,68 @@ where
     /// ```
     pub const fn new() -> Self {
         Self {
+            buf: Buffer::new(),
             inner: Mutex::new(RefCell::new(PipeState::new())),
         }
     }
 
     fn lock<R>(&self, f: impl FnOnce(&mut PipeState<N>) -> R) -> R {
-        self.inner.lock(|rc| f(&mut *rc.borrow_mut()))
+        self.inner.lock(|rc| f(&mut rc.borrow_mut()))
     }
 
     fn try_read_with_context(&self, cx: Option<&mut Context<'_>>, buf: &mut [u8]) -> Result<usize, TryReadError> {
-        self.lock(|c| c.try_read_with_context(cx, buf))
+        self.lock(|c| {
+            let available = c.poll_pop_buf(cx)?;
+            let n = available.len().min(buf.len());
+            // Safety: the range is only handed out to readers, which only access it while holding the lock,
+            // or through `fill_buf`, which requires exclusive access to the pipe.
+            buf[..n].copy_from_slice(unsafe { self.buf.get(available.start..available.start + n) });
+            c.buffer.pop(n);
+            Ok(n)
+        })
     }
 
     fn try_write_with_context(&self, cx: Option<&mut Context<'_>>, buf: &[u8]) -> Result<usize, TryWriteError> {
-        self.lock(|c| c.try_write_with_context(cx, buf))
+        self.lock(|c| {
+            let available = c.poll_push_buf(cx)?;
+            let n = available.len().min(buf.len());
+            // Safety: the free range is only accessed by writers, while holding the lock.
+            unsafe { self.buf.get_mut(available.start..available.start + n) }.copy_from_slice(&buf[..n]);
+            c.buffer.push(n);
+            Ok(n)
+        })
+    }
+
+    /// Returns the bytes available for reading, without consuming them.
+    ///
+    /// # Safety
+    ///
+    /// The caller must be the only reader of the pipe, and must not call `clear` while the returned
+    /// slice is alive.
+    unsafe fn try_fill_buf_with_context(&self, cx: Option<&mut Context<'_>>) -> Result<&[u8], TryReadError> {
+        let available = self.lock(|c| c.poll_pop_buf(cx))?;
+        // Safety: writers don't touch the filled part of the buffer, and the caller
+        // guarantees there are no other readers.
+        Ok(self.buf.get(available))
+    }
+
+    fn consume_inner(&self, amt: usize) {
+        self.lock(|c| {
+            let available = c.buffer.pop_buf();
+            assert!(amt <= available.len());
+            if c.buffer.is_full() {
+                c.write_waker.wake();
+            }
+            c.buffer.pop(amt);
+        })
+    }
+
+    /// Split the pipe into a reader that can lend out its buffer, and a writer.
+    ///
+    /// Unlike [`Reader`], the returned [`ExclusiveReader`] implements [`fill_buf`](ExclusiveReader::fill_buf)
+    /// and [`consume`](ExclusiveReader::consume), because it is guaranteed to be the only reader of the pipe.
+    pub fn split(&mut self) -> (ExclusiveReader<'_, M, N>, Writer<'_, M, N>) {
+        (ExclusiveReader { pipe: self }, Writer { pipe: self })
     }
 
     /// Get a writer for this pipe.
@@ -279,6 +485,8 @@ where
     ///
     /// Writeing completes when the value has been pushed to the pipe's queue.
     /// This doesn't mean the value has been read yet.
+    ///
+    /// If the pipe is closed, [`WriteError::Closed`] is returned.
     pub fn write<'a>(&'a self, buf: &'a [u8]) -> WriteFuture<'a, M, N> {
         WriteFuture { pipe: self, buf }
     }
@@ -292,15 +500,16 @@ where
     ///
     /// If the pipe capacity has been reached, i.e., the pipe has `n`
     /// buffered values where `n` is the argument passed to [`Pipe`], then an
-    /// error is returned.
+    /// error is returned. An error is also returned if the pipe is closed.
     pub fn try_write(&self, buf: &[u8]) -> Result<usize, TryWriteError> {
-        self.lock(|c| c.try_write(buf))
+        self.try_write_with_context(None, buf)
     }
 
     /// Receive the next value.
     ///
     /// If there are no messages in the pipe's buffer, this method will
-    /// wait until a message is written.
+    /// wait until a message is written, or return [`ReadError::Closed`] if the
+    /// pipe is closed.
     pub fn read<'a>(&'a self, buf: &'a mut [u8]) -> ReadFuture<'a, M, N> {
         ReadFuture { pipe: self, buf }
     }
@@ -310,7 +519,48 @@ where
     /// This method will either read a message from the pipe immediately or return an error
     /// if the pipe is empty.
     pub fn try_read(&self, buf: &mut [u8]) -> Result<usize, TryReadError> {
-        self.lock(|c| c.try_read(buf))
+        self.try_read_with_context(None, buf)
+    }
+
+    /// Wait until there are bytes to read, and return them without consuming them.
+    ///
+    /// Call [`consume`](Pipe::consume) afterwards to mark (part of) the bytes as read.
+    /// The returned slice may contain fewer bytes than are buffered, when the buffered
+    /// data wraps around the end of the ring buffer.
+    ///
+    /// If the pipe is empty and closed, [`ReadError::Closed`] is returned.
+    pub async fn fill_buf(&mut self) -> Result<&[u8], ReadError> {
+        // Safety: `&mut self` guarantees there are no other readers, and that `clear` isn't called.
+        unsafe { self.fill_buf_inner().await }
+    }
+
+    /// # Safety
+    ///
+    /// See [`try_fill_buf_with_context`](Pipe::try_fill_buf_with_context).
+    async unsafe fn fill_buf_inner(&self) -> Result<&[u8], ReadError> {
+        poll_fn(|cx| match self.try_fill_buf_with_context(Some(cx)) {
+            Ok(buf) => Poll::Ready(Ok(buf)),
+            Err(TryReadError::Empty) => Poll::Pending,
+            Err(TryReadError::Closed) => Poll::Ready(Err(ReadError::Closed)),
+        })
+        .await
+    }
+
+    /// Attempt to immediately get the bytes available for reading, without consuming them.
+    ///
+    /// See [`fill_buf`](Pipe::fill_buf).
+    pub fn try_fill_buf(&mut self) -> Result<&[u8], TryReadError> {
+        // Safety: `&mut self` guarantees there are no other readers, and that `clear` isn't called.
+        unsafe { self.try_fill_buf_with_context(None) }
+    }
+
+    /// Mark `amt` bytes returned by [`fill_buf`](Pipe::fill_buf) as read.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `amt` is larger than the slice returned by `fill_buf`.
+    pub fn consume(&mut self, amt: usize) {
+        self.consume_inner(amt)
     }
 
     /// Clear the data in the pipe's buffer.
@@ -318,6 +568,22 @@ where
         self.lock(|c| c.clear())
     }
 
+    /// Close the pipe.
+    ///
+    /// Writing to a closed pipe fails, and all pending writes are woken up to fail.
+    /// Data that is already in the pipe can still be read; once the pipe is empty,
+    /// reading fails as well.
+    ///
+    /// A closed pipe can't be reopened.
+    pub fn close(&self) {
+        self.lock(|c| c.close())
+    }
+
+    /// Returns whether the pipe is closed.
+    pub fn is_closed(&self) -> bool {
+        self.lock(|c| c.closed)
+    }
+
     /// Return whether the pipe is full (no free space in the buffer)
     pub fn is_full(&self) -> bool {
         self.len() == N
@@ -348,25 +614,259 @@ where
     }
 }
 
+/// Length of the prefix stored in front of each message of a [`MessagePipe`].
+const HEADER_LEN: usize = 2;
+
+struct MessagePipeState<const N: usize> {
+    buffer: RingBuffer<N>,
+    read_waker: WakerRegistration,
+    write_waker: WakerRegistration,
+    closed: bool,
+}
+
+/// A bounded pipe for sending messages of varying length between asynchronous tasks,
+/// with backpressure.
+///
+/// Unlike [`Pipe`], which is a byte stream, a message pipe preserves the boundaries between writes:
+/// each [`read`](MessagePipe::read) returns exactly one message that was passed to
+/// [`write`](MessagePipe::write).
+///
+/// Messages are stored in a ring buffer of `N` bytes, each prefixed with its length as a
+/// little-endian `u16`. A message of `n` bytes therefore takes `n + 2` bytes of the buffer.
+///
+/// ```
+/// use embassy_sync::blocking_mutex::raw::NoopRawMutex;
+/// use embassy_sync::pipe::MessagePipe;
+///
+/// let pipe = MessagePipe::<NoopRawMutex, 32>::new();
+/// pipe.try_write(b"hello").unwrap();
+/// pipe.try_write(b"world").unwrap();
+///
+/// let mut buf = [0; 16];
+/// assert_eq!(pipe.try_read(&mut buf), Ok(5));
+/// assert_eq!(&buf[..5], b"hello");
+/// ```
+pub struct MessagePipe<M, const N: usize>
+where
+    M: RawMutex,
+{
+    buf: Buffer<N>,
+    inner: Mutex<M, RefCell<MessagePipeState<N>>>,
+}
+
+impl<M, const N: usize> MessagePipe<M, N>
+where
+    M: RawMutex,
+{
+    /// Establish a new bounded message pipe, with a buffer of `N` bytes.
+    pub const fn new() -> Self {
+        Self {
+            buf: Buffer::new(),
+            inner: Mutex::new(RefCell::new(MessagePipeState {
+                buffer: RingBuffer::new(),
+                read_waker: WakerRegistration::new(),
+                write_waker: WakerRegistration::new(),
+                closed: false,
+            })),
+        }
+    }
+
+    fn lock<R>(&self, f: impl FnOnce(&mut MessagePipeState<N>) -> R) -> R {
+        self.inner.lock(|rc| f(&mut rc.borrow_mut()))
+    }
+
+    /// Copy `data` into the free part of the ring buffer, which must be large enough.
+    fn push_bytes(&self, buffer: &mut RingBuffer<N>, mut data: &[u8]) {
+        while !data.is_empty() {
+            let available = buffer.push_buf();
+            let n = available.len().min(data.len());
+            // Safety: the free range is only accessed by writers, while holding the lock.
+            unsafe { self.buf.get_mut(available.start..available.start + n) }.copy_from_slice(&data[..n]);
+            buffer.push(n);
+            data = &data[n..];
+        }
+    }
+
+    /// Remove `len` bytes from the ring buffer, copying as many of them as fit into `out`.
+    fn pop_bytes(&self, buffer: &mut RingBuffer<N>, mut out: &mut [u8], mut len: usize) {
+        while len != 0 {
+            let available = buffer.pop_buf();
+            let n = available.len().min(len);
+            let copy = n.min(out.len());
+            // Safety: the filled range is only accessed by readers, while holding the lock.
+            out[..copy].copy_from_slice(unsafe { self.buf.get(available.start..available.start + copy) });
+            out = &mut out[copy..];
+            buffer.pop(n);
+            len -= n;
+        }
+    }
+
+    fn try_write_with_context(&self, cx: Option<&mut Context<'_>>, message: &[u8]) -> Result<(), TryWriteError> {
+        assert!(
+            message.len() + HEADER_LEN <= N && message.len() <= u16::MAX as usize,
+            "message doesn't fit in the pipe"
+        );
+
+        self.lock(|s| {
+            if s.closed {
+                return Err(TryWriteError::Closed);
+            }
+
+            if N - s.buffer.len() < message.len() + HEADER_LEN {
+                if let Some(cx) = cx {
+                    s.write_waker.register(cx.waker());
+                }
+                return Err(TryWriteError::Full);
+            }
+
+            self.push_bytes(&mut s.buffer, &(message.len() as u16).to_le_bytes());
+            self.push_bytes(&mut s.buffer, message);
+            s.read_waker.wake();
+            Ok(())
+        })
+    }
+
+    fn try_read_with_context(&self, cx: Option<&mut Context<'_>>, buf: &mut [u8]) -> Result<usize, TryReadError> {
+        self.lock(|s| {
+            if s.buffer.is_empty() {
+                if s.closed {
+                    return Err(TryReadError::Closed);
+                }
+                if let Some(cx) = cx {
+                    s.read_waker.register(cx.waker());
+                }
+                return Err(TryReadError::Empty);
+            }
+
+            let mut header = [0; HEADER_LEN];
+            self.pop_bytes(&mut s.buffer, &mut header, HEADER_LEN);
+            let len = u16::from_le_bytes(header) as usize;
+            self.pop_bytes(&mut s.buffer, buf, len);
+            s.write_waker.wake();
+            Ok(len)
+        })
+    }
+
+    /// Write a message, waiting until there is room for all of it.
+    ///
+    /// If the pipe is closed, [`WriteError::Closed`] is returned.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the message can never fit, i.e. if it is longer than `N - 2` bytes.
+    pub async fn write(&self, message: &[u8]) -> Result<(), WriteError> {
+        poll_fn(|cx| match self.try_write_with_context(Some(cx), message) {
+            Ok(()) => Poll::Ready(Ok(())),
+            Err(TryWriteError::Full) => Poll::Pending,
+            Err(TryWriteError::Closed) => Poll::Ready(Err(WriteError::Closed)),
+        })
+        .await
+    }
+
+    /// Attempt to immediately write a message.
+    ///
+    /// A message is either written completely, or not at all.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the message can never fit, i.e. if it is longer than `N - 2` bytes.
+    pub fn try_write(&self, message: &[u8]) -> Result<(), TryWriteError> {
+        self.try_write_with_context(None, message)
+    }
+
+    /// Read the next message into `buf`, waiting until there is one.
+    ///
+    /// Returns the length of the message. If the message is longer than `buf`, only the
+    /// first `buf.len()` bytes are copied, and the rest of the message is discarded.
+    ///
+    /// If the pipe is empty and closed, [`ReadError::Closed`] is returned.
+    pub async fn read(&self, buf: &mut [u8]) -> Result<usize, ReadError> {
+        poll_fn(|cx| match self.try_read_with_context(Some(cx), buf) {
+            Ok(n) => Poll::Ready(Ok(n)),
+            Err(TryReadError::Empty) => Poll::Pending,
+            Err(TryReadError::Closed) => Poll::Ready(Err(ReadError::Closed)),
+        })
+        .await
+    }
+
+    /// Attempt to immediately read the next message.
+    ///
+    /// See [`read`](MessagePipe::read) for how messages longer than `buf` are handled.
+    pub fn try_read(&self, buf: &mut [u8]) -> Result<usize, TryReadError> {
+        self.try_read_with_context(None, buf)
+    }
+
+    /// Clear all messages in the pipe's buffer.
+    pub fn clear(&self) {
+        self.lock(|s| {
+            s.buffer.clear();
+            s.write_waker.wake();
+        })
+    }
+
+    /// Close the pipe.
+    ///
+    /// See [`Pipe::close()`]
+    pub fn close(&self) {
+        self.lock(|s| {
+            s.closed = true;
+            s.read_waker.wake();
+            s.write_waker.wake();
+        })
+    }
+
+    /// Returns whether the pipe is closed.
+    pub fn is_closed(&self) -> bool {
+        self.lock(|s| s.closed)
+    }
+
+    /// Return whether the pipe is empty (no messages buffered)
+    pub fn is_empty(&self) -> bool {
+        self.lock(|s| s.buffer.is_empty())
+    }
+
+    /// Free byte capacity, including the room needed for length prefixes.
+    pub fn free_capacity(&self) -> usize {
+        N - self.lock(|s| s.buffer.len())
+    }
+}
+
 #[cfg(feature = "nightly")]
 mod io_impls {
     use core::convert::Infallible;
 
     use super::*;
 
+    // Reading from a closed pipe is reported as end of file, so only writing can fail.
+    impl embedded_io::Error for WriteError {
+        fn kind(&self) -> embedded_io::ErrorKind {
+            embedded_io::ErrorKind::Other
+        }
+    }
+
     impl<M: RawMutex, const N: usize> embedded_io::Io for Pipe<M, N> {
-        type Error = Infallible;
+        type Error = WriteError;
     }
 
     impl<M: RawMutex, const N: usize> embedded_io::asynch::Read for Pipe<M, N> {
         async fn read(&mut self, buf: &mut [u8]) -> Result<usize, Self::Error> {
-            Ok(Pipe::read(self, buf).await)
+            Ok(Pipe::read(self, buf).await.unwrap_or(0))
+        }
+    }
+
+    impl<M: RawMutex, const N: usize> embedded_io::asynch::BufRead for Pipe<M, N> {
+        async fn fill_buf(&mut self) -> Result<&[u8], Self::Error> {
+            Ok(Pipe::fill_buf(self).await.unwrap_or(&[]))
+        }
+
+        fn consume(&mut self, amt: usize) {
+            Pipe::consume(self, amt)
         }
     }
 
     impl<M: RawMutex, const N: usize> embedded_io::asynch::Write for Pipe<M, N> {
         async fn write(&mut self, buf: &[u8]) -> Result<usize, Self::Error> {
-            Ok(Pipe::write(self, buf).await)
+            Pipe::write(self, buf).await
         }
 
         async fn flush(&mut self) -> Result<(), Self::Error> {
@@ -375,18 +875,18 @@ mod io_impls {
     }
 
     impl<M: RawMutex, const N: usize> embedded_io::Io for &Pipe<M, N> {
-        type Error = Infallible;
+        type Error = WriteError;
     }
 
     impl<M: RawMutex, const N: usize> embedded_io::asynch::Read for &Pipe<M, N> {
         async fn read(&mut self, buf: &mut [u8]) -> Result<usize, Self::Error> {
-            Ok(Pipe::read(self, buf).await)
+            Ok(Pipe::read(self, buf).await.unwrap_or(0))
         }
     }
 
     impl<M: RawMutex, const N: usize> embedded_io::asynch::Write for &Pipe<M, N> {
         async fn write(&mut self, buf: &[u8]) -> Result<usize, Self::Error> {
-            Ok(Pipe::write(self, buf).await)
+            Pipe::write(self, buf).await
         }
 
         async fn flush(&mut self) -> Result<(), Self::Error> {
@@ -400,17 +900,37 @@ mod io_impls {
 
     impl<M: RawMutex, const N: usize> embedded_io::asynch::Read for Reader<'_, M, N> {
         async fn read(&mut self, buf: &mut [u8]) -> Result<usize, Self::Error> {
-            Ok(Reader::read(self, buf).await)
+            Ok(Reader::read(self, buf).await.unwrap_or(0))
         }
     }
 
-    impl<M: RawMutex, const N: usize> embedded_io::Io for Writer<'_, M, N> {
+    impl<M: RawMutex, const N: usize> embedded_io::Io for ExclusiveReader<'_, M, N> {
         type Error = Infallible;
     }
 
+    impl<M: RawMutex, const N: usize> embedded_io::asynch::Read for ExclusiveReader<'_, M, N> {
+        async fn read(&mut self, buf: &mut [u8]) -> Result<usize, Self::Error> {
+            Ok(ExclusiveReader::read(self, buf).await.unwrap_or(0))
+        }
+    }
+
+    impl<M: RawMutex, const N: usize> embedded_io::asynch::BufRead for ExclusiveReader<'_, M, N> {
+        async fn fill_buf(&mut self) -> Result<&[u8], Self::Error> {
+            Ok(ExclusiveReader::fill_buf(self).await.unwrap_or(&[]))
+        }
+
+        fn consume(&mut self, amt: usize) {
+            ExclusiveReader::consume(self, amt)
+        }
+    }
+
+    impl<M: RawMutex, const N: usize> embedded_io::Io for Writer<'_, M, N> {
+        type Error = WriteError;
+    }
+
     impl<M: RawMutex, const N: usize> embedded_io::asynch::Write for Writer<'_, M, N> {
         async fn write(&mut self, buf: &[u8]) -> Result<usize, Self::Error> {
-            Ok(Writer::write(self, buf).await)
+            Writer::write(self, buf).await
         }
 
         async fn flush(&mut self) -> Result<(), Self::Error> {
@@ -428,20 +948,20 @@ mod tests {
     use super::*;
     use crate::blocking_mutex::raw::{CriticalSectionRawMutex, NoopRawMutex};
 
-    fn capacity<const N: usize>(c: &PipeState<N>) -> usize {
-        N - c.buffer.len()
+    fn capacity<M: RawMutex, const N: usize>(c: &Pipe<M, N>) -> usize {
+        c.free_capacity()
     }
 
     #[test]
     fn writing_once() {
-        let mut c = PipeState::<3>::new();
+        let c = Pipe::<NoopRawMutex, 3>::new();
         assert!(c.try_write(&[1]).is_ok());
         assert_eq!(capacity(&c), 2);
     }
 
     #[test]
     fn writing_when_full() {
-        let mut c = PipeState::<3>::new();
+        let c = Pipe::<NoopRawMutex, 3>::new();
         assert_eq!(c.try_write(&[42]), Ok(1));
         assert_eq!(c.try_write(&[43]), Ok(1));
         assert_eq!(c.try_write(&[44]), Ok(1));
@@ -451,7 +971,7 @@ mod tests {
 
     #[test]
     fn receiving_once_with_one_send() {
-        let mut c = PipeState::<3>::new();
+        let c = Pipe::<NoopRawMutex, 3>::new();
         assert!(c.try_write(&[42]).is_ok());
         let mut buf = [0; 16];
         assert_eq!(c.try_read(&mut buf), Ok(1));
@@ -461,7 +981,7 @@ mod tests {
 
     #[test]
     fn receiving_when_empty() {
-        let mut c = PipeState::<3>::new();
+        let c = Pipe::<NoopRawMutex, 3>::new();
         let mut buf = [0; 16];
         assert_eq!(c.try_read(&mut buf), Err(TryReadError::Empty));
         assert_eq!(capacity(&c), 3);
@@ -498,16 +1018,150 @@ mod tests {
         };
         executor.spawn(f).unwrap();
         let mut buf = [0; 16];
-        assert_eq!(c.read(&mut buf).await, 1);
+        assert_eq!(c.read(&mut buf).await, Ok(1));
         assert_eq!(buf[0], 42);
     }
 
     #[futures_test::test]
     async fn sender_send_completes_if_capacity() {
         let c = Pipe::<CriticalSectionRawMutex, 1>::new();
-        c.write(&[42]).await;
+        assert_eq!(c.write(&[42]).await, Ok(1));
+        let mut buf = [0; 16];
+        assert_eq!(c.read(&mut buf).await, Ok(1));
+        assert_eq!(buf[0], 42);
+    }
+
+    #[test]
+    fn closing() {
+        let c = Pipe::<NoopRawMutex, 3>::new();
+        assert_eq!(c.try_write(&[42]), Ok(1));
+
+        c.writer().close();
+        assert!(c.is_closed());
+        assert!(c.reader().is_closed());
+        assert_eq!(c.try_write(&[43]), Err(TryWriteError::Closed));
+
+        // Data written before closing can still be read.
         let mut buf = [0; 16];
-        assert_eq!(c.read(&mut buf).await, 1);
+        assert_eq!(c.try_read(&mut buf), Ok(1));
         assert_eq!(buf[0], 42);
+        assert_eq!(c.try_read(&mut buf), Err(TryReadError::Closed));
+    }
+
+    #[futures_test::test]
+    async fn close_wakes_reader() {
+        let executor = ThreadPool::new().unwrap();
+
+        static PIPE: StaticCell<Pipe<CriticalSectionRawMutex, 3>> = StaticCell::new();
+        let c = &*PIPE.init(Pipe::new());
+        let read_task = executor
+            .spawn_with_handle(async move {
+                let mut buf = [0; 16];
+                c.read(&mut buf).await
+            })
+            .unwrap();
+        c.close();
+        assert_eq!(read_task.await, Err(ReadError::Closed));
+        assert_eq!(c.write(&[42]).await, Err(WriteError::Closed));
+    }
+
+    #[test]
+    fn fill_buf_and_consume() {
+        let mut c = Pipe::<NoopRawMutex, 4>::new();
+        assert_eq!(c.try_fill_buf(), Err(TryReadError::Empty));
+        assert_eq!(c.try_write(&[1, 2, 3]), Ok(3));
+
+        assert_eq!(c.try_fill_buf(), Ok(&[1, 2, 3][..]));
+        c.consume(2);
+        assert_eq!(c.try_fill_buf(), Ok(&[3][..]));
+
+        // The data wraps around the end of the ring buffer, only the contiguous part is returned.
+        assert_eq!(c.try_write(&[4, 5]), Ok(1));
+        assert_eq!(c.try_write(&[5]), Ok(1));
+        assert_eq!(c.try_fill_buf(), Ok(&[3, 4][..]));
+        c.consume(2);
+        assert_eq!(c.try_fill_buf(), Ok(&[5][..]));
+        c.consume(1);
+        assert_eq!(c.try_fill_buf(), Err(TryReadError::Empty));
+    }
+
+    #[futures_test::test]
+    async fn split_fill_buf() {
+        let mut c = Pipe::<NoopRawMutex, 4>::new();
+        let (mut r, w) = c.split();
+        assert_eq!(w.write(&[1, 2]).await, Ok(2));
+        assert_eq!(r.fill_buf().await, Ok(&[1, 2][..]));
+        r.consume(1);
+        w.close();
+        assert_eq!(r.fill_buf().await, Ok(&[2][..]));
+        r.consume(1);
+        assert_eq!(r.fill_buf().await, Err(ReadError::Closed));
+    }
+
+    #[test]
+    fn message_boundaries() {
+        let c = MessagePipe::<NoopRawMutex, 10>::new();
+        assert_eq!(c.try_write(&[1, 2, 3]), Ok(()));
+        assert_eq!(c.try_write(&[4]), Ok(()));
+        // Messages are written completely or not at all.
+        assert_eq!(c.try_write(&[5, 6]), Err(TryWriteError::Full));
+        assert_eq!(c.free_capacity(), 2);
+
+        let mut buf = [0; 16];
+        assert_eq!(c.try_read(&mut buf), Ok(3));
+        assert_eq!(&buf[..3], &[1, 2, 3]);
+
+        // This message wraps around the end of the ring buffer.
+        assert_eq!(c.try_write(&[5, 6, 7]), Ok(()));
+
+        assert_eq!(c.try_read(&mut buf), Ok(1));
+        assert_eq!(buf[0], 4);
+        assert_eq!(c.try_read(&mut buf), Ok(3));
+        assert_eq!(&buf[..3], &[5, 6, 7]);
+        assert_eq!(c.try_read(&mut buf), Err(TryReadError::Empty));
+    }
+
+    #[test]
+    fn message_truncated() {
+        let c = MessagePipe::<NoopRawMutex, 10>::new();
+        assert_eq!(c.try_write(&[1, 2, 3]), Ok(()));
+        assert_eq!(c.try_write(&[4]), Ok(()));
+
+        let mut buf = [0; 2];
+        assert_eq!(c.try_read(&mut buf), Ok(3));
+        assert_eq!(buf, [1, 2]);
+        // The rest of the message was discarded.
+        assert_eq!(c.try_read(&mut buf), Ok(1));
+        assert_eq!(buf[0], 4);
+    }
+
+    #[test]
+    #[should_panic]
+    fn message_too_large() {
+        let c = MessagePipe::<NoopRawMutex, 4>::new();
+        let _ = c.try_write(&[1, 2, 3]);
+    }
+
+    #[futures_test::test]
+    async fn message_pipe_async() {
+        let executor = ThreadPool::new().unwrap();
+
+        static PIPE: StaticCell<MessagePipe<CriticalSectionRawMutex, 8>> = StaticCell::new();
+        let c = &*PIPE.init(MessagePipe::new());
+        executor
+            .spawn(async move {
+                for i in 0..4u8 {
+                    c.write(&[i; 5]).await.unwrap();
+                }
+                c.close();
+            })
+            .unwrap();
+
+        let mut buf = [0; 8];
+        for i in 0..4u8 {
+            assert_eq!(c.read(&mut buf).await, Ok(5));
+            assert_eq!(&buf[..5], &[i; 5]);
+        }
+        assert_eq!(c.read(&mut buf).await, Err(ReadError::Closed));
     }
 }
@@ -0,0 +1,735 @@
+//! A queue for sending values between asynchronous tasks, where the value with the highest
+//! priority is received first.
+//!
+//! It can be used concurrently by multiple producers (senders) and multiple
+//! consumers (receivers), i.e. it is an  "MPMC channel".
+//!
+//! This works like a [`Channel`](crate::channel::Channel), except that the queue is a binary heap
+//! instead of a FIFO. Receivers always get the queued message with the highest priority, as
+//! determined by the message's [`Ord`] implementation and the heap [`Kind`]: with [`Max`]
+//! the greatest message is received first, with [`Min`] the smallest one.
+//!
+//! To prioritize messages by a key instead, send them wrapped in [`Prioritized`], built from
+//! the key with [`Prioritized::by_key`].
+//!
+//! Like a [`Channel`](crate::channel::Channel), a priority channel can be closed to signal the
+//! end of the stream.
+
+use core::cell::RefCell;
+use core::cmp::Ordering;
+use core::future::Future;
+use core::mem;
+use core::pin::Pin;
+use core::task::{Context, Poll};
+
+pub use heapless::binary_heap::{Kind, Max, Min};
+use heapless::BinaryHeap;
+
+use crate::blocking_mutex::raw::RawMutex;
+use crate::blocking_mutex::Mutex;
+use crate::channel::{
+    DynamicChannel, DynamicReceiver, DynamicSender, RecvError, SendError, TryRecvError, TrySendError,
+};
+use crate::waitqueue::WakerRegistration;
+
+/// Send-only access to a [`PriorityChannel`].
+#[derive(Copy)]
+pub struct Sender<'ch, M, T, K, const N: usize>
+where
+    T: Ord,
+    K: Kind,
+    M: RawMutex,
+{
+    channel: &'ch PriorityChannel<M, T, K, N>,
+}
+
+impl<'ch, M, T, K, const N: usize> Clone for Sender<'ch, M, T, K, N>
+where
+    T: Ord,
+    K: Kind,
+    M: RawMutex,
+{
+    fn clone(&self) -> Self {
+        Sender { channel: self.channel }
+    }
+}
+
+impl<'ch, M, T, K, const N: usize> Sender<'ch, M, T, K, N>
+where
+    T: Ord,
+    K: Kind,
+    M: RawMutex,
+{
+    /// Sends a value.
+    ///
+    /// See [`PriorityChannel::send()`]
+    pub fn send(&self, message: T) -> SendFuture<'ch, M, T, K, N> {
+        self.channel.send(message)
+    }
+
+    /// Attempt to immediately send a message.
+    ///
+    /// See [`PriorityChannel::try_send()`]
+    pub fn try_send(&self, message: T) -> Result<(), TrySendError<T>> {
+        self.channel.try_send(message)
+    }
+
+    /// Send a message, evicting the message with the lowest priority if the channel is full.
+    ///
+    /// See [`PriorityChannel::send_or_evict()`]
+    pub fn send_or_evict(&self, message: T) -> Option<T> {
+        self.channel.send_or_evict(message)
+    }
+
+    /// Close the channel.
+    ///
+    /// See [`PriorityChannel::close()`]
+    pub fn close(&self) {
+        self.channel.close()
+    }
+
+    /// Returns whether the channel is closed.
+    ///
+    /// See [`PriorityChannel::is_closed()`]
+    pub fn is_closed(&self) -> bool {
+        self.channel.is_closed()
+    }
+}
+
+impl<'ch, M, T, K, const N: usize> From<Sender<'ch, M, T, K, N>> for DynamicSender<'ch, T>
+where
+    T: Ord,
+    K: Kind,
+    M: RawMutex,
+{
+    fn from(s: Sender<'ch, M, T, K, N>) -> Self {
+        Self { channel: s.channel }
+    }
+}
+
+/// Receive-only access to a [`PriorityChannel`].
+#[derive(Copy)]
+pub struct Receiver<'ch, M, T, K, const N: usize>
+where
+    T: Ord,
+    K: Kind,
+    M: RawMutex,
+{
+    channel: &'ch PriorityChannel<M, T, K, N>,
+}
+
+impl<'ch, M, T, K, const N: usize> Clone for Receiver<'ch, M, T, K, N>
+where
+    T: Ord,
+    K: Kind,
+    M: RawMutex,
+{
+    fn clone(&self) -> Self {
+        Receiver { channel: self.channel }
+    }
+}
+
+impl<'ch, M, T, K, const N: usize> Receiver<'ch, M, T, K, N>
+where
+    T: Ord,
+    K: Kind,
+    M: RawMutex,
+{
+    /// Receive the next value.
+    ///
+    /// See [`PriorityChannel::recv()`].
+    pub fn recv(&self) -> RecvFuture<'_, M, T, K, N> {
+        self.channel.recv()
+    }
+
+    /// Attempt to immediately receive the next value.
+    ///
+    /// See [`PriorityChannel::try_recv()`]
+    pub fn try_recv(&self) -> Result<T, TryRecvError> {
+        self.channel.try_recv()
+    }
+
+    /// Returns whether the channel is closed.
+    ///
+    /// See [`PriorityChannel::is_closed()`]
+    pub fn is_closed(&self) -> bool {
+        self.channel.is_closed()
+    }
+}
+
+impl<'ch, M, T, K, const N: usize> From<Receiver<'ch, M, T, K, N>> for DynamicReceiver<'ch, T>
+where
+    T: Ord,
+    K: Kind,
+    M: RawMutex,
+{
+    fn from(s: Receiver<'ch, M, T, K, N>) -> Self {
+        Self { channel: s.channel }
+    }
+}
+
+/// Future returned by [`PriorityChannel::recv`] and  [`Receiver::recv`].
+pub struct RecvFuture<'ch, M, T, K, const N: usize>
+where
+    T: Ord,
+    K: Kind,
+    M: RawMutex,
+{
+    channel: &'ch PriorityChannel<M, T, K, N>,
+}
+
+impl<'ch, M, T, K, const N: usize> Future for RecvFuture<'ch, M, T, K, N>
+where
+    T: Ord,
+    K: Kind,
+    M: RawMutex,
+{
+    type Output = Result<T, RecvError>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        match self.channel.try_recv_with_context(Some(cx)) {
+            Ok(v) => Poll::Ready(Ok(v)),
+            Err(TryRecvError::Empty) => Poll::Pending,
+            Err(TryRecvError::Closed) => Poll::Ready(Err(RecvError::Closed)),
+        }
+    }
+}
+
+/// Future returned by [`PriorityChannel::send`] and  [`Sender::send`].
+pub struct SendFuture<'ch, M, T, K, const N: usize>
+where
+    T: Ord,
+    K: Kind,
+    M: RawMutex,
+{
+    channel: &'ch PriorityChannel<M, T, K, N>,
+    message: Option<T>,
+}
+
+impl<'ch, M, T, K, const N: usize> Future for SendFuture<'ch, M, T, K, N>
+where
+    T: Ord,
+    K: Kind,
+    M: RawMutex,
+{
+    type Output = Result<(), SendError<T>>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        match self.message.take() {
+            Some(m) => match self.channel.try_send_with_context(m, Some(cx)) {
+                Ok(..) => Poll::Ready(Ok(())),
+                Err(TrySendError::Full(m)) => {
+                    self.message = Some(m);
+                    Poll::Pending
+                }
+                Err(TrySendError::Closed(m)) => Poll::Ready(Err(SendError::Closed(m))),
+            },
+            None => panic!("Message cannot be None"),
+        }
+    }
+}
+
+impl<'ch, M, T, K, const N: usize> Unpin for SendFuture<'ch, M, T, K, N>
+where
+    T: Ord,
+    K: Kind,
+    M: RawMutex,
+{
+}
+
+struct ChannelState<T, K, const N: usize> {
+    queue: BinaryHeap<T, K, N>,
+    receiver_waker: WakerRegistration,
+    senders_waker: WakerRegistration,
+    closed: bool,
+}
+
+impl<T, K, const N: usize> ChannelState<T, K, N>
+where
+    T: Ord,
+    K: Kind,
+{
+    const fn new() -> Self {
+        ChannelState {
+            queue: BinaryHeap::new(),
+            receiver_waker: WakerRegistration::new(),
+            senders_waker: WakerRegistration::new(),
+            closed: false,
+        }
+    }
+
+    fn try_recv(&mut self) -> Result<T, TryRecvError> {
+        self.try_recv_with_context(None)
+    }
+
+    fn try_recv_with_context(&mut self, cx: Option<&mut Context<'_>>) -> Result<T, TryRecvError> {
+        if self.queue.len() == self.queue.capacity() {
+            self.senders_waker.wake();
+        }
+
+        if let Some(message) = self.queue.pop() {
+            Ok(message)
+        } else if self.closed {
+            Err(TryRecvError::Closed)
+        } else {
+            if let Some(cx) = cx {
+                self.receiver_waker.register(cx.waker());
+            }
+            Err(TryRecvError::Empty)
+        }
+    }
+
+    fn try_send(&mut self, message: T) -> Result<(), TrySendError<T>> {
+        self.try_send_with_context(message, None)
+    }
+
+    fn try_send_with_context(&mut self, message: T, cx: Option<&mut Context<'_>>) -> Result<(), TrySendError<T>> {
+        if self.closed {
+            return Err(TrySendError::Closed(message));
+        }
+
+        match self.queue.push(message) {
+            Ok(()) => {
+                self.receiver_waker.wake();
+                Ok(())
+            }
+            Err(message) => {
+                if let Some(cx) = cx {
+                    self.senders_waker.register(cx.waker());
+                }
+                Err(TrySendError::Full(message))
+            }
+        }
+    }
+
+    fn send_or_evict(&mut self, message: T) -> Option<T> {
+        let message = match self.try_send(message) {
+            Ok(()) => return None,
+            Err(TrySendError::Full(message)) => message,
+            Err(TrySendError::Closed(message)) => return Some(message),
+        };
+
+        // Find the queued message with the lowest priority. It's rejected in favor of the new
+        // message only if the new one has a strictly higher priority: on a tie, the message that
+        // was queued first is kept.
+        let greatest_first = greatest_first::<K>();
+        let lowest = self
+            .queue
+            .iter()
+            .enumerate()
+            .min_by(|(_, a), (_, b)| priority_cmp(greatest_first, a, b));
+        let mut i = match lowest {
+            Some((i, lowest)) if priority_cmp(greatest_first, &message, lowest) == Ordering::Greater => i,
+            // The channel has no room for any message (N = 0), or the new message doesn't win.
+            _ => return Some(message),
+        };
+
+        // Replace the lowest message in place. The iterator goes over the heap's underlying array,
+        // in heap order. The new message has a higher priority than the one it replaces, so it can
+        // only break the heap ordering with its parents: sift it up.
+        let heap = self.queue.iter_mut().into_slice();
+        let evicted = mem::replace(&mut heap[i], message);
+        while i > 0 {
+            let parent = (i - 1) / 2;
+            if priority_cmp(greatest_first, &heap[i], &heap[parent]) != Ordering::Greater {
+                break;
+            }
+            heap.swap(i, parent);
+            i = parent;
+        }
+
+        Some(evicted)
+    }
+
+    fn close(&mut self) {
+        self.closed = true;
+        self.receiver_waker.wake();
+        self.senders_waker.wake();
+    }
+}
+
+/// Whether a heap of kind `K` pops its greatest element first.
+///
+/// [`Kind`] is sealed and doesn't expose its ordering, so find it out with a tiny heap.
+fn greatest_first<K: Kind>() -> bool {
+    let mut heap: BinaryHeap<u8, K, 2> = BinaryHeap::new();
+    let _ = heap.push(0);
+    let _ = heap.push(1);
+    heap.pop() == Some(1)
+}
+
+/// Compares two messages by priority: `Greater` means `a` is received before `b`.
+fn priority_cmp<T: Ord>(greatest_first: bool, a: &T, b: &T) -> Ordering {
+    if greatest_first {
+        a.cmp(b)
+    } else {
+        b.cmp(a)
+    }
+}
+
+/// A message prioritized by a key, for use in a [`PriorityChannel`].
+///
+/// Only the priority is compared: messages themselves don't need to implement [`Ord`].
+///
+/// ```
+/// # use embassy_sync::blocking_mutex::raw::NoopRawMutex;
+/// # use embassy_sync::priority_channel::{Max, Prioritized, PriorityChannel};
+/// struct Frame {
+///     urgency: u8,
+///     data: [u8; 8],
+/// }
+///
+/// let channel = PriorityChannel::<NoopRawMutex, Prioritized<u8, Frame>, Max, 4>::new();
+/// let frame = Frame { urgency: 3, data: [0; 8] };
+/// channel.try_send(Prioritized::by_key(frame, |f| f.urgency)).ok().unwrap();
+///
+/// let frame = channel.try_recv().ok().unwrap().message;
+/// assert_eq!(frame.urgency, 3);
+/// ```
+#[derive(Clone, Copy, Debug)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Prioritized<P, T> {
+    /// The priority of the message.
+    pub priority: P,
+    /// The message.
+    pub message: T,
+}
+
+impl<P: Ord, T> Prioritized<P, T> {
+    /// Wraps `message` with the given priority.
+    pub fn new(priority: P, message: T) -> Self {
+        Self { priority, message }
+    }
+
+    /// Wraps `message` with the priority computed by the key function `key`.
+    pub fn by_key(message: T, key: impl FnOnce(&T) -> P) -> Self {
+        Self {
+            priority: key(&message),
+            message,
+        }
+    }
+}
+
+impl<P: Ord, T> PartialEq for Prioritized<P, T> {
+    fn eq(&self, other: &Self) -> bool {
+        self.priority == other.priority
+    }
+}
+
+impl<P: Ord, T> Eq for Prioritized<P, T> {}
+
+impl<P: Ord, T> PartialOrd for Prioritized<P, T> {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl<P: Ord, T> Ord for Prioritized<P, T> {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.priority.cmp(&other.priority)
+    }
+}
+
+/// A bounded channel for communicating between asynchronous tasks
+/// with backpressure, where messages are received by priority.
+///
+/// The channel will buffer up to the provided number of messages. Once the
+/// buffer is full, attempts to `send` new messages will wait until a message is
+/// received from the channel. Alternatively, [`send_or_evict`](PriorityChannel::send_or_evict)
+/// makes room by dropping the message with the lowest priority.
+///
+/// Messages are received in priority order: with `K` = [`Max`], the greatest message
+/// is received first. With `K` = [`Min`], the smallest one is. The order in which messages with
+/// the same priority are received is unspecified.
+///
+/// ```
+/// # use embassy_sync::blocking_mutex::raw::NoopRawMutex;
+/// # use embassy_sync::priority_channel::{Max, PriorityChannel};
+/// #[derive(PartialEq, Eq, PartialOrd, Ord, Debug)]
+/// enum Command {
+///     // Variants declared later compare greater, so they have a higher priority in a `Max` channel.
+///     Blink,
+///     EmergencyStop,
+/// }
+///
+/// let channel = PriorityChannel::<NoopRawMutex, Command, Max, 4>::new();
+/// channel.try_send(Command::Blink).unwrap();
+/// channel.try_send(Command::EmergencyStop).unwrap();
+///
+/// assert_eq!(channel.try_recv(), Ok(Command::EmergencyStop));
+/// assert_eq!(channel.try_recv(), Ok(Command::Blink));
+/// ```
+pub struct PriorityChannel<M, T, K, const N: usize>
+where
+    T: Ord,
+    K: Kind,
+    M: RawMutex,
+{
+    inner: Mutex<M, RefCell<ChannelState<T, K, N>>>,
+}
+
+impl<M, T, K, const N: usize> PriorityChannel<M, T, K, N>
+where
+    T: Ord,
+    K: Kind,
+    M: RawMutex,
+{
+    /// Establish a new bounded priority channel. For example, to create one with a NoopMutex:
+    ///
+    /// ```
+    /// use embassy_sync::priority_channel::{Max, PriorityChannel};
+    /// use embassy_sync::blocking_mutex::raw::NoopRawMutex;
+    ///
+    /// // Declare a bounded priority channel of 3 u32s, receiving the greatest first.
+    /// let mut channel = PriorityChannel::<NoopRawMutex, u32, Max, 3>::new();
+    /// ```
+    pub const fn new() -> Self {
+        Self {
+            inner: Mutex::new(RefCell::new(ChannelState::new())),
+        }
+    }
+
+    fn lock<R>(&self, f: impl FnOnce(&mut ChannelState<T, K, N>) -> R) -> R {
+        self.inner.lock(|rc| f(&mut *rc.borrow_mut()))
+    }
+
+    fn try_recv_with_context(&self, cx: Option<&mut Context<'_>>) -> Result<T, TryRecvError> {
+        self.lock(|c| c.try_recv_with_context(cx))
+    }
+
+    fn try_send_with_context(&self, m: T, cx: Option<&mut Context<'_>>) -> Result<(), TrySendError<T>> {
+        self.lock(|c| c.try_send_with_context(m, cx))
+    }
+
+    /// Get a sender for this channel.
+    pub fn sender(&self) -> Sender<'_, M, T, K, N> {
+        Sender { channel: self }
+    }
+
+    /// Get a receiver for this channel.
+    pub fn receiver(&self) -> Receiver<'_, M, T, K, N> {
+        Receiver { channel: self }
+    }
+
+    /// Send a value, waiting until there is capacity.
+    ///
+    /// Sending completes when the value has been pushed to the channel's queue.
+    /// This doesn't mean the value has been received yet.
+    pub fn send(&self, message: T) -> SendFuture<'_, M, T, K, N> {
+        SendFuture {
+            channel: self,
+            message: Some(message),
+        }
+    }
+
+    /// Attempt to immediately send a message.
+    ///
+    /// This method differs from [`send`](PriorityChannel::send) by returning immediately if the channel's
+    /// buffer is full, instead of waiting.
+    ///
+    /// # Errors
+    ///
+    /// If the channel capacity has been reached, i.e., the channel has `n`
+    /// buffered values where `n` is the argument passed to [`PriorityChannel`], then an
+    /// error is returned.
+    pub fn try_send(&self, message: T) -> Result<(), TrySendError<T>> {
+        self.lock(|c| c.try_send(message))
+    }
+
+    /// Immediately send a message, evicting the message with the lowest priority if the channel is full.
+    ///
+    /// If the channel is full, the message with the lowest priority among the queued messages and
+    /// `message` itself is dropped from the channel and returned. If `message` has the same priority
+    /// as the lowest queued message, `message` is the one returned.
+    ///
+    /// Returns `None` if there was room for the message. If the channel is closed, `message` is
+    /// returned without evicting anything.
+    ///
+    /// Evicting a message takes time proportional to `N`, with the channel locked.
+    pub fn send_or_evict(&self, message: T) -> Option<T> {
+        self.lock(|c| c.send_or_evict(message))
+    }
+
+    /// Receive the next value.
+    ///
+    /// If there are no messages in the channel's buffer, this method will
+    /// wait until a message is sent, or return [`RecvError::Closed`] if the
+    /// channel is closed.
+    pub fn recv(&self) -> RecvFuture<'_, M, T, K, N> {
+        RecvFuture { channel: self }
+    }
+
+    /// Attempt to immediately receive a message.
+    ///
+    /// This method will either receive a message from the channel immediately or return an error
+    /// if the channel is empty.
+    pub fn try_recv(&self) -> Result<T, TryRecvError> {
+        self.lock(|c| c.try_recv())
+    }
+
+    /// Close the channel.
+    ///
+    /// See [`Channel::close()`](crate::channel::Channel::close)
+    pub fn close(&self) {
+        self.lock(|c| c.close())
+    }
+
+    /// Returns whether the channel is closed.
+    pub fn is_closed(&self) -> bool {
+        self.lock(|c| c.closed)
+    }
+}
+
+/// Implements the DynamicChannel to allow creating types that are unaware of the queue size with the
+/// tradeoff cost of dynamic dispatch.
+impl<M, T, K, const N: usize> DynamicChannel<T> for PriorityChannel<M, T, K, N>
+where
+    T: Ord,
+    K: Kind,
+    M: RawMutex,
+{
+    fn try_send_with_context(&self, m: T, cx: Option<&mut Context<'_>>) -> Result<(), TrySendError<T>> {
+        PriorityChannel::try_send_with_context(self, m, cx)
+    }
+
+    fn try_recv_with_context(&self, cx: Option<&mut Context<'_>>) -> Result<T, TryRecvError> {
+        PriorityChannel::try_recv_with_context(self, cx)
+    }
+
+    fn close(&self) {
+        PriorityChannel::close(self)
+    }
+
+    fn is_closed(&self) -> bool {
+        PriorityChannel::is_closed(self)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::blocking_mutex::raw::{CriticalSectionRawMutex, NoopRawMutex};
+
+    fn capacity<T: Ord, K: Kind, const N: usize>(c: &ChannelState<T, K, N>) -> usize {
+        c.queue.capacity() - c.queue.len()
+    }
+
+    #[test]
+    fn sending_when_full() {
+        let mut c = ChannelState::<u32, Max, 3>::new();
+        let _ = c.try_send(1);
+        let _ = c.try_send(1);
+        let _ = c.try_send(1);
+        match c.try_send(2) {
+            Err(TrySendError::Full(2)) => {}
+            _ => panic!("expected full channel"),
+        }
+        assert_eq!(capacity(&c), 0);
+    }
+
+    #[test]
+    fn receiving_in_priority_order() {
+        let max = PriorityChannel::<NoopRawMutex, u32, Max, 4>::new();
+        let min = PriorityChannel::<NoopRawMutex, u32, Min, 4>::new();
+        for m in [2, 4, 1, 3] {
+            assert!(max.try_send(m).is_ok());
+            assert!(min.try_send(m).is_ok());
+        }
+
+        for m in [4, 3, 2, 1] {
+            assert_eq!(max.try_recv(), Ok(m));
+        }
+        for m in [1, 2, 3, 4] {
+            assert_eq!(min.try_recv(), Ok(m));
+        }
+        assert_eq!(max.try_recv(), Err(TryRecvError::Empty));
+    }
+
+    #[test]
+    fn send_or_evict() {
+        let c = PriorityChannel::<NoopRawMutex, u32, Max, 3>::new();
+        assert_eq!(c.send_or_evict(2), None);
+        assert_eq!(c.send_or_evict(5), None);
+        assert_eq!(c.send_or_evict(3), None);
+
+        // Lower or equal priority than all queued messages: the new message is rejected.
+        assert_eq!(c.send_or_evict(1), Some(1));
+        assert_eq!(c.send_or_evict(2), Some(2));
+
+        // Higher priority: the lowest queued message is evicted.
+        assert_eq!(c.send_or_evict(4), Some(2));
+
+        assert_eq!(c.try_recv(), Ok(5));
+        assert_eq!(c.try_recv(), Ok(4));
+        assert_eq!(c.try_recv(), Ok(3));
+    }
+
+    #[test]
+    fn send_or_evict_min() {
+        let c = PriorityChannel::<NoopRawMutex, u32, Min, 2>::new();
+        assert_eq!(c.send_or_evict(2), None);
+        assert_eq!(c.send_or_evict(5), None);
+        assert_eq!(c.send_or_evict(1), Some(5));
+
+        assert_eq!(c.try_recv(), Ok(1));
+        assert_eq!(c.try_recv(), Ok(2));
+    }
+
+    #[test]
+    fn send_or_evict_keeps_heap_order() {
+        let c = PriorityChannel::<NoopRawMutex, u32, Max, 7>::new();
+        for m in [10, 20, 30, 40, 50, 60, 70] {
+            assert_eq!(c.send_or_evict(m), None);
+        }
+        assert_eq!(c.send_or_evict(100), Some(10));
+        assert_eq!(c.send_or_evict(15), Some(15));
+        assert_eq!(c.send_or_evict(55), Some(20));
+
+        for m in [100, 70, 60, 55, 50, 40, 30] {
+            assert_eq!(c.try_recv(), Ok(m));
+        }
+    }
+
+    #[test]
+    fn prioritized_by_key() {
+        let c = PriorityChannel::<NoopRawMutex, Prioritized<u8, &str>, Min, 3>::new();
+        for m in ["ccc", "a", "bb"] {
+            assert!(c.try_send(Prioritized::by_key(m, |m| m.len() as u8)).is_ok());
+        }
+        for m in ["a", "bb", "ccc"] {
+            assert_eq!(c.try_recv().map(|p| p.message), Ok(m));
+        }
+    }
+
+    #[test]
+    fn dynamic_dispatch() {
+        let c = PriorityChannel::<NoopRawMutex, u32, Max, 3>::new();
+        let s: DynamicSender<'_, u32> = c.sender().into();
+        let r: DynamicReceiver<'_, u32> = c.receiver().into();
+
+        assert!(s.try_send(1).is_ok());
+        assert!(s.try_send(2).is_ok());
+        assert_eq!(r.try_recv().unwrap(), 2);
+    }
+
+    #[test]
+    fn closing() {
+        let c = PriorityChannel::<NoopRawMutex, u32, Max, 3>::new();
+        let s: DynamicSender<'_, u32> = c.sender().into();
+        assert!(c.try_send(1).is_ok());
+
+        s.close();
+        assert!(c.is_closed());
+        assert_eq!(c.try_send(2), Err(TrySendError::Closed(2)));
+        assert_eq!(c.send_or_evict(3), Some(3));
+        assert_eq!(c.try_recv(), Ok(1));
+        assert_eq!(c.try_recv(), Err(TryRecvError::Closed));
+    }
+
+    #[futures_test::test]
+    async fn sender_send_completes_if_capacity() {
+        let c = PriorityChannel::<CriticalSectionRawMutex, u32, Max, 1>::new();
+        c.send(1).await.unwrap();
+        assert_eq!(c.recv().await, Ok(1));
+    }
+}
@@ -0,0 +1,425 @@
+//! Async read-write lock.
+//!
+//! This module provides a read-write lock that can be used to synchronize data between asynchronous tasks.
+use core::cell::{RefCell, UnsafeCell};
+use core::future::poll_fn;
+use core::ops::{Deref, DerefMut};
+use core::task::{Poll, Waker};
+
+use crate::blocking_mutex::raw::RawMutex;
+use crate::blocking_mutex::Mutex as BlockingMutex;
+use crate::waitqueue::MultiWakerRegistration;
+
+/// Error returned by [`RwLock::try_read`] and [`RwLock::try_write`]
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct TryLockError;
+
+struct State<const N: usize> {
+    /// Number of read guards alive.
+    readers: usize,
+    /// Whether a write guard is alive.
+    writer: bool,
+    /// Number of `write()` calls waiting for the lock.
+    writers_waiting: usize,
+    readers_waker: MultiWakerRegistration<N>,
+    writers_waker: MultiWakerRegistration<N>,
+}
+
+impl<const N: usize> State<N> {
+    fn can_read(&self) -> bool {
+        // Waiting writers block new readers, so that a steady flow of readers can't starve writers.
+        !self.writer && self.writers_waiting == 0
+    }
+
+    fn can_write(&self) -> bool {
+        !self.writer && self.readers == 0
+    }
+}
+
+fn register<const N: usize>(wakers: &mut MultiWakerRegistration<N>, waker: &Waker) {
+    if wakers.register(waker).is_err() {
+        // All waker slots were full. Wake everything, any future that is still waiting will simply reregister.
+        wakers.wake();
+        wakers.register(waker).unwrap();
+    }
+}
+
+/// Async read-write lock.
+///
+/// The lock allows either any number of readers, or a single writer at a time.
+///
+/// The lock is writer-preferring: once a writer is waiting, new readers wait until that writer
+/// has had its turn. This prevents a steady stream of readers from starving writers.
+///
+/// Like [`Mutex`](crate::mutex::Mutex), the lock is generic over a blocking
+/// [`RawMutex`](crate::blocking_mutex::raw::RawMutex), which is used to guard access to the
+/// internal state. It is held for very short periods only, while locking and unlocking. It is *not*
+/// held for the entire time the async RwLock is locked.
+///
+/// `N` is the number of wakers registered at once, for readers and writers each. More tasks
+/// can wait, but when the slots run out, all waiting tasks are woken to register again, which
+/// wastes CPU time. Set it to the number of tasks expected to wait for the lock at once.
+pub struct RwLock<M, T, const N: usize = 4>
+where
+    M: RawMutex,
+    T: ?Sized,
+{
+    state: BlockingMutex<M, RefCell<State<N>>>,
+    inner: UnsafeCell<T>,
+}
+
+unsafe impl<M: RawMutex + Send, T: ?Sized + Send, const N: usize> Send for RwLock<M, T, N> {}
+unsafe impl<M: RawMutex + Sync, T: ?Sized + Send + Sync, const N: usize> Sync for RwLock<M, T, N> {}
+
+impl<M, T, const N: usize> RwLock<M, T, N>
+where
+    M: RawMutex,
+{
+    /// Create a new read-write lock with the given value.
+    pub const fn new(value: T) -> Self {
+        Self {
+            inner: UnsafeCell::new(value),
+            state: BlockingMutex::new(RefCell::new(State {
+                readers: 0,
+                writer: false,
+                writers_waiting: 0,
+                readers_waker: MultiWakerRegistration::new(),
+                writers_waker: MultiWakerRegistration::new(),
+            })),
+        }
+    }
+}
+
+impl<M, T, const N: usize> RwLock<M, T, N>
+where
+    M: RawMutex,
+    T: ?Sized,
+{
+    /// Lock for reading.
+    ///
+    /// This will wait until there is no writer holding or waiting for the lock.
+    pub async fn read(&self) -> RwLockReadGuard<'_, M, T, N> {
+        poll_fn(|cx| {
+            let ready = self.state.lock(|s| {
+                let mut s = s.borrow_mut();
+                if s.can_read() {
+                    s.readers += 1;
+                    true
+                } else {
+                    register(&mut s.readers_waker, cx.waker());
+                    false
+                }
+            });
+
+            if ready {
+                Poll::Ready(RwLockReadGuard { lock: self })
+            } else {
+                Poll::Pending
+            }
+        })
+        .await
+    }
+
+    /// Lock for writing.
+    ///
+    /// This will wait until there are no readers or writer holding the lock. While waiting,
+    /// new readers are held off.
+    pub async fn write(&self) -> RwLockWriteGuard<'_, M, T, N> {
+        let mut waiting = WaitingWriter {
+            lock: self,
+            waiting: false,
+        };
+
+        poll_fn(|cx| {
+            let ready = self.state.lock(|s| {
+                let mut s = s.borrow_mut();
+                if s.can_write() {
+                    s.writer = true;
+                    if waiting.waiting {
+                        waiting.waiting = false;
+                        s.writers_waiting -= 1;
+                    }
+                    true
+                } else {
+                    if !waiting.waiting {
+                        waiting.waiting = true;
+                        s.writers_waiting += 1;
+                    }
+                    register(&mut s.writers_waker, cx.waker());
+                    false
+                }
+            });
+
+            if ready {
+                Poll::Ready(RwLockWriteGuard { lock: self })
+            } else {
+                Poll::Pending
+            }
+        })
+        .await
+    }
+
+    /// Attempt to immediately lock for reading.
+    ///
+    /// If a writer holds or is waiting for the lock, this will return an error instead of waiting.
+    pub fn try_read(&self) -> Result<RwLockReadGuard<'_, M, T, N>, TryLockError> {
+        self.state.lock(|s| {
+            let mut s = s.borrow_mut();
+            if s.can_read() {
+                s.readers += 1;
+                Ok(())
+            } else {
+                Err(TryLockError)
+            }
+        })?;
+
+        Ok(RwLockReadGuard { lock: self })
+    }
+
+    /// Attempt to immediately lock for writing.
+    ///
+    /// If the lock is held by readers or a writer, this will return an error instead of waiting.
+    pub fn try_write(&self) -> Result<RwLockWriteGuard<'_, M, T, N>, TryLockError> {
+        self.state.lock(|s| {
+            let mut s = s.borrow_mut();
+            if s.can_write() {
+                s.writer = true;
+                Ok(())
+            } else {
+                Err(TryLockError)
+            }
+        })?;
+
+        Ok(RwLockWriteGuard { lock: self })
+    }
+
+    /// Consumes this lock, returning the underlying data.
+    pub fn into_inner(self) -> T
+    where
+        T: Sized,
+    {
+        self.inner.into_inner()
+    }
+
+    /// Returns a mutable reference to the underlying data.
+    ///
+    /// Since this call borrows the RwLock mutably, no actual locking needs to
+    /// take place -- the mutable borrow statically guarantees no locks exist.
+    pub fn get_mut(&mut self) -> &mut T {
+        self.inner.get_mut()
+    }
+}
+
+/// Keeps track of a writer waiting in [`RwLock::write`], so it stops holding off readers when
+/// the future is dropped before acquiring the lock.
+struct WaitingWriter<'a, M, T, const N: usize>
+where
+    M: RawMutex,
+    T: ?Sized,
+{
+    lock: &'a RwLock<M, T, N>,
+    waiting: bool,
+}
+
+impl<'a, M, T, const N: usize> Drop for WaitingWriter<'a, M, T, N>
+where
+    M: RawMutex,
+    T: ?Sized,
+{
+    fn drop(&mut self) {
+        if self.waiting {
+            self.lock.state.lock(|s| {
+                let mut s = s.borrow_mut();
+                s.writers_waiting -= 1;
+                if s.can_read() {
+                    s.readers_waker.wake();
+                }
+            })
+        }
+    }
+}
+
+/// Async read-write lock read guard.
+///
+/// Owning an instance of this type indicates having
+/// successfully locked the lock for reading, and grants shared access to the contents.
+///
+/// Dropping it unlocks the lock.
+pub struct RwLockReadGuard<'a, M, T, const N: usize = 4>
+where
+    M: RawMutex,
+    T: ?Sized,
+{
+    lock: &'a RwLock<M, T, N>,
+}
+
+impl<'a, M, T, const N: usize> Drop for RwLockReadGuard<'a, M, T, N>
+where
+    M: RawMutex,
+    T: ?Sized,
+{
+    fn drop(&mut self) {
+        self.lock.state.lock(|s| {
+            let mut s = s.borrow_mut();
+            s.readers -= 1;
+            if s.readers == 0 {
+                s.writers_waker.wake();
+            }
+        })
+    }
+}
+
+impl<'a, M, T, const N: usize> Deref for RwLockReadGuard<'a, M, T, N>
+where
+    M: RawMutex,
+    T: ?Sized,
+{
+    type Target = T;
+    fn deref(&self) -> &Self::Target {
+        // Safety: the RwLockReadGuard represents shared access to the contents
+        // of the lock, and no writer can exist at the same time.
+        unsafe { &*(self.lock.inner.get() as *const T) }
+    }
+}
+
+/// Async read-write lock write guard.
+///
+/// Owning an instance of this type indicates having
+/// successfully locked the lock for writing, and grants exclusive access to the contents.
+///
+/// Dropping it unlocks the lock.
+pub struct RwLockWriteGuard<'a, M, T, const N: usize = 4>
+where
+    M: RawMutex,
+    T: ?Sized,
+{
+    lock: &'a RwLock<M, T, N>,
+}
+
+impl<'a, M, T, const N: usize> Drop for RwLockWriteGuard<'a, M, T, N>
+where
+    M: RawMutex,
+    T: ?Sized,
+{
+    fn drop(&mut self) {
+        self.lock.state.lock(|s| {
+            let mut s = s.borrow_mut();
+            s.writer = false;
+            s.writers_waker.wake();
+            s.readers_waker.wake();
+        })
+    }
+}
+
+impl<'a, M, T, const N: usize> Deref for RwLockWriteGuard<'a, M, T, N>
+where
+    M: RawMutex,
+    T: ?Sized,
+{
+    type Target = T;
+    fn deref(&self) -> &Self::Target {
+        // Safety: the RwLockWriteGuard represents exclusive access to the contents
+        // of the lock, so it's OK to get it.
+        unsafe { &*(self.lock.inner.get() as *const T) }
+    }
+}
+
+impl<'a, M, T, const N: usize> DerefMut for RwLockWriteGuard<'a, M, T, N>
+where
+    M: RawMutex,
+    T: ?Sized,
+{
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        // Safety: the RwLockWriteGuard represents exclusive access to the contents
+        // of the lock, so it's OK to get it.
+        unsafe { &mut *(self.lock.inner.get()) }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use futures_util::{pin_mut, FutureExt};
+
+    use super::*;
+    use crate::blocking_mutex::raw::NoopRawMutex;
+
+    #[test]
+    fn multiple_readers() {
+        let lock = RwLock::<NoopRawMutex, u32>::new(5);
+        let r1 = lock.try_read().unwrap();
+        let r2 = lock.try_read().unwrap();
+        assert_eq!(*r1 + *r2, 10);
+        assert_eq!(lock.try_write().err(), Some(TryLockError));
+
+        drop(r1);
+        assert!(lock.try_write().is_err());
+        drop(r2);
+        assert!(lock.try_write().is_ok());
+    }
+
+    #[test]
+    fn writer_is_exclusive() {
+        let lock = RwLock::<NoopRawMutex, u32>::new(5);
+        let mut w = lock.try_write().unwrap();
+        *w = 6;
+        assert!(lock.try_read().is_err());
+        assert!(lock.try_write().is_err());
+
+        drop(w);
+        assert_eq!(*lock.try_read().unwrap(), 6);
+    }
+
+    #[futures_test::test]
+    async fn waiting_writer_holds_off_readers() {
+        let lock = RwLock::<NoopRawMutex, u32>::new(0);
+        let r = lock.read().await;
+
+        let write = lock.write();
+        pin_mut!(write);
+        assert!(write.as_mut().now_or_never().is_none());
+
+        // The writer is waiting, so new readers have to wait as well.
+        assert!(lock.try_read().is_err());
+        let read = lock.read();
+        pin_mut!(read);
+        assert!(read.as_mut().now_or_never().is_none());
+
+        drop(r);
+        let mut w = write.await;
+        *w = 1;
+        drop(w);
+
+        assert_eq!(*read.await, 1);
+    }
+
+    #[futures_test::test]
+    async fn more_waiters_than_waker_slots() {
+        let lock = RwLock::<NoopRawMutex, u32, 1>::new(0);
+        let w = lock.write().await;
+
+        let read1 = lock.read();
+        let read2 = lock.read();
+        pin_mut!(read1, read2);
+        assert!(read1.as_mut().now_or_never().is_none());
+        assert!(read2.as_mut().now_or_never().is_none());
+
+        drop(w);
+        assert_eq!(*read1.await + *read2.await, 0);
+    }
+
+    #[futures_test::test]
+    async fn cancelled_writer_releases_readers() {
+        let lock = RwLock::<NoopRawMutex, u32>::new(0);
+        let _r = lock.read().await;
+
+        {
+            let write = lock.write();
+            pin_mut!(write);
+            assert!(write.as_mut().now_or_never().is_none());
+            assert!(lock.try_read().is_err());
+        }
+
+        assert!(lock.try_read().is_ok());
+    }
+}
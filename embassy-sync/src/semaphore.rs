@@ -0,0 +1,423 @@
+//! Async counting semaphore.
+//!
+//! This module provides a semaphore that can be used to limit concurrent access to a resource,
+//! for example the number of simultaneous transactions on a bus or the number of open sockets.
+use core::cell::RefCell;
+use core::future::Future;
+use core::pin::Pin;
+use core::task::{Context, Poll};
+
+use heapless::Vec;
+
+use crate::blocking_mutex::raw::RawMutex;
+use crate::blocking_mutex::Mutex;
+use crate::waitqueue::WakerRegistration;
+
+/// Error returned by [`Semaphore::acquire`] when the wait queue is full.
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct WaitQueueFull;
+
+struct Waiter {
+    id: u64,
+    permits: usize,
+    waker: WakerRegistration,
+}
+
+struct State<const N: usize> {
+    permits: usize,
+    waiters: Vec<Waiter, N>,
+    next_id: u64,
+}
+
+impl<const N: usize> State<N> {
+    /// Wake the waiter at the head of the queue, so it can check if enough permits are available.
+    fn wake_head(&mut self) {
+        if let Some(waiter) = self.waiters.first_mut() {
+            waiter.waker.wake();
+        }
+    }
+}
+
+/// Async counting semaphore.
+///
+/// A semaphore holds a number of permits. Tasks [acquire](Semaphore::acquire) permits,
+/// waiting until enough are available, and give them back when the returned
+/// [`SemaphoreReleaser`] is dropped.
+///
+/// Acquisition is fair: tasks waiting for permits are served in FIFO order. A task
+/// requesting many permits is not starved by tasks requesting fewer permits that arrive after it,
+/// and a task can't acquire permits through [`try_acquire`](Semaphore::try_acquire) while other
+/// tasks are waiting.
+///
+/// Up to `N` tasks can wait for permits at the same time. Waiting on a semaphore whose wait queue
+/// is full fails with [`WaitQueueFull`].
+///
+/// The semaphore is generic over a blocking [`RawMutex`](crate::blocking_mutex::raw::RawMutex),
+/// which guards the internal state. See [`Mutex`](crate::mutex::Mutex) for how to choose one.
+///
+/// ```
+/// # use embassy_sync::blocking_mutex::raw::NoopRawMutex;
+/// # use embassy_sync::semaphore::Semaphore;
+/// # use futures_executor::block_on;
+/// # let test = async {
+/// // Allow at most 2 concurrent users, with up to 4 tasks waiting.
+/// let semaphore = Semaphore::<NoopRawMutex, 4>::new(2);
+///
+/// let a = semaphore.acquire(1).await.unwrap();
+/// let b = semaphore.acquire(1).await.unwrap();
+/// assert!(semaphore.try_acquire(1).is_none());
+///
+/// // Dropping a releaser gives its permits back.
+/// drop(a);
+/// assert!(semaphore.try_acquire(1).is_some());
+/// # drop(b);
+/// # };
+/// #
+/// # block_on(test);
+/// ```
+pub struct Semaphore<M, const N: usize>
+where
+    M: RawMutex,
+{
+    state: Mutex<M, RefCell<State<N>>>,
+}
+
+impl<M, const N: usize> Semaphore<M, N>
+where
+    M: RawMutex,
+{
+    /// Create a new semaphore with the given number of permits.
+    pub const fn new(permits: usize) -> Self {
+        Self {
+            state: Mutex::new(RefCell::new(State {
+                permits,
+                waiters: Vec::new(),
+                next_id: 0,
+            })),
+        }
+    }
+
+    /// Acquire `permits` permits.
+    ///
+    /// This waits until enough permits are available, and all tasks that started waiting
+    /// earlier have been served. The permits are released when the returned
+    /// [`SemaphoreReleaser`] is dropped.
+    ///
+    /// Returns [`WaitQueueFull`] if the task would need to wait, but `N` other tasks are already waiting.
+    ///
+    /// Requesting more permits than the semaphore can ever hold waits forever, and blocks all
+    /// tasks queued after this one.
+    pub fn acquire(&self, permits: usize) -> AcquireFuture<'_, M, N> {
+        AcquireFuture {
+            semaphore: self,
+            permits,
+            id: None,
+        }
+    }
+
+    /// Attempt to immediately acquire `permits` permits.
+    ///
+    /// Returns `None` if not enough permits are available, or if other tasks are waiting for permits.
+    pub fn try_acquire(&self, permits: usize) -> Option<SemaphoreReleaser<'_, M, N>> {
+        self.state.lock(|s| {
+            let mut s = s.borrow_mut();
+            if s.waiters.is_empty() && s.permits >= permits {
+                s.permits -= permits;
+                Some(SemaphoreReleaser {
+                    semaphore: self,
+                    permits,
+                })
+            } else {
+                None
+            }
+        })
+    }
+
+    /// Release `permits` permits, making them available to other tasks.
+    ///
+    /// This is useful to hand out permits that were never acquired, or to give back permits
+    /// kept with [`SemaphoreReleaser::disarm`].
+    pub fn release(&self, permits: usize) {
+        self.state.lock(|s| {
+            let mut s = s.borrow_mut();
+            s.permits += permits;
+            s.wake_head();
+        })
+    }
+
+    /// Set the number of available permits.
+    ///
+    /// This doesn't take into account the permits that are currently acquired:
+    /// they are still added back when released.
+    pub fn set(&self, permits: usize) {
+        self.state.lock(|s| {
+            let mut s = s.borrow_mut();
+            s.permits = permits;
+            s.wake_head();
+        })
+    }
+
+    /// Returns the number of available permits.
+    pub fn permits(&self) -> usize {
+        self.state.lock(|s| s.borrow().permits)
+    }
+
+    fn poll_acquire(
+        &self,
+        permits: usize,
+        id: &mut Option<u64>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Result<(), WaitQueueFull>> {
+        self.state.lock(|s| {
+            let s = &mut *s.borrow_mut();
+            match *id {
+                None => {
+                    if s.waiters.is_empty() && s.permits >= permits {
+                        s.permits -= permits;
+                        return Poll::Ready(Ok(()));
+                    }
+
+                    let mut waiter = Waiter {
+                        id: s.next_id,
+                        permits,
+                        waker: WakerRegistration::new(),
+                    };
+                    waiter.waker.register(cx.waker());
+                    if s.waiters.push(waiter).is_err() {
+                        return Poll::Ready(Err(WaitQueueFull));
+                    }
+                    *id = Some(s.next_id);
+                    s.next_id += 1;
+                    Poll::Pending
+                }
+                Some(waiter_id) => {
+                    let head = &s.waiters[0];
+                    if head.id == waiter_id && s.permits >= head.permits {
+                        s.permits -= permits;
+                        s.waiters.remove(0);
+                        *id = None;
+                        // The next waiter may be satisfied by the remaining permits.
+                        s.wake_head();
+                        return Poll::Ready(Ok(()));
+                    }
+
+                    // A waiter is only removed from the queue by itself, so it must still be there.
+                    let waiter = s.waiters.iter_mut().find(|w| w.id == waiter_id).unwrap();
+                    waiter.waker.register(cx.waker());
+                    Poll::Pending
+                }
+            }
+        })
+    }
+
+    fn cancel_acquire(&self, waiter_id: u64) {
+        self.state.lock(|s| {
+            let mut s = s.borrow_mut();
+            if let Some(index) = s.waiters.iter().position(|w| w.id == waiter_id) {
+                s.waiters.remove(index);
+                if index == 0 {
+                    // The cancelled waiter may have been holding back the others.
+                    s.wake_head();
+                }
+            }
+        })
+    }
+}
+
+/// Future returned by [`Semaphore::acquire`].
+#[must_use = "futures do nothing unless you `.await` or poll them"]
+pub struct AcquireFuture<'a, M, const N: usize>
+where
+    M: RawMutex,
+{
+    semaphore: &'a Semaphore<M, N>,
+    permits: usize,
+    /// Position in the wait queue, once waiting.
+    id: Option<u64>,
+}
+
+impl<'a, M, const N: usize> Future for AcquireFuture<'a, M, N>
+where
+    M: RawMutex,
+{
+    type Output = Result<SemaphoreReleaser<'a, M, N>, WaitQueueFull>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = &mut *self;
+        this.semaphore
+            .poll_acquire(this.permits, &mut this.id, cx)
+            .map_ok(|()| SemaphoreReleaser {
+                semaphore: this.semaphore,
+                permits: this.permits,
+            })
+    }
+}
+
+impl<'a, M, const N: usize> Drop for AcquireFuture<'a, M, N>
+where
+    M: RawMutex,
+{
+    fn drop(&mut self) {
+        if let Some(id) = self.id {
+            self.semaphore.cancel_acquire(id);
+        }
+    }
+}
+
+impl<'a, M, const N: usize> Unpin for AcquireFuture<'a, M, N> where M: RawMutex {}
+
+/// Permits acquired from a [`Semaphore`].
+///
+/// Dropping it releases the permits.
+#[must_use = "permits are released immediately if the releaser is dropped"]
+pub struct SemaphoreReleaser<'a, M, const N: usize>
+where
+    M: RawMutex,
+{
+    semaphore: &'a Semaphore<M, N>,
+    permits: usize,
+}
+
+impl<'a, M, const N: usize> SemaphoreReleaser<'a, M, N>
+where
+    M: RawMutex,
+{
+    /// The number of permits held by this releaser.
+    pub fn permits(&self) -> usize {
+        self.permits
+    }
+
+    /// Keep the permits acquired, instead of releasing them on drop.
+    ///
+    /// Returns the number of permits held. They can be released later with [`Semaphore::release`].
+    pub fn disarm(self) -> usize {
+        let permits = self.permits;
+        core::mem::forget(self);
+        permits
+    }
+}
+
+impl<'a, M, const N: usize> Drop for SemaphoreReleaser<'a, M, N>
+where
+    M: RawMutex,
+{
+    fn drop(&mut self) {
+        self.semaphore.release(self.permits);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use core::pin::Pin;
+    use core::task::{Context, Poll};
+
+    use futures_executor::block_on;
+    use futures_util::task::noop_waker_ref;
+    use futures_util::Future;
+
+    use super::*;
+    use crate::blocking_mutex::raw::NoopRawMutex;
+
+    fn poll<F: Future + Unpin>(fut: &mut F) -> Poll<F::Output> {
+        Pin::new(fut).poll(&mut Context::from_waker(noop_waker_ref()))
+    }
+
+    #[test]
+    fn try_acquire_and_release() {
+        let semaphore = Semaphore::<NoopRawMutex, 2>::new(3);
+
+        let a = semaphore.try_acquire(2).unwrap();
+        assert_eq!(a.permits(), 2);
+        assert_eq!(semaphore.permits(), 1);
+        assert!(semaphore.try_acquire(2).is_none());
+
+        drop(a);
+        assert_eq!(semaphore.permits(), 3);
+    }
+
+    #[test]
+    fn acquire_waits_for_permits() {
+        let semaphore = Semaphore::<NoopRawMutex, 2>::new(1);
+
+        let a = block_on(semaphore.acquire(1)).unwrap();
+
+        let mut fut = semaphore.acquire(1);
+        assert!(poll(&mut fut).is_pending());
+
+        drop(a);
+        let b = match poll(&mut fut) {
+            Poll::Ready(Ok(b)) => b,
+            _ => panic!("expected permits"),
+        };
+        assert_eq!(semaphore.permits(), 0);
+        drop(b);
+        assert_eq!(semaphore.permits(), 1);
+    }
+
+    #[test]
+    fn acquire_is_fifo() {
+        let semaphore = Semaphore::<NoopRawMutex, 2>::new(0);
+
+        let mut big = semaphore.acquire(2);
+        let mut small = semaphore.acquire(1);
+        assert!(poll(&mut big).is_pending());
+        assert!(poll(&mut small).is_pending());
+
+        // Only one permit: the first waiter needs two, so the second one must not overtake it.
+        semaphore.release(1);
+        assert!(poll(&mut small).is_pending());
+        assert!(semaphore.try_acquire(1).is_none());
+
+        semaphore.release(1);
+        assert!(matches!(poll(&mut small), Poll::Pending));
+        let big = match poll(&mut big) {
+            Poll::Ready(Ok(r)) => r,
+            _ => panic!("expected permits"),
+        };
+        drop(big);
+        assert!(matches!(poll(&mut small), Poll::Ready(Ok(_))));
+    }
+
+    #[test]
+    fn cancelled_waiter_unblocks_queue() {
+        let semaphore = Semaphore::<NoopRawMutex, 2>::new(1);
+
+        let mut big = semaphore.acquire(2);
+        let mut small = semaphore.acquire(1);
+        assert!(poll(&mut big).is_pending());
+        assert!(poll(&mut small).is_pending());
+
+        drop(big);
+        assert!(matches!(poll(&mut small), Poll::Ready(Ok(_))));
+    }
+
+    #[test]
+    fn wait_queue_full() {
+        let semaphore = Semaphore::<NoopRawMutex, 1>::new(0);
+
+        let mut a = semaphore.acquire(1);
+        let mut b = semaphore.acquire(1);
+        assert!(poll(&mut a).is_pending());
+        assert!(matches!(poll(&mut b), Poll::Ready(Err(WaitQueueFull))));
+    }
+
+    #[test]
+    fn set_and_disarm() {
+        let semaphore = Semaphore::<NoopRawMutex, 1>::new(0);
+
+        let mut a = semaphore.acquire(2);
+        assert!(poll(&mut a).is_pending());
+
+        semaphore.set(2);
+        let a = match poll(&mut a) {
+            Poll::Ready(Ok(r)) => r,
+            _ => panic!("expected permits"),
+        };
+        assert_eq!(a.disarm(), 2);
+        assert_eq!(semaphore.permits(), 0);
+
+        semaphore.release(2);
+        assert_eq!(semaphore.permits(), 2);
+    }
+}
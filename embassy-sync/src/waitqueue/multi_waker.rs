@@ -15,7 +15,13 @@ impl<const N: usize> MultiWakerRegistration<N> {
     }
 
     /// Register a waker. If the buffer is full the function returns it in the error
+    ///
+    /// Registering a waker that wakes the same task as one already registered does nothing,
+    /// so a task polling repeatedly only takes one slot.
     pub fn register<'a>(&mut self, w: &'a Waker) -> Result<(), &'a Waker> {
+        if self.wakers.iter().any(|waker_slot| waker_slot.will_wake(w)) {
+            return Ok(());
+        }
         if let Some(waker_slot) = self.wakers.iter_mut().find(|waker_slot| !waker_slot.occupied()) {
             waker_slot.register(w);
             Ok(())
@@ -0,0 +1,528 @@
+//! A synchronization primitive for broadcasting the latest value to multiple tasks.
+
+use core::cell::RefCell;
+use core::future::poll_fn;
+use core::marker::PhantomData;
+use core::ops::{Deref, DerefMut};
+use core::task::{Context, Poll};
+
+use crate::blocking_mutex::raw::RawMutex;
+use crate::blocking_mutex::Mutex;
+use crate::waitqueue::MultiWakerRegistration;
+
+/// A `Watch` broadcasts the latest value to up to `N` receivers.
+///
+/// This is similar to a [`Signal`](crate::signal::Signal), except that any number of receivers
+/// (up to `N`) can wait on it, and the value is not consumed when read. Each receiver keeps track
+/// of which value it has already seen, so it can wait until the value changes.
+///
+/// Unlike a [`PubSubChannel`](crate::pubsub::PubSubChannel), only the latest value is kept.
+/// Receivers that don't keep up simply skip intermediate values. This makes it a good fit for
+/// "state" that many tasks are interested in, like configuration or the latest sensor reading.
+///
+/// ## Example
+///
+/// ```
+/// # use embassy_sync::blocking_mutex::raw::NoopRawMutex;
+/// # use embassy_sync::watch::Watch;
+/// # use futures_executor::block_on;
+/// # let test = async {
+/// // Create the watch. This can be static as well
+/// let watch = Watch::<NoopRawMutex, u32, 2>::new();
+///
+/// // This is a generic receiver with a direct reference to the watch
+/// let mut rcv0 = watch.receiver().unwrap();
+/// // This is a dynamic receiver with a dynamic (trait object) reference to the watch
+/// let mut rcv1 = watch.dyn_receiver().unwrap();
+///
+/// watch.send(10);
+///
+/// // Both receivers see the new value
+/// assert_eq!(rcv0.changed().await, 10);
+/// assert_eq!(rcv1.changed().await, 10);
+///
+/// // The value stays available, but isn't new anymore
+/// assert_eq!(rcv0.get().await, 10);
+/// assert_eq!(rcv0.try_changed(), None);
+///
+/// // Intermediate values are skipped by receivers that didn't look at them
+/// watch.send(20);
+/// watch.send(30);
+/// assert_eq!(rcv1.try_changed(), Some(30));
+/// # };
+/// #
+/// # block_on(test);
+/// ```
+pub struct Watch<M: RawMutex, T: Clone, const N: usize> {
+    inner: Mutex<M, RefCell<WatchState<T, N>>>,
+}
+
+impl<M: RawMutex, T: Clone, const N: usize> Watch<M, T, N> {
+    /// Create a new `Watch`, without a value.
+    pub const fn new() -> Self {
+        Self {
+            inner: Mutex::const_new(M::INIT, RefCell::new(WatchState::new())),
+        }
+    }
+
+    /// Create a new receiver.
+    ///
+    /// If the watch already contains a value, the new receiver considers it as not seen yet.
+    ///
+    /// If there are no receiver slots left, an error will be returned.
+    pub fn receiver(&self) -> Result<Receiver<'_, M, T, N>, Error> {
+        self.register_receiver()?;
+        Ok(Receiver(Rcv::new(self)))
+    }
+
+    /// Create a new receiver that holds a dynamic reference to the watch.
+    ///
+    /// If the watch already contains a value, the new receiver considers it as not seen yet.
+    ///
+    /// If there are no receiver slots left, an error will be returned.
+    pub fn dyn_receiver(&self) -> Result<DynReceiver<'_, T>, Error> {
+        self.register_receiver()?;
+        Ok(DynReceiver(Rcv::new(self)))
+    }
+
+    fn register_receiver(&self) -> Result<(), Error> {
+        self.inner.lock(|s| {
+            let mut s = s.borrow_mut();
+
+            if s.receiver_count >= N {
+                Err(Error::MaximumReceiversReached)
+            } else {
+                s.receiver_count += 1;
+                Ok(())
+            }
+        })
+    }
+
+    /// Set a new value, and wake all receivers waiting for a change.
+    pub fn send(&self, val: T) {
+        self.inner.lock(|s| {
+            let mut s = s.borrow_mut();
+            s.data = Some(val);
+            s.current_id += 1;
+            s.wakers.wake();
+        })
+    }
+
+    /// Remove the current value, if any.
+    ///
+    /// Receivers calling [`get`](Rcv::get) will wait for the next value to be sent.
+    pub fn clear(&self) {
+        self.inner.lock(|s| s.borrow_mut().data = None)
+    }
+
+    /// Get a clone of the current value, if any.
+    pub fn try_get(&self) -> Option<T> {
+        self.inner.lock(|s| s.borrow().data.clone())
+    }
+
+    /// Returns true if the watch contains a value.
+    pub fn contains_value(&self) -> bool {
+        self.inner.lock(|s| s.borrow().data.is_some())
+    }
+}
+
+impl<M: RawMutex, T: Clone, const N: usize> WatchBehavior<T> for Watch<M, T, N> {
+    fn poll_get(&self, id: &mut u64, cx: &mut Context<'_>) -> Poll<T> {
+        self.inner.lock(|s| {
+            let mut s = s.borrow_mut();
+            match &s.data {
+                Some(data) => {
+                    let data = data.clone();
+                    *id = s.current_id;
+                    Poll::Ready(data)
+                }
+                None => {
+                    s.register_waker(cx);
+                    Poll::Pending
+                }
+            }
+        })
+    }
+
+    fn try_get(&self, id: &mut u64) -> Option<T> {
+        self.inner.lock(|s| {
+            let s = s.borrow();
+            let data = s.data.clone();
+            if data.is_some() {
+                *id = s.current_id;
+            }
+            data
+        })
+    }
+
+    fn poll_changed(&self, id: &mut u64, cx: &mut Context<'_>) -> Poll<T> {
+        self.inner.lock(|s| {
+            let mut s = s.borrow_mut();
+            match &s.data {
+                Some(data) if s.current_id > *id => {
+                    let data = data.clone();
+                    *id = s.current_id;
+                    Poll::Ready(data)
+                }
+                _ => {
+                    s.register_waker(cx);
+                    Poll::Pending
+                }
+            }
+        })
+    }
+
+    fn try_changed(&self, id: &mut u64) -> Option<T> {
+        self.inner.lock(|s| {
+            let s = s.borrow();
+            match &s.data {
+                Some(data) if s.current_id > *id => {
+                    *id = s.current_id;
+                    Some(data.clone())
+                }
+                _ => None,
+            }
+        })
+    }
+
+    fn peek(&self) -> Option<T> {
+        self.try_get()
+    }
+
+    fn contains_value(&self) -> bool {
+        Watch::contains_value(self)
+    }
+
+    fn unregister_receiver(&self) {
+        self.inner.lock(|s| s.borrow_mut().receiver_count -= 1)
+    }
+}
+
+/// Internal state for the [Watch]
+struct WatchState<T: Clone, const N: usize> {
+    /// The current value, if any
+    data: Option<T>,
+    /// Incremented every time a value is sent. Receivers compare it with the id they last saw.
+    current_id: u64,
+    /// Collection of wakers for receivers that are waiting
+    wakers: MultiWakerRegistration<N>,
+    /// The amount of receivers that are active
+    receiver_count: usize,
+}
+
+impl<T: Clone, const N: usize> WatchState<T, N> {
+    const fn new() -> Self {
+        Self {
+            data: None,
+            current_id: 0,
+            wakers: MultiWakerRegistration::new(),
+            receiver_count: 0,
+        }
+    }
+
+    fn register_waker(&mut self, cx: &mut Context<'_>) {
+        if self.wakers.register(cx.waker()).is_err() {
+            // All waker slots were full: more tasks than receiver slots are waiting, or receivers
+            // that were waiting have dropped. Wake everything, any future that is still active
+            // will simply reregister.
+            self.wakers.wake();
+            self.wakers.register(cx.waker()).unwrap();
+        }
+    }
+}
+
+/// Error type for the [Watch]
+#[derive(Debug, PartialEq, Eq, Clone)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Error {
+    /// All receiver slots are used. To add another receiver, first another receiver must be dropped or
+    /// the capacity of the watch must be increased.
+    MaximumReceiversReached,
+}
+
+/// 'Middle level' behaviour of the watch.
+/// This trait is used so that receivers can be generic over the watch.
+pub trait WatchBehavior<T: Clone> {
+    /// Poll the watch for its current value. `id` is updated to the id of the returned value.
+    ///
+    /// If there is no value, the waker of the context is registered.
+    fn poll_get(&self, id: &mut u64, cx: &mut Context<'_>) -> Poll<T>;
+
+    /// Try to get the current value. If there is one, `id` is updated to its id.
+    fn try_get(&self, id: &mut u64) -> Option<T>;
+
+    /// Poll the watch for a value with a newer id than `id`. `id` is updated to the id of the returned value.
+    ///
+    /// If there is no such value, the waker of the context is registered.
+    fn poll_changed(&self, id: &mut u64, cx: &mut Context<'_>) -> Poll<T>;
+
+    /// Try to get a value with a newer id than `id`. If there is one, `id` is updated to its id.
+    fn try_changed(&self, id: &mut u64) -> Option<T>;
+
+    /// Get the current value, if any, without updating any id.
+    fn peek(&self) -> Option<T>;
+
+    /// Returns true if the watch contains a value.
+    fn contains_value(&self) -> bool;
+
+    /// Let the watch know that a receiver has dropped
+    fn unregister_receiver(&self);
+}
+
+/// A receiver of a watch
+pub struct Rcv<'a, W: WatchBehavior<T> + ?Sized, T: Clone> {
+    /// The id of the last value this receiver has seen
+    at_id: u64,
+    /// The watch we are a receiver of
+    watch: &'a W,
+    _phantom: PhantomData<T>,
+}
+
+impl<'a, W: WatchBehavior<T> + ?Sized, T: Clone> Rcv<'a, W, T> {
+    fn new(watch: &'a W) -> Self {
+        Self {
+            at_id: 0,
+            watch,
+            _phantom: PhantomData,
+        }
+    }
+
+    /// Wait for the watch to contain a value, and return a clone of it.
+    ///
+    /// The value is marked as seen by this receiver.
+    pub async fn get(&mut self) -> T {
+        poll_fn(|cx| self.watch.poll_get(&mut self.at_id, cx)).await
+    }
+
+    /// Return a clone of the current value, if any.
+    ///
+    /// The value is marked as seen by this receiver.
+    pub fn try_get(&mut self) -> Option<T> {
+        self.watch.try_get(&mut self.at_id)
+    }
+
+    /// Wait for a value this receiver hasn't seen yet, and return a clone of it.
+    ///
+    /// The value is marked as seen by this receiver.
+    pub async fn changed(&mut self) -> T {
+        poll_fn(|cx| self.watch.poll_changed(&mut self.at_id, cx)).await
+    }
+
+    /// Return a clone of the current value if this receiver hasn't seen it yet.
+    ///
+    /// The value is marked as seen by this receiver.
+    pub fn try_changed(&mut self) -> Option<T> {
+        self.watch.try_changed(&mut self.at_id)
+    }
+
+    /// Return a clone of the current value, if any, without marking it as seen.
+    pub fn peek(&self) -> Option<T> {
+        self.watch.peek()
+    }
+
+    /// Returns true if the watch contains a value.
+    pub fn contains_value(&self) -> bool {
+        self.watch.contains_value()
+    }
+}
+
+impl<'a, W: WatchBehavior<T> + ?Sized, T: Clone> Drop for Rcv<'a, W, T> {
+    fn drop(&mut self) {
+        self.watch.unregister_receiver();
+    }
+}
+
+/// A receiver that holds a generic reference to the watch
+pub struct Receiver<'a, M: RawMutex, T: Clone, const N: usize>(Rcv<'a, Watch<M, T, N>, T>);
+
+impl<'a, M: RawMutex, T: Clone, const N: usize> Deref for Receiver<'a, M, T, N> {
+    type Target = Rcv<'a, Watch<M, T, N>, T>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+impl<'a, M: RawMutex, T: Clone, const N: usize> DerefMut for Receiver<'a, M, T, N> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.0
+    }
+}
+
+/// A receiver that holds a dynamic reference to the watch
+pub struct DynReceiver<'a, T: Clone>(Rcv<'a, dyn WatchBehavior<T> + 'a, T>);
+
+impl<'a, T: Clone> Deref for DynReceiver<'a, T> {
+    type Target = Rcv<'a, dyn WatchBehavior<T> + 'a, T>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+impl<'a, T: Clone> DerefMut for DynReceiver<'a, T> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use core::future::Future;
+    use core::sync::atomic::{AtomicUsize, Ordering};
+    use core::task::{RawWaker, RawWakerVTable, Waker};
+
+    use futures_executor::block_on;
+    use futures_util::future::poll_fn;
+    use futures_util::pin_mut;
+    use futures_util::task::noop_waker_ref;
+
+    use super::*;
+    use crate::blocking_mutex::raw::NoopRawMutex;
+
+    #[futures_test::test]
+    async fn all_receivers_see_changes() {
+        let watch = Watch::<NoopRawMutex, u32, 2>::new();
+
+        let mut rcv0 = watch.receiver().unwrap();
+        let mut rcv1 = watch.dyn_receiver().unwrap();
+
+        assert_eq!(rcv0.try_changed(), None);
+        assert_eq!(rcv1.try_get(), None);
+
+        watch.send(42);
+
+        assert_eq!(rcv0.changed().await, 42);
+        assert_eq!(rcv1.changed().await, 42);
+
+        assert_eq!(rcv0.try_changed(), None);
+        assert_eq!(rcv1.try_changed(), None);
+
+        assert_eq!(rcv0.get().await, 42);
+        assert_eq!(rcv1.peek(), Some(42));
+    }
+
+    /// Waker counting how many times it's woken in `count`.
+    fn count_waker(count: &'static AtomicUsize) -> Waker {
+        const VTABLE: RawWakerVTable = RawWakerVTable::new(
+            |data| RawWaker::new(data, &VTABLE),
+            |data| unsafe {
+                (*(data as *const AtomicUsize)).fetch_add(1, Ordering::Relaxed);
+            },
+            |data| unsafe {
+                (*(data as *const AtomicUsize)).fetch_add(1, Ordering::Relaxed);
+            },
+            |_| {},
+        );
+        let raw = RawWaker::new(count as *const AtomicUsize as *const (), &VTABLE);
+        unsafe { Waker::from_raw(raw) }
+    }
+
+    #[test]
+    fn repeated_polls_take_one_waker_slot() {
+        let watch = Watch::<NoopRawMutex, u32, 2>::new();
+        let mut rcv0 = watch.receiver().unwrap();
+        let mut rcv1 = watch.receiver().unwrap();
+        static COUNT0: AtomicUsize = AtomicUsize::new(0);
+        static COUNT1: AtomicUsize = AtomicUsize::new(0);
+        let waker0 = count_waker(&COUNT0);
+        let waker1 = count_waker(&COUNT1);
+
+        for _ in 0..3 {
+            let fut = rcv0.changed();
+            pin_mut!(fut);
+            assert!(fut.poll(&mut Context::from_waker(&waker0)).is_pending());
+        }
+        let fut = rcv1.changed();
+        pin_mut!(fut);
+        assert!(fut.poll(&mut Context::from_waker(&waker1)).is_pending());
+
+        // There was a slot left for rcv1, so rcv0 wasn't woken to make room.
+        assert_eq!(COUNT0.load(Ordering::Relaxed), 0);
+
+        watch.send(1);
+        assert_eq!(COUNT0.load(Ordering::Relaxed), 1);
+        assert_eq!(COUNT1.load(Ordering::Relaxed), 1);
+    }
+
+    #[test]
+    fn only_latest_value_is_kept() {
+        let watch = Watch::<NoopRawMutex, u32, 1>::new();
+        let mut rcv = watch.receiver().unwrap();
+
+        watch.send(1);
+        watch.send(2);
+
+        assert_eq!(rcv.try_changed(), Some(2));
+        assert_eq!(rcv.try_changed(), None);
+    }
+
+    #[test]
+    fn peek_does_not_mark_seen() {
+        let watch = Watch::<NoopRawMutex, u32, 1>::new();
+        let mut rcv = watch.receiver().unwrap();
+
+        watch.send(1);
+
+        assert_eq!(rcv.peek(), Some(1));
+        assert_eq!(rcv.try_changed(), Some(1));
+    }
+
+    #[test]
+    fn new_receiver_sees_existing_value() {
+        let watch = Watch::<NoopRawMutex, u32, 1>::new();
+        watch.send(1);
+
+        let mut rcv = watch.receiver().unwrap();
+        assert_eq!(rcv.try_changed(), Some(1));
+    }
+
+    #[test]
+    fn clear_value() {
+        let watch = Watch::<NoopRawMutex, u32, 1>::new();
+        let mut rcv = watch.receiver().unwrap();
+
+        watch.send(1);
+        watch.clear();
+
+        assert!(!rcv.contains_value());
+        assert_eq!(rcv.try_changed(), None);
+        assert_eq!(rcv.try_get(), None);
+
+        watch.send(2);
+        assert_eq!(block_on(rcv.get()), 2);
+    }
+
+    #[test]
+    fn changed_waits_for_send() {
+        let watch = Watch::<NoopRawMutex, u32, 1>::new();
+        let mut rcv = watch.receiver().unwrap();
+        let mut cx = Context::from_waker(noop_waker_ref());
+
+        watch.send(1);
+        assert_eq!(rcv.try_changed(), Some(1));
+
+        let mut id = rcv.at_id;
+        assert_eq!(watch.poll_changed(&mut id, &mut cx), Poll::Pending);
+
+        watch.send(2);
+        assert_eq!(block_on(poll_fn(|cx| watch.poll_changed(&mut id, cx))), 2);
+    }
+
+    #[test]
+    fn limited_receivers() {
+        let watch = Watch::<NoopRawMutex, u32, 2>::new();
+
+        let rcv0 = watch.receiver();
+        let rcv1 = watch.dyn_receiver();
+        let rcv2 = watch.receiver();
+
+        assert!(rcv0.is_ok());
+        assert!(rcv1.is_ok());
+        assert_eq!(rcv2.err().unwrap(), Error::MaximumReceiversReached);
+
+        drop(rcv0);
+
+        assert!(watch.receiver().is_ok());
+    }
+}
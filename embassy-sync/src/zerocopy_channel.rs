@@ -0,0 +1,632 @@
+//! A zero-copy queue for sending values between asynchronous tasks.
+//!
+//! It can be used concurrently by multiple producers (senders) and a single
+//! consumer (receiver), i.e. it is an "MPSC channel".
+//!
+//! Unlike [`Channel`](crate::channel::Channel), values are never moved in or out of this
+//! channel. Instead, the channel lends out slots of a buffer provided by the user:
+//!
+//! - A sender gets a mutable reference to a free slot with [`Sender::send`], fills it in place,
+//!   and then makes it available to the receiver with [`Sender::send_done`].
+//! - The receiver gets a mutable reference to the oldest filled slot with [`Receiver::recv`],
+//!   processes it in place, and then gives it back to the senders with [`Receiver::recv_done`].
+//!
+//! This avoids copying large values, like network packets or sensor frames, which is especially
+//! expensive on small microcontrollers. Slots are reused as-is: a sender sees whatever the slot
+//! contained the last time it was used.
+//!
+//! Only one sender can fill a slot at a time. Other senders wait until that sender calls
+//! [`send_done`](Sender::send_done), or is dropped.
+//!
+//! ```
+//! use embassy_sync::blocking_mutex::raw::NoopRawMutex;
+//! use embassy_sync::zerocopy_channel::Channel;
+//! # use futures_executor::block_on;
+//! # let test = async {
+//!
+//! let mut buf = [[0u8; 4]; 2];
+//! let mut channel = Channel::<NoopRawMutex, [u8; 4]>::new(&mut buf);
+//! let (mut sender, mut receiver) = channel.split();
+//!
+//! let slot = sender.send().await;
+//! slot.copy_from_slice(b"ping");
+//! sender.send_done();
+//!
+//! let slot = receiver.recv().await;
+//! assert_eq!(slot, b"ping");
+//! receiver.recv_done();
+//! # };
+//! # block_on(test);
+//! ```
+
+use core::cell::RefCell;
+use core::future::poll_fn;
+use core::marker::PhantomData;
+use core::task::{Context, Poll};
+
+use crate::blocking_mutex::raw::RawMutex;
+use crate::blocking_mutex::Mutex;
+use crate::waitqueue::WakerRegistration;
+
+/// A bounded zero-copy channel for communicating between asynchronous tasks
+/// with backpressure.
+///
+/// The channel uses the slots of the buffer passed to [`Channel::new`]. Once all slots are
+/// filled, senders wait until the receiver is done with a slot.
+///
+/// All data sent will become available in the same order as it was sent.
+pub struct Channel<'a, M: RawMutex, T> {
+    buf: *mut T,
+    phantom: PhantomData<&'a mut T>,
+    state: Mutex<M, RefCell<State>>,
+}
+
+unsafe impl<'a, M: RawMutex + Send, T: Send> Send for Channel<'a, M, T> {}
+unsafe impl<'a, M: RawMutex + Sync, T: Send> Sync for Channel<'a, M, T> {}
+
+impl<'a, M: RawMutex, T> Channel<'a, M, T> {
+    /// Create a new channel, using the slots in `buf`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `buf` is empty.
+    pub fn new(buf: &'a mut [T]) -> Self {
+        let len = buf.len();
+        assert!(len != 0);
+
+        Self {
+            buf: buf.as_mut_ptr(),
+            phantom: PhantomData,
+            state: Mutex::new(RefCell::new(State {
+                len,
+                front: 0,
+                back: 0,
+                full: false,
+                reserved_by: None,
+                next_sender_id: 1,
+                send_waker: WakerRegistration::new(),
+                recv_waker: WakerRegistration::new(),
+            })),
+        }
+    }
+
+    /// Create a sender and the receiver for this channel.
+    ///
+    /// More senders can be created by cloning the returned one.
+    pub fn split(&mut self) -> (Sender<'_, M, T>, Receiver<'_, M, T>) {
+        (
+            Sender {
+                channel: self,
+                id: 0,
+                releases: true,
+            },
+            Receiver { channel: self },
+        )
+    }
+
+    fn lock<R>(&self, f: impl FnOnce(&mut State) -> R) -> R {
+        self.state.lock(|s| f(&mut s.borrow_mut()))
+    }
+
+    fn slot(&self, i: usize) -> *mut T {
+        // safety: indexes returned by `State` are always in bounds.
+        unsafe { self.buf.add(i) }
+    }
+}
+
+impl<'a, M: RawMutex, T> DynamicChannel<T> for Channel<'a, M, T> {
+    fn new_sender_id(&self) -> u32 {
+        self.lock(|s| {
+            let id = s.next_sender_id;
+            s.next_sender_id = s.next_sender_id.wrapping_add(1);
+            id
+        })
+    }
+
+    fn poll_send(&self, id: u32, cx: Option<&mut Context<'_>>) -> Poll<*mut T> {
+        self.lock(|s| match s.push_index(id) {
+            Some(i) => Poll::Ready(self.slot(i)),
+            None => {
+                if let Some(cx) = cx {
+                    s.send_waker.register(cx.waker());
+                }
+                Poll::Pending
+            }
+        })
+    }
+
+    fn send_done(&self, id: u32) {
+        self.lock(|s| s.push_done(id))
+    }
+
+    fn release(&self, id: u32) {
+        self.lock(|s| s.release(id))
+    }
+
+    fn poll_recv(&self, cx: Option<&mut Context<'_>>) -> Poll<*mut T> {
+        self.lock(|s| match s.pop_index() {
+            Some(i) => Poll::Ready(self.slot(i)),
+            None => {
+                if let Some(cx) = cx {
+                    s.recv_waker.register(cx.waker());
+                }
+                Poll::Pending
+            }
+        })
+    }
+
+    fn recv_done(&self) {
+        self.lock(|s| s.pop_done())
+    }
+}
+
+/// Send-only access to a [`Channel`].
+///
+/// The slot returned by [`send`](Sender::send) is held by this sender until [`send_done`](Sender::send_done)
+/// is called, or the sender is dropped. Calling `send` again before `send_done` returns the same slot.
+pub struct Sender<'a, M: RawMutex, T> {
+    channel: &'a Channel<'a, M, T>,
+    id: u32,
+    /// Whether dropping this sender releases its slot. Borrowed senders don't.
+    releases: bool,
+}
+
+impl<'a, M: RawMutex, T> Sender<'a, M, T> {
+    /// Creates one further [`Sender`] over the same channel, sharing the slot held by this one.
+    pub fn borrow(&mut self) -> Sender<'_, M, T> {
+        Sender {
+            channel: self.channel,
+            id: self.id,
+            releases: false,
+        }
+    }
+
+    /// Attempts to get a free slot, without waiting.
+    pub fn try_send(&mut self) -> Option<&mut T> {
+        match self.channel.poll_send(self.id, None) {
+            Poll::Ready(p) => Some(unsafe { &mut *p }),
+            Poll::Pending => None,
+        }
+    }
+
+    /// Attempts to get a free slot, registering the waker of `cx` if there is none.
+    pub fn poll_send(&mut self, cx: &mut Context) -> Poll<&mut T> {
+        match self.channel.poll_send(self.id, Some(cx)) {
+            Poll::Ready(p) => Poll::Ready(unsafe { &mut *p }),
+            Poll::Pending => Poll::Pending,
+        }
+    }
+
+    /// Waits for a free slot.
+    pub async fn send(&mut self) -> &mut T {
+        let p = poll_fn(|cx| self.channel.poll_send(self.id, Some(cx))).await;
+        unsafe { &mut *p }
+    }
+
+    /// Makes the slot obtained with [`send`](Sender::send) available to the receiver.
+    ///
+    /// # Panics
+    ///
+    /// Panics if this sender doesn't hold a slot.
+    pub fn send_done(&mut self) {
+        self.channel.send_done(self.id)
+    }
+}
+
+impl<'a, M: RawMutex, T> Clone for Sender<'a, M, T> {
+    fn clone(&self) -> Self {
+        Sender {
+            channel: self.channel,
+            id: self.channel.new_sender_id(),
+            releases: true,
+        }
+    }
+}
+
+impl<'a, M: RawMutex, T> Drop for Sender<'a, M, T> {
+    fn drop(&mut self) {
+        if self.releases {
+            self.channel.release(self.id)
+        }
+    }
+}
+
+/// Send-only access to a [`Channel`] without knowing the mutex type.
+pub struct DynamicSender<'a, T> {
+    channel: &'a dyn DynamicChannel<T>,
+    id: u32,
+    /// Whether dropping this sender releases its slot. Borrowed senders don't.
+    releases: bool,
+}
+
+impl<'a, M: RawMutex, T> From<Sender<'a, M, T>> for DynamicSender<'a, T> {
+    fn from(s: Sender<'a, M, T>) -> Self {
+        let s = core::mem::ManuallyDrop::new(s);
+        Self {
+            channel: s.channel,
+            id: s.id,
+            releases: s.releases,
+        }
+    }
+}
+
+impl<'a, T> DynamicSender<'a, T> {
+    /// Creates one further [`DynamicSender`] over the same channel, sharing the slot held by this one.
+    pub fn borrow(&mut self) -> DynamicSender<'_, T> {
+        DynamicSender {
+            channel: self.channel,
+            id: self.id,
+            releases: false,
+        }
+    }
+
+    /// Attempts to get a free slot, without waiting.
+    ///
+    /// See [`Sender::try_send()`]
+    pub fn try_send(&mut self) -> Option<&mut T> {
+        match self.channel.poll_send(self.id, None) {
+            Poll::Ready(p) => Some(unsafe { &mut *p }),
+            Poll::Pending => None,
+        }
+    }
+
+    /// Attempts to get a free slot, registering the waker of `cx` if there is none.
+    ///
+    /// See [`Sender::poll_send()`]
+    pub fn poll_send(&mut self, cx: &mut Context) -> Poll<&mut T> {
+        match self.channel.poll_send(self.id, Some(cx)) {
+            Poll::Ready(p) => Poll::Ready(unsafe { &mut *p }),
+            Poll::Pending => Poll::Pending,
+        }
+    }
+
+    /// Waits for a free slot.
+    ///
+    /// See [`Sender::send()`]
+    pub async fn send(&mut self) -> &mut T {
+        let p = poll_fn(|cx| self.channel.poll_send(self.id, Some(cx))).await;
+        unsafe { &mut *p }
+    }
+
+    /// Makes the slot obtained with [`send`](DynamicSender::send) available to the receiver.
+    ///
+    /// See [`Sender::send_done()`]
+    pub fn send_done(&mut self) {
+        self.channel.send_done(self.id)
+    }
+}
+
+impl<'a, T> Clone for DynamicSender<'a, T> {
+    fn clone(&self) -> Self {
+        DynamicSender {
+            channel: self.channel,
+            id: self.channel.new_sender_id(),
+            releases: true,
+        }
+    }
+}
+
+impl<'a, T> Drop for DynamicSender<'a, T> {
+    fn drop(&mut self) {
+        if self.releases {
+            self.channel.release(self.id)
+        }
+    }
+}
+
+/// Receive-only access to a [`Channel`].
+pub struct Receiver<'a, M: RawMutex, T> {
+    channel: &'a Channel<'a, M, T>,
+}
+
+impl<'a, M: RawMutex, T> Receiver<'a, M, T> {
+    /// Creates one further [`Receiver`] over the same channel.
+    pub fn borrow(&mut self) -> Receiver<'_, M, T> {
+        Receiver { channel: self.channel }
+    }
+
+    /// Attempts to get the oldest filled slot, without waiting.
+    pub fn try_recv(&mut self) -> Option<&mut T> {
+        match self.channel.poll_recv(None) {
+            Poll::Ready(p) => Some(unsafe { &mut *p }),
+            Poll::Pending => None,
+        }
+    }
+
+    /// Attempts to get the oldest filled slot, registering the waker of `cx` if there is none.
+    pub fn poll_recv(&mut self, cx: &mut Context) -> Poll<&mut T> {
+        match self.channel.poll_recv(Some(cx)) {
+            Poll::Ready(p) => Poll::Ready(unsafe { &mut *p }),
+            Poll::Pending => Poll::Pending,
+        }
+    }
+
+    /// Waits for a filled slot.
+    pub async fn recv(&mut self) -> &mut T {
+        let p = poll_fn(|cx| self.channel.poll_recv(Some(cx))).await;
+        unsafe { &mut *p }
+    }
+
+    /// Gives the slot obtained with [`recv`](Receiver::recv) back to the senders.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the channel is empty.
+    pub fn recv_done(&mut self) {
+        self.channel.recv_done()
+    }
+}
+
+/// Receive-only access to a [`Channel`] without knowing the mutex type.
+pub struct DynamicReceiver<'a, T> {
+    channel: &'a dyn DynamicChannel<T>,
+}
+
+impl<'a, M: RawMutex, T> From<Receiver<'a, M, T>> for DynamicReceiver<'a, T> {
+    fn from(r: Receiver<'a, M, T>) -> Self {
+        Self { channel: r.channel }
+    }
+}
+
+impl<'a, T> DynamicReceiver<'a, T> {
+    /// Creates one further [`DynamicReceiver`] over the same channel.
+    pub fn borrow(&mut self) -> DynamicReceiver<'_, T> {
+        DynamicReceiver { channel: self.channel }
+    }
+
+    /// Attempts to get the oldest filled slot, without waiting.
+    ///
+    /// See [`Receiver::try_recv()`]
+    pub fn try_recv(&mut self) -> Option<&mut T> {
+        match self.channel.poll_recv(None) {
+            Poll::Ready(p) => Some(unsafe { &mut *p }),
+            Poll::Pending => None,
+        }
+    }
+
+    /// Attempts to get the oldest filled slot, registering the waker of `cx` if there is none.
+    ///
+    /// See [`Receiver::poll_recv()`]
+    pub fn poll_recv(&mut self, cx: &mut Context) -> Poll<&mut T> {
+        match self.channel.poll_recv(Some(cx)) {
+            Poll::Ready(p) => Poll::Ready(unsafe { &mut *p }),
+            Poll::Pending => Poll::Pending,
+        }
+    }
+
+    /// Waits for a filled slot.
+    ///
+    /// See [`Receiver::recv()`]
+    pub async fn recv(&mut self) -> &mut T {
+        let p = poll_fn(|cx| self.channel.poll_recv(Some(cx))).await;
+        unsafe { &mut *p }
+    }
+
+    /// Gives the slot obtained with [`recv`](DynamicReceiver::recv) back to the senders.
+    ///
+    /// See [`Receiver::recv_done()`]
+    pub fn recv_done(&mut self) {
+        self.channel.recv_done()
+    }
+}
+
+trait DynamicChannel<T> {
+    fn new_sender_id(&self) -> u32;
+
+    fn poll_send(&self, id: u32, cx: Option<&mut Context<'_>>) -> Poll<*mut T>;
+
+    fn send_done(&self, id: u32);
+
+    fn release(&self, id: u32);
+
+    fn poll_recv(&self, cx: Option<&mut Context<'_>>) -> Poll<*mut T>;
+
+    fn recv_done(&self);
+}
+
+struct State {
+    len: usize,
+
+    /// Front index. Always 0..=(N-1)
+    front: usize,
+    /// Back index. Always 0..=(N-1).
+    back: usize,
+
+    /// Used to distinguish "empty" and "full" cases when `front == back`.
+    /// May only be `true` if `front == back`, always `false` otherwise.
+    full: bool,
+
+    /// Id of the sender currently filling the slot at `back`, if any.
+    reserved_by: Option<u32>,
+    /// Id given to the next cloned sender. The sender returned by `split` has id 0.
+    next_sender_id: u32,
+
+    /// Senders waiting for a free slot.
+    send_waker: WakerRegistration,
+    /// Receiver waiting for a filled slot.
+    recv_waker: WakerRegistration,
+}
+
+impl State {
+    fn increment(&self, i: usize) -> usize {
+        if i + 1 == self.len {
+            0
+        } else {
+            i + 1
+        }
+    }
+
+    fn is_full(&self) -> bool {
+        self.full
+    }
+
+    fn is_empty(&self) -> bool {
+        self.front == self.back && !self.full
+    }
+
+    fn push_index(&mut self, id: u32) -> Option<usize> {
+        match self.reserved_by {
+            Some(holder) if holder == id => Some(self.back),
+            Some(_) => None,
+            None if self.is_full() => None,
+            None => {
+                self.reserved_by = Some(id);
+                Some(self.back)
+            }
+        }
+    }
+
+    fn push_done(&mut self, id: u32) {
+        assert!(self.reserved_by == Some(id));
+        self.reserved_by = None;
+        self.back = self.increment(self.back);
+        if self.back == self.front {
+            self.full = true;
+        }
+        self.recv_waker.wake();
+        // Another sender may be waiting for the slot to be released.
+        self.send_waker.wake();
+    }
+
+    fn release(&mut self, id: u32) {
+        if self.reserved_by == Some(id) {
+            self.reserved_by = None;
+            self.send_waker.wake();
+        }
+    }
+
+    fn pop_index(&mut self) -> Option<usize> {
+        match self.is_empty() {
+            true => None,
+            false => Some(self.front),
+        }
+    }
+
+    fn pop_done(&mut self) {
+        assert!(!self.is_empty());
+        self.front = self.increment(self.front);
+        self.full = false;
+        self.send_waker.wake();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use futures_executor::block_on;
+
+    use super::*;
+    use crate::blocking_mutex::raw::NoopRawMutex;
+
+    #[test]
+    fn send_and_recv_in_order() {
+        let mut buf = [0u32; 3];
+        let mut channel = Channel::<NoopRawMutex, u32>::new(&mut buf);
+        let (mut tx, mut rx) = channel.split();
+
+        assert!(rx.try_recv().is_none());
+
+        for i in 0..3 {
+            *tx.try_send().unwrap() = i;
+            tx.send_done();
+        }
+        assert!(tx.try_send().is_none());
+
+        for i in 0..3 {
+            assert_eq!(*rx.try_recv().unwrap(), i);
+            rx.recv_done();
+        }
+        assert!(rx.try_recv().is_none());
+    }
+
+    #[test]
+    fn slots_are_reused_in_place() {
+        let mut buf = [0u32; 1];
+        let mut channel = Channel::<NoopRawMutex, u32>::new(&mut buf);
+        let (mut tx, mut rx) = channel.split();
+
+        *block_on(tx.send()) = 42;
+        tx.send_done();
+        assert_eq!(*block_on(rx.recv()), 42);
+        rx.recv_done();
+
+        // The slot still contains the previous value.
+        assert_eq!(*tx.try_send().unwrap(), 42);
+    }
+
+    #[test]
+    fn one_sender_fills_a_slot_at_a_time() {
+        let mut buf = [0u32; 2];
+        let mut channel = Channel::<NoopRawMutex, u32>::new(&mut buf);
+        let (mut tx0, mut rx) = channel.split();
+        let mut tx1 = tx0.clone();
+
+        *tx0.try_send().unwrap() = 1;
+        // tx0 holds the slot until it's done.
+        assert!(tx1.try_send().is_none());
+        // Getting a slot again returns the same one.
+        assert_eq!(*tx0.try_send().unwrap(), 1);
+        tx0.send_done();
+
+        *tx1.try_send().unwrap() = 2;
+        tx1.send_done();
+
+        assert_eq!(*rx.try_recv().unwrap(), 1);
+        rx.recv_done();
+        assert_eq!(*rx.try_recv().unwrap(), 2);
+        rx.recv_done();
+    }
+
+    #[test]
+    fn dropped_sender_releases_slot() {
+        let mut buf = [0u32; 2];
+        let mut channel = Channel::<NoopRawMutex, u32>::new(&mut buf);
+        let (mut tx0, mut rx) = channel.split();
+        let mut tx1 = tx0.clone();
+
+        assert!(tx1.try_send().is_some());
+        drop(tx1);
+
+        assert!(tx0.try_send().is_some());
+        tx0.send_done();
+        assert!(rx.try_recv().is_some());
+    }
+
+    #[test]
+    fn dropped_borrow_keeps_slot() {
+        let mut buf = [0u32; 2];
+        let mut channel = Channel::<NoopRawMutex, u32>::new(&mut buf);
+        let (mut tx0, mut rx) = channel.split();
+        let mut tx1 = tx0.clone();
+
+        *tx0.try_send().unwrap() = 1;
+        {
+            let mut borrowed = tx0.borrow();
+            // The borrow shares the slot held by tx0.
+            assert_eq!(*borrowed.try_send().unwrap(), 1);
+        }
+        // The slot is still held by tx0.
+        assert!(tx1.try_send().is_none());
+        tx0.send_done();
+        assert_eq!(*rx.try_recv().unwrap(), 1);
+
+        let mut tx0: DynamicSender<'_, u32> = tx0.into();
+        *tx0.try_send().unwrap() = 2;
+        drop(tx0.borrow());
+        assert!(tx1.try_send().is_none());
+        tx0.send_done();
+    }
+
+    #[test]
+    fn dynamic_dispatch() {
+        let mut buf = [0u32; 2];
+        let mut channel = Channel::<NoopRawMutex, u32>::new(&mut buf);
+        let (tx, rx) = channel.split();
+        let mut tx: DynamicSender<'_, u32> = tx.into();
+        let mut rx: DynamicReceiver<'_, u32> = rx.into();
+
+        *tx.try_send().unwrap() = 7;
+        tx.send_done();
+        assert_eq!(*rx.try_recv().unwrap(), 7);
+        rx.recv_done();
+    }
+}
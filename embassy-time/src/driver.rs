@@ -23,6 +23,11 @@
 //!
 //! If there is none or multiple drivers in the crate tree, linking will fail.
 //!
+//! The symbols are `_embassy_time_now`, `_embassy_time_allocate_alarm`, `_embassy_time_set_alarm_callback`,
+//! `_embassy_time_set_alarm` and `_embassy_time_compensate_sleep`. [`time_driver_impl`](crate::time_driver_impl)
+//! defines all of them. Drivers defining them by hand must define `_embassy_time_compensate_sleep` too, calling
+//! [`Driver::compensate_sleep`] or doing nothing if their timer never stops.
+//!
 //! This method has a few key advantages for something as foundational as timekeeping:
 //!
 //! - The time driver is available everywhere easily, without having to thread the implementation
@@ -113,6 +118,18 @@ pub trait Driver: Send + Sync + 'static {
     ///
     /// Only one alarm can be active at a time for each AlarmHandle. This overwrites any previously-set alarm if any.
     fn set_alarm(&self, alarm: AlarmHandle, timestamp: u64) -> bool;
+
+    /// Advance the current timestamp by `ticks`, to account for time during which the
+    /// hardware timer was stopped, such as in a deep sleep mode.
+    ///
+    /// This is called after waking up from such a sleep mode, with the time slept as measured
+    /// by other means. Alarms that expired during that time must be handled as soon as possible.
+    ///
+    /// The default implementation does nothing, which is correct for drivers whose timer keeps
+    /// running in all the sleep modes the program uses.
+    fn compensate_sleep(&self, ticks: u64) {
+        let _ = ticks;
+    }
 }
 
 extern "Rust" {
@@ -120,6 +137,7 @@ extern "Rust" {
     fn _embassy_time_allocate_alarm() -> Option<AlarmHandle>;
     fn _embassy_time_set_alarm_callback(alarm: AlarmHandle, callback: fn(*mut ()), ctx: *mut ());
     fn _embassy_time_set_alarm(alarm: AlarmHandle, timestamp: u64) -> bool;
+    fn _embassy_time_compensate_sleep(ticks: u64);
 }
 
 /// See [`Driver::now`]
@@ -144,6 +162,11 @@ pub fn set_alarm(alarm: AlarmHandle, timestamp: u64) -> bool {
     unsafe { _embassy_time_set_alarm(alarm, timestamp) }
 }
 
+/// See [`Driver::compensate_sleep`]
+pub fn compensate_sleep(ticks: u64) {
+    unsafe { _embassy_time_compensate_sleep(ticks) }
+}
+
 /// Set the time Driver implementation.
 ///
 /// See the module documentation for an example.
@@ -171,5 +194,10 @@ macro_rules! time_driver_impl {
         fn _embassy_time_set_alarm(alarm: $crate::driver::AlarmHandle, timestamp: u64) -> bool {
             <$t as $crate::driver::Driver>::set_alarm(&$name, alarm, timestamp)
         }
+
+        #[no_mangle]
+        fn _embassy_time_compensate_sleep(ticks: u64) {
+            <$t as $crate::driver::Driver>::compensate_sleep(&$name, ticks)
+        }
     };
 }
@@ -0,0 +1,271 @@
+use core::cell::RefCell;
+
+use critical_section::Mutex as CsMutex;
+
+use crate::driver::{AlarmHandle, Driver};
+use crate::{Duration, Instant};
+
+const ALARM_COUNT: usize = 4;
+
+/// A mock driver that can be manually advanced.
+/// This is useful for testing code that works with [`Instant`] and [`Duration`].
+///
+/// This driver can also be used to test runtime functionality, such as
+/// timers, delays, etc.
+///
+/// Time only moves when [`advance`](MockDriver::advance) or [`set_now`](MockDriver::set_now)
+/// is called. Alarm callbacks whose timestamp has been reached are called synchronously
+/// from these methods, so all timers that expired are already woken when they return.
+///
+/// # Example
+///
+/// ```ignore
+/// fn has_a_second_passed(reference: Instant) -> bool {
+///     Instant::now().duration_since(reference) >= Duration::from_secs(1)
+/// }
+///
+/// fn test_second_passed() {
+///     let driver = embassy_time::MockDriver::get();
+///     let reference = Instant::now();
+///     assert_eq!(false, has_a_second_passed(reference));
+///     driver.advance(Duration::from_secs(1));
+///     assert_eq!(true, has_a_second_passed(reference));
+/// }
+/// ```
+pub struct MockDriver(CsMutex<RefCell<InnerMockDriver>>);
+
+crate::time_driver_impl!(static DRIVER: MockDriver = MockDriver::new());
+
+impl MockDriver {
+    /// Creates a new mock driver.
+    pub const fn new() -> Self {
+        Self(CsMutex::new(RefCell::new(InnerMockDriver::new())))
+    }
+
+    /// Gets a reference to the global mock driver.
+    pub fn get() -> &'static MockDriver {
+        &DRIVER
+    }
+
+    /// Resets the internal state of the mock driver.
+    ///
+    /// This rewinds time to zero, and forgets all allocated alarms.
+    /// Wakers scheduled in a timer queue are not woken.
+    pub fn reset(&self) {
+        critical_section::with(|cs| self.0.borrow(cs).replace(InnerMockDriver::new()));
+    }
+
+    /// Sets the current time of the mock driver.
+    ///
+    /// All alarms whose timestamp is reached are fired before this returns.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `now` is earlier than the current time.
+    pub fn set_now(&self, now: Instant) {
+        critical_section::with(|cs| {
+            let mut inner = self.0.borrow_ref_mut(cs);
+            assert!(now.as_ticks() >= inner.now, "Going back in time?");
+            inner.now = now.as_ticks();
+        });
+
+        self.fire_alarms();
+    }
+
+    /// Advances the time of the mock driver by the specified duration.
+    ///
+    /// All alarms whose timestamp is reached are fired before this returns.
+    pub fn advance(&self, duration: Duration) {
+        critical_section::with(|cs| {
+            let mut inner = self.0.borrow_ref_mut(cs);
+            inner.now += duration.as_ticks();
+        });
+
+        self.fire_alarms();
+    }
+
+    fn fire_alarms(&self) {
+        // Callbacks are called outside the critical section, so they can set another alarm.
+        // Loop until no expired alarm is left, in case a callback sets an alarm in the past.
+        while let Some((callback, ctx)) = critical_section::with(|cs| {
+            let mut inner = self.0.borrow_ref_mut(cs);
+            let now = inner.now;
+            inner
+                .alarms
+                .iter_mut()
+                .find(|alarm| alarm.timestamp <= now)
+                .map(|alarm| {
+                    alarm.timestamp = u64::MAX;
+                    (alarm.callback, alarm.ctx)
+                })
+        }) {
+            callback(ctx);
+        }
+    }
+}
+
+impl Driver for MockDriver {
+    fn now(&self) -> u64 {
+        critical_section::with(|cs| self.0.borrow_ref(cs).now)
+    }
+
+    unsafe fn allocate_alarm(&self) -> Option<AlarmHandle> {
+        critical_section::with(|cs| {
+            let mut inner = self.0.borrow_ref_mut(cs);
+            if inner.alarm_count < ALARM_COUNT as u8 {
+                let id = inner.alarm_count;
+                inner.alarm_count += 1;
+                Some(AlarmHandle::new(id))
+            } else {
+                None
+            }
+        })
+    }
+
+    fn set_alarm_callback(&self, alarm: AlarmHandle, callback: fn(*mut ()), ctx: *mut ()) {
+        critical_section::with(|cs| {
+            let mut inner = self.0.borrow_ref_mut(cs);
+            let alarm = &mut inner.alarms[alarm.id() as usize];
+            alarm.callback = callback;
+            alarm.ctx = ctx;
+        });
+    }
+
+    fn set_alarm(&self, alarm: AlarmHandle, timestamp: u64) -> bool {
+        critical_section::with(|cs| {
+            let mut inner = self.0.borrow_ref_mut(cs);
+            if timestamp <= inner.now {
+                false
+            } else {
+                inner.alarms[alarm.id() as usize].timestamp = timestamp;
+                true
+            }
+        })
+    }
+}
+
+struct InnerMockDriver {
+    now: u64,
+    alarm_count: u8,
+    alarms: [AlarmState; ALARM_COUNT],
+}
+
+impl InnerMockDriver {
+    const fn new() -> Self {
+        Self {
+            now: 0,
+            alarm_count: 0,
+            alarms: [AlarmState::new(); ALARM_COUNT],
+        }
+    }
+}
+
+#[derive(Clone, Copy)]
+struct AlarmState {
+    timestamp: u64,
+    callback: fn(*mut ()),
+    ctx: *mut (),
+}
+
+unsafe impl Send for AlarmState {}
+
+impl AlarmState {
+    const fn new() -> Self {
+        Self {
+            timestamp: u64::MAX,
+            callback: Self::noop,
+            ctx: core::ptr::null_mut(),
+        }
+    }
+
+    fn noop(_ctx: *mut ()) {}
+}
+
+#[cfg(test)]
+mod tests {
+    use core::sync::atomic::{AtomicUsize, Ordering};
+
+    use serial_test::serial;
+
+    use super::*;
+
+    fn setup() {
+        DRIVER.reset();
+    }
+
+    #[test]
+    #[serial]
+    fn test_advance() {
+        setup();
+
+        let driver = MockDriver::get();
+        let reference = driver.now();
+        driver.advance(Duration::from_secs(1));
+        assert_eq!(Duration::from_secs(1).as_ticks(), driver.now() - reference);
+    }
+
+    #[test]
+    #[serial]
+    fn test_set_now() {
+        setup();
+
+        let driver = MockDriver::get();
+        driver.set_now(Instant::from_secs(1));
+        assert_eq!(Instant::from_secs(1), Instant::now());
+    }
+
+    #[test]
+    #[serial]
+    #[should_panic]
+    fn test_set_now_backwards() {
+        setup();
+
+        let driver = MockDriver::get();
+        driver.set_now(Instant::from_secs(2));
+        driver.set_now(Instant::from_secs(1));
+    }
+
+    #[test]
+    #[serial]
+    fn test_alarm() {
+        setup();
+
+        static CALLS: AtomicUsize = AtomicUsize::new(0);
+
+        fn callback(ctx: *mut ()) {
+            assert_eq!(ctx as usize, 42);
+            CALLS.fetch_add(1, Ordering::Relaxed);
+        }
+
+        CALLS.store(0, Ordering::Relaxed);
+
+        let driver = MockDriver::get();
+        let alarm = unsafe { driver.allocate_alarm() }.unwrap();
+        driver.set_alarm_callback(alarm, callback, 42 as *mut ());
+
+        assert!(driver.set_alarm(alarm, Instant::from_secs(1).as_ticks()));
+        assert!(!driver.set_alarm(alarm, driver.now()));
+
+        driver.advance(Duration::from_millis(999));
+        assert_eq!(CALLS.load(Ordering::Relaxed), 0);
+
+        driver.advance(Duration::from_millis(1));
+        assert_eq!(CALLS.load(Ordering::Relaxed), 1);
+
+        // The alarm is cleared once fired.
+        driver.advance(Duration::from_secs(1));
+        assert_eq!(CALLS.load(Ordering::Relaxed), 1);
+    }
+
+    #[test]
+    #[serial]
+    fn test_allocate_alarm() {
+        setup();
+
+        let driver = MockDriver::get();
+        for _ in 0..ALARM_COUNT {
+            assert!(unsafe { driver.allocate_alarm() }.is_some());
+        }
+        assert!(unsafe { driver.allocate_alarm() }.is_none());
+    }
+}
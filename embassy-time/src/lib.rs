@@ -16,6 +16,8 @@ pub mod queue;
 mod tick;
 mod timer;
 
+#[cfg(feature = "mock-driver")]
+mod driver_mock;
 #[cfg(feature = "std")]
 mod driver_std;
 #[cfg(feature = "wasm")]
@@ -24,9 +26,11 @@ mod driver_wasm;
 mod queue_generic;
 
 pub use delay::{block_for, Delay};
+#[cfg(feature = "mock-driver")]
+pub use driver_mock::MockDriver;
 pub use duration::Duration;
 pub use instant::Instant;
-pub use timer::{with_timeout, Ticker, TimeoutError, Timer};
+pub use timer::{with_timeout, MissedTickBehavior, Ticker, TimeoutError, Timer};
 
 /// Ticks per second of the global timebase.
 ///
@@ -2,7 +2,7 @@ use core::future::Future;
 use core::pin::Pin;
 use core::task::{Context, Poll, Waker};
 
-use futures_util::future::{select, Either};
+use futures_util::future::{poll_fn, select, Either};
 use futures_util::{pin_mut, Stream};
 
 use crate::{Duration, Instant};
@@ -79,9 +79,38 @@ impl Future for Timer {
     }
 }
 
+/// Behavior of a [`Ticker`] when one or more ticks were missed.
+///
+/// Ticks are missed when the task using the ticker doesn't wait for the next tick before
+/// the following one is already due, for example because it performed blocking work
+/// or because other tasks kept the executor busy.
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum MissedTickBehavior {
+    /// Yield all missed ticks immediately, one after another, until the ticker has caught up.
+    ///
+    /// Ticks stay aligned to the start instant of the ticker, and the total amount of ticks
+    /// yielded over a long period of time is exact.
+    #[default]
+    Burst,
+    /// Yield a single tick immediately, then schedule the following ticks relative to
+    /// the instant the missed tick was yielded.
+    ///
+    /// This shifts the tick schedule by the time the ticker was late. Ticks that are late by
+    /// less than a whole interval are not missed: they don't shift the schedule.
+    Delay,
+    /// Yield a single tick immediately and drop the other missed ticks.
+    ///
+    /// The following ticks stay aligned to the start instant of the ticker.
+    Skip,
+}
+
 /// Asynchronous stream that yields every Duration, indefinitely.
 ///
 /// This stream will tick at uniform intervals, even if blocking work is performed between ticks.
+/// Ticks are computed relative to the instant the ticker was created (or last [reset](Ticker::reset)),
+/// so no drift accumulates over time. Only [`MissedTickBehavior::Delay`] shifts the ticks, when
+/// a whole interval is missed.
 ///
 /// For instance, consider the following code fragment.
 /// ``` no_run
@@ -108,7 +137,6 @@ impl Future for Timer {
 /// # #![feature(type_alias_impl_trait)]
 /// #
 /// use embassy_time::{Duration, Ticker};
-/// use futures::StreamExt;
 /// # fn foo(){}
 ///
 /// #[embassy_executor::task]
@@ -120,16 +148,90 @@ impl Future for Timer {
 ///     }
 /// }
 /// ```
+///
+/// If `foo` sometimes takes longer than the tick interval, the ticks that were missed in the
+/// meantime are handled according to the ticker's [`MissedTickBehavior`].
 pub struct Ticker {
+    start: Instant,
     expires_at: Instant,
     duration: Duration,
+    missed_tick_behavior: MissedTickBehavior,
 }
 
 impl Ticker {
     /// Creates a new ticker that ticks at the specified duration interval.
+    ///
+    /// The first tick happens one `duration` from now.
     pub fn every(duration: Duration) -> Self {
-        let expires_at = Instant::now() + duration;
-        Self { expires_at, duration }
+        let start = Instant::now();
+        Self {
+            start,
+            expires_at: start + duration,
+            duration,
+            missed_tick_behavior: MissedTickBehavior::default(),
+        }
+    }
+
+    /// Sets the behavior of the ticker when ticks are missed.
+    ///
+    /// The default is [`MissedTickBehavior::Burst`].
+    pub fn with_missed_tick_behavior(mut self, behavior: MissedTickBehavior) -> Self {
+        self.missed_tick_behavior = behavior;
+        self
+    }
+
+    /// Sets the behavior of the ticker when ticks are missed.
+    pub fn set_missed_tick_behavior(&mut self, behavior: MissedTickBehavior) {
+        self.missed_tick_behavior = behavior;
+    }
+
+    /// Returns the behavior of the ticker when ticks are missed.
+    pub fn missed_tick_behavior(&self) -> MissedTickBehavior {
+        self.missed_tick_behavior
+    }
+
+    /// Returns the instant the next tick is due.
+    pub fn next_tick(&self) -> Instant {
+        self.expires_at
+    }
+
+    /// Resets the ticker to start now.
+    ///
+    /// The next tick happens one interval from now, and all following ticks are aligned to this instant.
+    pub fn reset(&mut self) {
+        self.start = Instant::now();
+        self.expires_at = self.start + self.duration;
+    }
+
+    /// Waits for the next tick.
+    #[allow(clippy::should_implement_trait)]
+    pub fn next(&mut self) -> impl Future<Output = ()> + '_ {
+        poll_fn(|cx| self.poll_tick(cx))
+    }
+
+    fn poll_tick(&mut self, cx: &mut Context<'_>) -> Poll<()> {
+        let now = Instant::now();
+        if self.expires_at <= now {
+            self.expires_at = match self.missed_tick_behavior {
+                MissedTickBehavior::Burst => self.expires_at + self.duration,
+                // Only reschedule from now when a whole interval was missed. Otherwise the
+                // latency of every tick would add up.
+                MissedTickBehavior::Delay if now >= self.expires_at + self.duration => {
+                    self.start = now;
+                    now + self.duration
+                }
+                MissedTickBehavior::Delay => self.expires_at + self.duration,
+                MissedTickBehavior::Skip => {
+                    let period = self.duration.as_ticks().max(1);
+                    let elapsed = now.duration_since(self.start).as_ticks();
+                    self.start + Duration::from_ticks((elapsed / period + 1) * period)
+                }
+            };
+            Poll::Ready(())
+        } else {
+            schedule_wake(self.expires_at, cx.waker());
+            Poll::Pending
+        }
     }
 }
 
@@ -138,14 +240,7 @@ impl Unpin for Ticker {}
 impl Stream for Ticker {
     type Item = ();
     fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
-        if self.expires_at <= Instant::now() {
-            let dur = self.duration;
-            self.expires_at += dur;
-            Poll::Ready(Some(()))
-        } else {
-            schedule_wake(self.expires_at, cx.waker());
-            Poll::Pending
-        }
+        self.poll_tick(cx).map(Some)
     }
 }
 
@@ -156,3 +251,107 @@ extern "Rust" {
 fn schedule_wake(at: Instant, waker: &Waker) {
     unsafe { _embassy_time_schedule_wake(at, waker) }
 }
+
+#[cfg(all(test, feature = "mock-driver", feature = "generic-queue"))]
+mod tests {
+    use futures_util::task::noop_waker_ref;
+    use serial_test::serial;
+
+    use super::*;
+    use crate::MockDriver;
+
+    fn setup() -> &'static MockDriver {
+        let driver = MockDriver::get();
+        driver.reset();
+        driver
+    }
+
+    fn poll(ticker: &mut Ticker) -> bool {
+        let mut cx = Context::from_waker(noop_waker_ref());
+        ticker.poll_tick(&mut cx).is_ready()
+    }
+
+    #[test]
+    #[serial]
+    fn test_ticks_on_time() {
+        let driver = setup();
+        let mut ticker = Ticker::every(Duration::from_secs(1));
+
+        assert!(!poll(&mut ticker));
+        driver.advance(Duration::from_millis(999));
+        assert!(!poll(&mut ticker));
+        driver.advance(Duration::from_millis(1));
+        assert!(poll(&mut ticker));
+        assert!(!poll(&mut ticker));
+        assert_eq!(ticker.next_tick(), Instant::from_secs(2));
+    }
+
+    #[test]
+    #[serial]
+    fn test_burst() {
+        let driver = setup();
+        let mut ticker = Ticker::every(Duration::from_secs(1));
+
+        driver.set_now(Instant::from_millis(3500));
+        for _ in 0..3 {
+            assert!(poll(&mut ticker));
+        }
+        assert!(!poll(&mut ticker));
+        assert_eq!(ticker.next_tick(), Instant::from_secs(4));
+    }
+
+    #[test]
+    #[serial]
+    fn test_skip() {
+        let driver = setup();
+        let mut ticker = Ticker::every(Duration::from_secs(1)).with_missed_tick_behavior(MissedTickBehavior::Skip);
+
+        driver.set_now(Instant::from_millis(3500));
+        assert!(poll(&mut ticker));
+        assert!(!poll(&mut ticker));
+        assert_eq!(ticker.next_tick(), Instant::from_secs(4));
+    }
+
+    #[test]
+    #[serial]
+    fn test_delay() {
+        let driver = setup();
+        let mut ticker = Ticker::every(Duration::from_secs(1)).with_missed_tick_behavior(MissedTickBehavior::Delay);
+
+        driver.set_now(Instant::from_millis(3500));
+        assert!(poll(&mut ticker));
+        assert!(!poll(&mut ticker));
+        assert_eq!(ticker.next_tick(), Instant::from_millis(4500));
+    }
+
+    #[test]
+    #[serial]
+    fn test_delay_doesnt_drift() {
+        let driver = setup();
+        let mut ticker = Ticker::every(Duration::from_secs(1)).with_missed_tick_behavior(MissedTickBehavior::Delay);
+
+        // Ticks handled late, but within the interval, keep the schedule.
+        for i in 1..=5 {
+            driver.set_now(Instant::from_secs(i) + Duration::from_millis(10));
+            assert!(poll(&mut ticker));
+            assert!(!poll(&mut ticker));
+            assert_eq!(ticker.next_tick(), Instant::from_secs(i + 1));
+        }
+    }
+
+    #[test]
+    #[serial]
+    fn test_reset() {
+        let driver = setup();
+        let mut ticker = Ticker::every(Duration::from_secs(1));
+
+        driver.set_now(Instant::from_millis(2500));
+        ticker.reset();
+        assert!(!poll(&mut ticker));
+        assert_eq!(ticker.next_tick(), Instant::from_millis(3500));
+
+        driver.set_now(Instant::from_millis(3500));
+        assert!(poll(&mut ticker));
+        assert_eq!(ticker.next_tick(), Instant::from_millis(4500));
+    }
+}
@@ -89,8 +89,10 @@ impl<const N: usize> UsbLogger<N> {
                 let mut rx: [u8; MAX_PACKET_SIZE as usize] = [0; MAX_PACKET_SIZE as usize];
                 class.wait_connection().await;
                 loop {
-                    let len = self.buffer.read(&mut rx[..]).await;
-                    let _ = class.write_packet(&rx[..len]).await;
+                    // The pipe is never closed, so reading can't fail.
+                    if let Ok(len) = self.buffer.read(&mut rx[..]).await {
+                        let _ = class.write_packet(&rx[..len]).await;
+                    }
                 }
             };
             join(run_fut, log_fut).await;
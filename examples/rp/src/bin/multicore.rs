@@ -18,6 +18,7 @@ static EXECUTOR0: StaticCell<Executor> = StaticCell::new();
 static EXECUTOR1: StaticCell<Executor> = StaticCell::new();
 static CHANNEL: Channel<CriticalSectionRawMutex, LedState, 1> = Channel::new();
 
+#[derive(Format)]
 enum LedState {
     On,
     Off,
@@ -41,9 +42,9 @@ fn main() -> ! {
 async fn core0_task() {
     info!("Hello from core 0");
     loop {
-        CHANNEL.send(LedState::On).await;
+        unwrap!(CHANNEL.send(LedState::On).await);
         Timer::after(Duration::from_millis(100)).await;
-        CHANNEL.send(LedState::Off).await;
+        unwrap!(CHANNEL.send(LedState::Off).await);
         Timer::after(Duration::from_millis(400)).await;
     }
 }
@@ -52,7 +53,7 @@ async fn core0_task() {
 async fn core1_task(mut led: Output<'static, PIN_25>) {
     info!("Hello from core 1");
     loop {
-        match CHANNEL.recv().await {
+        match unwrap!(CHANNEL.recv().await) {
             LedState::On => led.set_high(),
             LedState::Off => led.set_low(),
         }